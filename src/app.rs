@@ -1,17 +1,41 @@
+use crate::config_and_keymap::config::Config;
+use crate::config_and_keymap::keymap::{Action, KeyChord, KeyLookup, Keymap};
+use crate::feeds_and_entry::archive::{self, RetentionConfig};
+use crate::feeds_and_entry::downloads::{DownloadManager, DownloadStatus};
 use crate::feeds_and_entry::entry::Entry;
-use crate::session_and_user::{session::Session, user::User};
+use crate::feeds_and_entry::export;
+use crate::feeds_and_entry::feeds::BlogFeed;
+use crate::feeds_and_entry::health::HealthConfig;
+use crate::feeds_and_entry::hooks::Hooks;
+use crate::feeds_and_entry::http::{Auth, HttpSettings};
+use crate::feeds_and_entry::opml;
+use crate::feeds_and_entry::ordering::{GroupMode, Row};
+use crate::feeds_and_entry::read_later::ReadLater;
+use crate::feeds_and_entry::rules::{Condition, Rule};
+use crate::feeds_and_entry::scripts::Scripts;
+use crate::feeds_and_entry::source::expand_home;
+use crate::session_and_user::digest::DigestConfig;
+use crate::session_and_user::session::{Session, UnreadCounts};
+use crate::session_and_user::sync::SyncConfig;
+use crate::session_and_user::user::User;
+use crate::ui::command_line::{Command, CommandLine, CompletionSource, ExportScope};
+use crate::ui::history::{HistoryEntry, NavigationHistory};
+use crate::ui::layout::{resize_pane, split_panes, FeedPaneItem, LayoutConfig, Pane};
 use crate::ui::screens::{FeedsOptions, HomeScreenOptions, Options, PostsOptions, SelectedScreen};
+use crate::ui::theme::Theme;
 use crate::ui::{primitives::StatefulList, screens::ProceduresOptions};
 use anyhow::Result;
 use crossterm::event::{self, Event, KeyCode, KeyEvent};
 use futures::executor::block_on;
 use std::{
+    collections::BTreeSet,
     io,
     time::{Duration, Instant},
 };
 use tui::layout::{Alignment, Constraint, Direction};
-use tui::style::Color;
-use tui::widgets::{Paragraph, Wrap};
+use tui::symbols;
+use tui::text::Span;
+use tui::widgets::{LineGauge, Paragraph, Wrap};
 use tui::{
     backend::Backend,
     layout::{Layout, Rect},
@@ -25,6 +49,28 @@ use tui::{
 enum InputMode {
     Normal,
     Editing,
+    /// Typing a `:` command into the input buffer.
+    Command,
+    /// Answering the question at the front of `confirmations`.
+    Confirm,
+}
+
+/// Something that needs a yes or no before it happens.
+#[derive(Clone, Debug)]
+enum Confirmation {
+    MoveFeed { feed: String, url: String },
+    Unsubscribe { feed: String },
+}
+
+impl Confirmation {
+    fn question(&self) -> String {
+        match self {
+            Confirmation::MoveFeed { feed, url } => {
+                format!("{} has moved to {}. Update it?", feed, url)
+            }
+            Confirmation::Unsubscribe { feed } => format!("Unsubscribe from {}?", feed),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -47,7 +93,7 @@ pub struct App {
     scroll: u16,
     pub session: Option<Session>,
     pub selected_screen: SelectedScreen,
-    pub previous_screen: SelectedScreen,
+    history: NavigationHistory,
     pub items: StatefulList<String>,
     pub show_popup: bool,
     pub should_open_link: bool,
@@ -55,89 +101,217 @@ pub struct App {
     input_mode: InputMode,
     messages: Vec<String>,
     current_form_questions: Option<Vec<QuestionWithResponse>>,
+    keymap: Keymap,
+    pending_keys: Vec<KeyChord>,
+    show_help: bool,
+    page_size: usize,
+    theme: Theme,
+    last_error: Option<String>,
+    /// Questions waiting to be answered, asked one at a time.
+    confirmations: Vec<Confirmation>,
+    /// Apply feed moves without asking.
+    update_moved_feeds: bool,
+    command_line: CommandLine,
+    search_query: Option<String>,
+    /// A view from the scripts narrowing the BrowsePosts list, after any search.
+    script_view: Option<String>,
+    /// Urls of the entries marked for export.
+    marked: BTreeSet<String>,
+    /// The lines of the BrowsePosts list, rebuilt by `load_screen_items` rather
+    /// than on every frame.
+    rows: Vec<Row>,
+    layout: LayoutConfig,
+    focused_pane: Pane,
+    visible_panes: Vec<Pane>,
+    feed_pane: StatefulList<FeedPaneItem>,
+    /// The counts shown in the feeds pane, rebuilt with it by `load_feed_pane`.
+    unread: UnreadCounts,
+    feed_filter: FeedPaneItem,
+    preview_scroll: u16,
+    retention: RetentionConfig,
+    /// Default request settings for fetching feeds.
+    http: HttpSettings,
+    /// When failing feeds are flagged as possibly dead.
+    health: HealthConfig,
+    /// Set when the last fetch couldn't reach the network, so cached pages are used.
+    offline: bool,
+    downloads: DownloadManager,
+    /// Where "send to read-later" goes, if the config names a service.
+    read_later: Option<ReadLater>,
+    /// The server the session syncs with, if the config names one.
+    sync: Option<SyncConfig>,
+    /// Where `:digest` sends unread entries, if the config says.
+    digest: Option<DigestConfig>,
+    /// Commands run on feed events; their failures end up in the message log.
+    hooks: Hooks,
+    scripts: Scripts,
 }
 
 /// Pass the app no session to initiate at the create session screen.
 impl App {
-    pub fn new(session: Option<Session>) -> App {
+    pub fn new(session: Option<Session>, config: Config) -> Result<App> {
+        let keymap = config.build_keymap()?;
+        let theme = config.build_theme()?;
+        let hooks = Hooks::new(&config.hooks)?;
+        // A broken script shouldn't keep the app from starting; its error is
+        // shown in the message log instead.
+        let scripts = Scripts::load();
         match session {
-            Some(x) => {
-                App {
-                    scroll: 0,
-                    session: Some(x),
-                    selected_screen: SelectedScreen::Home,
-                    previous_screen: SelectedScreen::Home,
-                    items: SelectedScreen::Home.get_list_items(),
-                    show_popup: false,
-                    should_open_link: false,
-                    input: String::from(""),
-                    input_mode: InputMode::Normal,
-                    messages: vec![],
-                    current_form_questions: None,
-                }
-            }
-            None => {
-                return App {
-                    scroll: 0,
-                    session,
-                    selected_screen: SelectedScreen::CreateSession,
-                    previous_screen: SelectedScreen::CreateSession,
-                    items: SelectedScreen::CreateSession.get_list_items(),
-                    show_popup: false,
-                    should_open_link: false,
-                    input: String::from(""),
-                    input_mode: InputMode::Normal,
-                    messages: vec![],
-                    current_form_questions: Some(
-                        SelectedScreen::CreateSession
-                            .get_list_items()
-                            .items
-                            .iter()
-                            .map(|question| {
-                                QuestionWithResponse::new(question.to_owned())
-                            })
-                            .collect(),
-                    ),
-                };
-            }
+            Some(x) => Ok(App {
+                scroll: 0,
+                session: Some(x),
+                selected_screen: SelectedScreen::Home,
+                history: NavigationHistory::default(),
+                items: SelectedScreen::Home.get_list_items(),
+                show_popup: false,
+                should_open_link: false,
+                input: String::from(""),
+                input_mode: InputMode::Normal,
+                messages: vec![],
+                current_form_questions: None,
+                keymap,
+                pending_keys: vec![],
+                show_help: false,
+                page_size: 10,
+                theme,
+                last_error: None,
+                confirmations: vec![],
+                update_moved_feeds: config.update_moved_feeds,
+                command_line: CommandLine::default(),
+                search_query: None,
+                script_view: None,
+                marked: BTreeSet::new(),
+                rows: vec![],
+                layout: config.layout,
+                focused_pane: Pane::Entries,
+                visible_panes: vec![Pane::Entries],
+                feed_pane: StatefulList::with_items(vec![FeedPaneItem::AllFeeds]),
+                unread: UnreadCounts::default(),
+                feed_filter: FeedPaneItem::AllFeeds,
+                preview_scroll: 0,
+                retention: config.retention.clone(),
+                http: config.http.clone(),
+                health: config.health.clone(),
+                offline: false,
+                downloads: DownloadManager::new(&config.downloads),
+                read_later: config
+                    .read_later
+                    .as_ref()
+                    .map(|read_later| ReadLater::new(read_later, &config.http)),
+                sync: config.sync.clone(),
+                digest: config.digest.clone(),
+                hooks: hooks.clone(),
+                scripts: scripts.clone(),
+            }),
+            None => Ok(App {
+                scroll: 0,
+                session,
+                selected_screen: SelectedScreen::CreateSession,
+                history: NavigationHistory::default(),
+                items: SelectedScreen::CreateSession.get_list_items(),
+                show_popup: false,
+                should_open_link: false,
+                input: String::from(""),
+                input_mode: InputMode::Normal,
+                messages: vec![],
+                current_form_questions: Some(
+                    SelectedScreen::CreateSession
+                        .get_list_items()
+                        .items
+                        .iter()
+                        .map(|question| QuestionWithResponse::new(question.to_owned()))
+                        .collect(),
+                ),
+                keymap,
+                pending_keys: vec![],
+                show_help: false,
+                page_size: 10,
+                theme,
+                last_error: None,
+                confirmations: vec![],
+                update_moved_feeds: config.update_moved_feeds,
+                command_line: CommandLine::default(),
+                search_query: None,
+                script_view: None,
+                marked: BTreeSet::new(),
+                rows: vec![],
+                layout: config.layout,
+                focused_pane: Pane::Entries,
+                visible_panes: vec![Pane::Entries],
+                feed_pane: StatefulList::with_items(vec![FeedPaneItem::AllFeeds]),
+                unread: UnreadCounts::default(),
+                feed_filter: FeedPaneItem::AllFeeds,
+                preview_scroll: 0,
+                retention: config.retention.clone(),
+                http: config.http.clone(),
+                health: config.health.clone(),
+                offline: false,
+                downloads: DownloadManager::new(&config.downloads),
+                read_later: config
+                    .read_later
+                    .as_ref()
+                    .map(|read_later| ReadLater::new(read_later, &config.http)),
+                sync: config.sync.clone(),
+                digest: config.digest.clone(),
+                hooks: hooks.clone(),
+                scripts: scripts.clone(),
+            }),
         }
     }
 
     fn on_tick(&mut self) {
         self.scroll += 1;
         self.scroll %= 10;
+        for failure in self.hooks.take_failures() {
+            self.report_error(failure);
+        }
+        let (printed, errors) = self.scripts.take_messages();
+        for message in printed {
+            self.report(message);
+        }
+        for error in errors {
+            self.report_error(error);
+        }
+    }
+
+    /// Shows a message in the status bar and keeps it in the message log.
+    fn report(&mut self, message: String) {
+        self.last_error = None;
+        self.messages.push(message);
+    }
+
+    /// Like `report`, but drawn with the theme's error style.
+    fn report_error(&mut self, message: String) {
+        self.messages.push(message.clone());
+        self.last_error = Some(message);
     }
 
     fn save_current_buffer_to_selected_response(&mut self) {
-        match &mut self.current_form_questions {
-            None => {}
-            Some(questions) => {
-                questions[self.items.state.selected().unwrap()].response = Some(self.input.clone());
-                self.input = String::from("");
-            }
+        if let Some(questions) = &mut self.current_form_questions {
+            questions[self.items.state.selected().unwrap()].response = Some(self.input.clone());
+            self.input = String::from("");
         }
     }
 
     fn proceed_with_question_responses(&mut self) -> Result<()> {
-        match self.selected_screen {
-            SelectedScreen::CreateSession => {
-                self.session = Some(Session::new(
-                    User::new(
-                        self.current_form_questions.as_ref().unwrap()[0]
-                            .response
-                            .as_ref()
-                            .unwrap()
-                            .as_str(),
-                    ),
-                    vec![],
-                    self.current_form_questions.as_ref().unwrap()[1]
+        if let SelectedScreen::CreateSession = self.selected_screen {
+            let name = self.current_form_questions.as_ref().unwrap()[1]
+                .response
+                .as_ref()
+                .unwrap()
+                .clone();
+            Session::check_name(&name)?;
+            self.session = Some(Session::new(
+                User::new(
+                    self.current_form_questions.as_ref().unwrap()[0]
                         .response
                         .as_ref()
                         .unwrap()
                         .as_str(),
-                ));
-            }
-            _ => {}
+                ),
+                vec![],
+                &name,
+            ));
         }
         self.current_form_questions = None;
         Ok(())
@@ -145,33 +319,249 @@ impl App {
 
     /// Move the user to a different screen.
     fn select_screen(&mut self, screen: SelectedScreen) {
-        self.previous_screen = self.selected_screen.clone();
+        self.history.visit(self.history_entry());
+        self.show_screen(screen);
+    }
+
+    /// Switch screens without touching the history.
+    fn show_screen(&mut self, screen: SelectedScreen) {
         self.selected_screen = screen;
+        self.load_screen_items();
+        // Move the cursor to the first available position.
+        self.items.state.select(Some(0));
+    }
+
+    /// Fill the feeds pane as a tree: each folder followed by its feeds, then the
+    /// feeds that aren't in a folder. The cursor stays on whatever is being filtered by.
+    fn load_feed_pane(&mut self) {
+        let session = self.session.as_ref().unwrap();
+        let mut items = vec![FeedPaneItem::AllFeeds];
+        let mut folders = session.get_folder_paths();
+        folders.push(String::from(""));
+        for folder in folders {
+            if !folder.is_empty() {
+                items.push(FeedPaneItem::Folder(folder.clone()));
+            }
+            for feed in session.blog_feeds.iter() {
+                if feed.folder_path().join("/") == folder {
+                    items.push(FeedPaneItem::Feed(feed.name.clone()));
+                }
+            }
+        }
+        let selected = items.iter().position(|item| item == &self.feed_filter);
+        self.unread = session.unread_counts();
+        self.feed_pane.items = items;
+        if selected.is_none() {
+            self.feed_filter = FeedPaneItem::AllFeeds;
+        }
+        self.feed_pane.state.select(Some(selected.unwrap_or(0)));
+    }
+
+    /// Show the entries of whichever feed or folder is highlighted in the feeds pane.
+    fn apply_feed_filter(&mut self) {
+        let selected = self.feed_pane.state.selected().unwrap_or(0);
+        self.feed_filter = self
+            .feed_pane
+            .items
+            .get(selected)
+            .cloned()
+            .unwrap_or(FeedPaneItem::AllFeeds);
+        if let FeedPaneItem::Feed(name) = &self.feed_filter {
+            let session = self.session.as_ref().unwrap();
+            let error = session
+                .blog_feeds
+                .iter()
+                .find(|feed| &feed.name == name)
+                .and_then(|feed| feed.last_error.clone());
+            if let Some(error) = error {
+                self.report_error(format!("{} failed to update: {}", name, error));
+            }
+        }
+        self.load_screen_items();
+        self.items.state.select(Some(0));
+        self.preview_scroll = 0;
+    }
+
+    /// Go to the BrowsePosts screen for a feed or folder, showing only the entries
+    /// matching `query` if given.
+    fn browse_posts(&mut self, filter: FeedPaneItem, query: Option<String>) {
+        self.history.visit(self.history_entry());
+        self.feed_filter = filter;
+        self.search_query = query;
+        self.script_view = None;
+        self.show_screen(SelectedScreen::BrowsePosts);
+    }
+
+    /// A snapshot of the current screen for the navigation history.
+    fn history_entry(&self) -> HistoryEntry {
+        HistoryEntry {
+            screen: self.selected_screen.clone(),
+            list_state: self.items.state.clone(),
+            selected_label: self
+                .items
+                .state
+                .selected()
+                .and_then(|i| self.items.items.get(i))
+                .cloned(),
+            search_query: self.search_query.clone(),
+            feed_filter: self.feed_filter.clone(),
+            script_view: self.script_view.clone(),
+        }
+    }
+
+    /// Put the user back exactly where a history entry says they were. If the list
+    /// has changed since, the cursor follows the item it was on.
+    fn restore_history_entry(&mut self, entry: HistoryEntry) {
+        self.selected_screen = entry.screen;
+        self.search_query = entry.search_query;
+        self.feed_filter = entry.feed_filter;
+        self.script_view = entry.script_view;
+        self.load_screen_items();
+        self.items.state = entry.list_state;
+        let index = self.items.state.selected().unwrap_or(0);
+        if let Some(label) = entry.selected_label {
+            if self.items.items.get(index) != Some(&label) {
+                if let Some(found) = self.items.items.iter().position(|item| item == &label) {
+                    self.items.state.select(Some(found));
+                }
+            }
+        }
+        if self.items.state.selected().unwrap_or(0) >= self.items.items.len() {
+            self.items.last();
+        }
+    }
+
+    fn go_back(&mut self) {
+        if let Some(entry) = self.history.go_back(self.history_entry()) {
+            self.restore_history_entry(entry);
+        }
+    }
+
+    fn go_forward(&mut self) {
+        if let Some(entry) = self.history.go_forward(self.history_entry()) {
+            self.restore_history_entry(entry);
+        }
+    }
+
+    /// Populate the app's current items with the current screen's values.
+    fn load_screen_items(&mut self) {
         // Populate the app's current items with the corresponding screen's values.
         self.items.items = match self.selected_screen {
             SelectedScreen::Home => HomeScreenOptions::as_vec_of_strings(),
             SelectedScreen::Posts => PostsOptions::as_vec_of_strings(),
             SelectedScreen::Procedures => ProceduresOptions::as_vec_of_strings(),
             SelectedScreen::BrowsePosts => {
-                self.session.as_ref().unwrap().get_all_blog_entry_titles()
+                self.load_feed_pane();
+                self.rows = self.arrange_rows();
+                self.rows
+                    .iter()
+                    .map(|row| {
+                        let entry = match row {
+                            Row::Header(header) => return format!("── {} ──", header),
+                            Row::Entry(entry) => entry,
+                        };
+                        let mut label = entry.to_string();
+                        if self.marked.contains(&entry.url) {
+                            label = format!("+ {}", label);
+                        }
+                        if !entry.enclosures.is_empty() {
+                            label.push_str(" 📎");
+                        }
+                        if !entry.tags.is_empty() {
+                            label.push_str(&format!(" [{}]", entry.tags.join(", ")));
+                        }
+                        if !entry.also_in.is_empty() {
+                            label.push_str(&format!(" (also in: {})", entry.also_in.join(", ")));
+                        }
+                        label
+                    })
+                    .collect()
             }
             // SelectedScreen::CreateSession => SelectedScreen::CreateSession.get_list_items().items,
             SelectedScreen::CreateSession => SelectedScreen::CreateSession.get_list_items().items,
             SelectedScreen::Feeds => FeedsOptions::as_vec_of_strings(),
-            SelectedScreen::SelectSession => todo!(),
+            SelectedScreen::SelectSession => Session::saved_session_names(),
             SelectedScreen::Authors => self.session.as_ref().unwrap().get_unique_authors(),
+            SelectedScreen::Folders => {
+                let session = self.session.as_ref().unwrap();
+                let unread = session.unread_counts();
+                session
+                    .get_folder_paths()
+                    .iter()
+                    .map(|path| format!("{} ({})", path, unread.folder(path)))
+                    .collect()
+            }
+            SelectedScreen::FeedDetails => match &self.feed_filter {
+                FeedPaneItem::Feed(name) => self
+                    .session
+                    .as_ref()
+                    .unwrap()
+                    .blog_feeds
+                    .iter()
+                    .find(|feed| &feed.name == name)
+                    .map(|feed| self.feed_details(feed))
+                    .unwrap_or_default(),
+                _ => vec![],
+            },
+            SelectedScreen::ViewFeeds => self
+                .session
+                .as_ref()
+                .unwrap()
+                .blog_feeds
+                .iter()
+                .map(|feed| format!("{}: {}", feed.name, feed.health.summary(&self.health)))
+                .collect(),
+            SelectedScreen::Downloads => self
+                .downloads
+                .snapshot()
+                .iter()
+                .map(|download| download.title.clone())
+                .collect(),
+            SelectedScreen::Storage => {
+                let usage = archive::storage_usage(&self.session.as_ref().unwrap().blog_feeds);
+                let total: u64 = usage.iter().map(|feed| feed.bytes).sum();
+                let mut lines: Vec<String> = usage
+                    .iter()
+                    .map(|feed| {
+                        format!(
+                            "{}: {} in {} pages",
+                            feed.feed_name,
+                            archive::format_size(feed.bytes),
+                            feed.pages
+                        )
+                    })
+                    .collect();
+                lines.push(format!("Total: {}", archive::format_size(total)));
+                lines
+            }
+            SelectedScreen::Rules => {
+                let session = self.session.as_ref().unwrap();
+                session
+                    .rules
+                    .iter()
+                    .map(|rule| {
+                        let actions: Vec<String> = rule
+                            .actions
+                            .iter()
+                            .map(|action| action.get_name())
+                            .collect();
+                        let matches = match session.rule_match_count(rule) {
+                            Ok(count) => format!("{} matches", count),
+                            Err(e) => format!("error: {}", e),
+                        };
+                        format!(
+                            "[{}] {}: {} ({})",
+                            if rule.enabled { "x" } else { " " },
+                            rule.name,
+                            actions.join(", "),
+                            matches
+                        )
+                    })
+                    .collect()
+            }
         };
-        // Move the cursor to the first available position.
-        self.items.state.select(Some(0));
     }
 
-    /// Move the user to the previously visited screen.
-    fn go_to_previous_screen(&mut self) {
-        // Todo: Make an undo tree rather than just having one previous screen.
-        let target_screen = self.previous_screen.clone();
-        self.previous_screen = self.selected_screen.clone();
-        self.select_screen(target_screen);
-    }
     /// To be used in the main loop, allows user navigation.
     /// Returns true if the user hasn't pressed q.
     fn navigate<B: Backend>(
@@ -190,6 +580,11 @@ impl App {
         true
     }
 
+    /// Waits for the hooks still running, which exiting would kill.
+    pub async fn finished(&self) {
+        self.hooks.finished().await;
+    }
+
     /// The main loop
     pub fn run<B: Backend>(
         &mut self,
@@ -222,45 +617,50 @@ impl App {
 
     /// Utility function for calling the appropriate ui screen type
     fn handle_screen_selection<B: Backend>(&mut self, terminal: &mut Terminal<B>) {
-        match self.selected_screen {
-            SelectedScreen::CreateSession => {
-                terminal
-                    .draw(|f| {
-                        self.user_input_flow(f, self.selected_screen.get_screen_name().as_str())
-                    })
-                    .unwrap();
-            }
-            SelectedScreen::BrowsePosts => {
-                terminal
-                    .draw(|f| {
-                        self.nav_list_for_blog_entries(
-                            f,
-                            self.selected_screen.get_screen_name().as_str(),
-                            self.session.clone().unwrap().get_all_blog_entries(),
-                        )
-                    })
-                    .unwrap();
-            }
-            _ => {
-                terminal
-                    .draw(|f| {
-                        self.nav_list_generic(f, self.selected_screen.get_screen_name().as_str())
-                    })
-                    .unwrap();
-            }
-        };
+        terminal
+            .draw(|f| {
+                let title = self.selected_screen.get_screen_name();
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(1)])
+                    .split(f.size());
+                match self.selected_screen {
+                    SelectedScreen::CreateSession => {
+                        self.user_input_flow(f, title.as_str(), chunks[0])
+                    }
+                    SelectedScreen::BrowsePosts => {
+                        let mut title = match &self.feed_filter {
+                            FeedPaneItem::AllFeeds => title.clone(),
+                            FeedPaneItem::Folder(path) => format!("{} in {}", title, path),
+                            FeedPaneItem::Feed(name) => format!("{} from {}", title, name),
+                        };
+                        if let Some(query) = &self.search_query {
+                            title = format!("{} matching {:?}", title, query);
+                        }
+                        if let Some(view) = &self.script_view {
+                            title = format!("{} in view {}", title, view);
+                        }
+                        self.nav_list_for_blog_entries(f, title.as_str(), chunks[0])
+                    }
+                    SelectedScreen::Downloads => {
+                        self.render_downloads(f, title.as_str(), chunks[0])
+                    }
+                    _ => self.nav_list_generic(f, title.as_str(), chunks[0]),
+                };
+                self.render_status_bar(f, title.as_str(), chunks[1]);
+                if self.show_help {
+                    self.render_help(f);
+                }
+            })
+            .unwrap();
     }
 
     fn handle_keyboard_input(&mut self, key: KeyEvent) -> bool {
-        let mut resp = true;
         match self.input_mode {
             InputMode::Editing => match key.code {
                 KeyCode::Enter => {
-                    match self.selected_screen {
-                        SelectedScreen::CreateSession => {
-                            self.save_current_buffer_to_selected_response();
-                        }
-                        _ => {}
+                    if let SelectedScreen::CreateSession = self.selected_screen {
+                        self.save_current_buffer_to_selected_response();
                     }
                     self.messages.push(self.input.drain(..).collect());
                     self.input_mode = InputMode::Normal;
@@ -277,114 +677,1140 @@ impl App {
                 }
                 _ => {}
             },
-            InputMode::Normal => match key.code {
-                KeyCode::Left => {
-                    self.go_to_previous_screen();
+            InputMode::Command => return self.handle_command_line_input(key),
+            InputMode::Confirm => return self.handle_confirmation(key),
+            InputMode::Normal => {
+                if self.show_help && key.code == KeyCode::Esc {
+                    self.show_help = false;
+                    return true;
                 }
-                KeyCode::Char('q') => resp = false,
-                KeyCode::Char('j') => {
-                    self.items.next();
+                self.pending_keys.push(KeyChord::from_event(key));
+                match self.keymap.lookup(&self.pending_keys) {
+                    KeyLookup::Matched(action) => {
+                        self.pending_keys.clear();
+                        return self.run_action(action);
+                    }
+                    KeyLookup::Script(command) => {
+                        self.pending_keys.clear();
+                        self.run_script_command(&command);
+                    }
+                    KeyLookup::Pending => {}
+                    KeyLookup::Unbound => {
+                        // A key that breaks off a sequence, like `j` after `g`, still
+                        // does what it does on its own.
+                        let broke_off = self.pending_keys.len() > 1;
+                        self.pending_keys.clear();
+                        if broke_off {
+                            return self.handle_keyboard_input(key);
+                        }
+                    }
                 }
-                KeyCode::Char('k') => {
-                    self.items.previous();
+            }
+        }
+        true
+    }
+
+    fn open_command_line(&mut self, text: &str) {
+        self.input = text.to_string();
+        self.command_line.reset();
+        self.input_mode = InputMode::Command;
+    }
+
+    /// Keys typed at the `:` prompt. Returns false if a command asked to quit.
+    fn handle_command_line_input(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Enter => {
+                let line: String = self.input.drain(..).collect();
+                self.input_mode = InputMode::Normal;
+                self.command_line.push_history(&line);
+                match Command::parse(&line) {
+                    Ok(command) => return self.run_command(command),
+                    Err(e) => self.report_error(e.to_string()),
                 }
-                KeyCode::Char('b') => {
-                    self.go_to_previous_screen();
+            }
+            KeyCode::Esc => {
+                self.input.clear();
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Tab => {
+                let source = CompletionSource {
+                    feed_names: self
+                        .session
+                        .as_ref()
+                        .map(|session| session.get_feed_names())
+                        .unwrap_or_default(),
+                    session_names: Session::saved_session_names(),
+                    folder_names: self
+                        .session
+                        .as_ref()
+                        .map(|session| session.get_folder_paths())
+                        .unwrap_or_default(),
+                    script_commands: self.scripts.commands(),
+                    script_views: self.scripts.views(),
+                };
+                if let Some(completed) = self.command_line.complete(&self.input, &source) {
+                    self.input = completed;
                 }
-                KeyCode::Char('p') => self.show_popup = !self.show_popup,
-                KeyCode::Char('i') => {
-                    self.input_mode = InputMode::Editing;
+            }
+            KeyCode::Up => {
+                if let Some(line) = self.command_line.previous() {
+                    self.input = line;
                 }
-                KeyCode::Down => {
-                    self.items.next();
+            }
+            KeyCode::Down => {
+                if let Some(line) = self.command_line.next() {
+                    self.input = line;
                 }
-                KeyCode::Up => {
-                    self.items.previous();
+            }
+            KeyCode::Backspace => {
+                self.command_line.reset();
+                if self.input.pop().is_none() {
+                    self.input_mode = InputMode::Normal;
                 }
-                KeyCode::Enter => {
-                    let label = self.items.items[self.items.state.selected().unwrap()].as_str();
-                    match self.selected_screen {
-                        SelectedScreen::Authors => {}
-                        SelectedScreen::Home => {
-                            match HomeScreenOptions::from_string(label) {
-                                HomeScreenOptions::ViewPosts => {
-                                    self.select_screen(SelectedScreen::Posts);
-                                }
-                                HomeScreenOptions::CreateSession => {
-                                    self.select_screen(SelectedScreen::CreateSession)
-                                }
-                                HomeScreenOptions::ChangeSession => {
-                                    self.select_screen(SelectedScreen::SelectSession)
-                                }
-                                HomeScreenOptions::AddRemoveSources => {
-                                    self.select_screen(SelectedScreen::Feeds)
-                                }
-                                HomeScreenOptions::Procedures => {
-                                    self.select_screen(SelectedScreen::Procedures)
-                                }
-                            };
+            }
+            KeyCode::Char(c) => {
+                self.command_line.reset();
+                self.input.push(c);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Carries out a `:` command. Returns false if the app should quit.
+    fn run_command(&mut self, command: Command) -> bool {
+        if let Command::Quit = command {
+            return false;
+        }
+        if let Command::Session { name } = &command {
+            self.switch_session(name);
+            return true;
+        }
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => {
+                self.report_error(String::from("Create a session first"));
+                return true;
+            }
+        };
+        match command {
+            Command::Add { url, name } => {
+                let result =
+                    block_on(session.add_feed(&url, name.as_deref(), &self.http, &self.scripts));
+                let done = match &result {
+                    Ok(name) => format!("Added {}", name),
+                    Err(_) => String::new(),
+                };
+                self.report_fetch(result.map(|_| ()), done);
+                self.load_screen_items();
+            }
+            Command::Refresh { feed: None } => self.refresh_posts(),
+            Command::Refresh { feed: Some(name) }
+                if self.sync.is_some() && session.is_synced_feed(&name) =>
+            {
+                self.sync_with_server()
+            }
+            Command::Refresh { feed: Some(name) } => {
+                let result = block_on(session.fetch_feed_entries(
+                    &name,
+                    &self.http,
+                    &self.hooks,
+                    &self.scripts,
+                ));
+                self.report_fetch(result, format!("Updated {}", name));
+                self.load_screen_items();
+            }
+            Command::Search { query } => self.browse_posts(self.feed_filter.clone(), Some(query)),
+            Command::Export {
+                format,
+                path,
+                scope,
+            } => {
+                let (title, entries) = self.export_entries(scope);
+                let language = self.session.as_ref().unwrap().language_of(&entries);
+                match export::export(&path, format, &title, language.as_deref(), &entries) {
+                    Ok(_) => {
+                        self.report(format!("Exported {} entries to {}", entries.len(), path));
+                        if scope == ExportScope::Marked {
+                            self.marked.clear();
+                            self.load_screen_items();
                         }
-                        SelectedScreen::Posts => {
-                            match PostsOptions::from_string(label) {
-                                PostsOptions::Home => self.select_screen(SelectedScreen::Home),
-                                PostsOptions::Browse => {
-                                    self.select_screen(SelectedScreen::BrowsePosts)
-                                }
-                                PostsOptions::Search => {
-                                    todo!("will be a self.run_procedure or something instead of select_screen")
-                                }
-                                PostsOptions::Authors => {
-                                    self.select_screen(SelectedScreen::Authors)
-                                }
-                                PostsOptions::Categories => {
-                                    todo!()
-                                }
-                            };
+                    }
+                    Err(e) => self.report_error(format!("Export failed: {}", e)),
+                }
+            }
+            Command::ExportOpml { path } => {
+                let text = opml::to_opml(&session.name, &session.blog_feeds);
+                match expand_home(&path).and_then(|file| Ok(std::fs::write(file, text)?)) {
+                    Ok(_) => self.report(format!("Exported feeds to {}", path)),
+                    Err(e) => self.report_error(format!("Export failed: {}", e)),
+                }
+            }
+            Command::RefreshFolder { folder } => {
+                let result = block_on(session.fetch_folder_entries(
+                    &folder,
+                    true,
+                    &self.http,
+                    &self.hooks,
+                    &self.scripts,
+                ));
+                self.report_fetch(result, format!("Updated {}", folder));
+                self.load_screen_items();
+            }
+            Command::ImportOpml { path } => {
+                match expand_home(&path)
+                    .and_then(|file| Ok(std::fs::read_to_string(file)?))
+                    .and_then(|text| opml::from_opml(&text))
+                {
+                    Ok(feeds) => {
+                        let (added, rejected) = session.import_blog_feeds(feeds);
+                        self.report(format!("Imported {} feeds from {}", added, path));
+                        if !rejected.is_empty() {
+                            self.report_error(format!(
+                                "Skipped {} feeds that aren't web feeds: {}",
+                                rejected.len(),
+                                rejected.join(", ")
+                            ));
                         }
-                        SelectedScreen::Feeds => match FeedsOptions::from_string(label) {
-                            FeedsOptions::ViewFeeds => {
-                                todo!("screen with editable content");
-                            }
-                            FeedsOptions::AddFeed => {
-                                todo!("procedure");
+                    }
+                    Err(e) => self.report_error(format!("Import failed: {}", e)),
+                }
+            }
+            Command::MarkFolderRead { folder } => {
+                session.mark_folder_read(&folder);
+                self.report(format!("Marked {} read", folder));
+                self.load_screen_items();
+            }
+            Command::Folder { path } => match &self.feed_filter {
+                FeedPaneItem::Feed(name) => {
+                    let name = name.clone();
+                    match session.set_feed_folder(&name, &path) {
+                        Ok(_) => self.report(format!("Moved {} to {:?}", name, path)),
+                        Err(e) => self.report_error(e.to_string()),
+                    }
+                    self.load_screen_items();
+                }
+                _ => self.report_error(String::from("Pick a feed in the feeds pane first")),
+            },
+            Command::Rule {
+                action,
+                field,
+                pattern,
+            } => {
+                let name = format!("{:?} ~ {}", field, pattern).to_lowercase();
+                let rule = Rule::new(&name, Condition::Matches { field, pattern }, vec![action]);
+                match session.add_rule(rule) {
+                    Ok(_) => {
+                        self.report(format!("Added rule {}, :apply-rules to run it now", name))
+                    }
+                    Err(e) => self.report_error(e.to_string()),
+                }
+                self.load_screen_items();
+            }
+            Command::ApplyRules => {
+                match session.apply_rules() {
+                    Ok(count) => self.report(format!("Rules matched {} entries", count)),
+                    Err(e) => self.report_error(e.to_string()),
+                }
+                self.load_screen_items();
+            }
+            Command::UnhideAll => {
+                session.unhide_all();
+                self.report(String::from("Every hidden entry is back"));
+                self.load_screen_items();
+            }
+            Command::FullContent { enabled } => match &self.feed_filter {
+                FeedPaneItem::Feed(name) => {
+                    let name = name.clone();
+                    if let Err(e) = session.set_full_content(&name, enabled) {
+                        self.report_error(e.to_string());
+                    } else if enabled {
+                        match block_on(session.fetch_full_articles(&name, &self.http)) {
+                            Ok(count) => {
+                                self.report(format!("Fetched {} articles for {}", count, name))
                             }
-                            FeedsOptions::Home => self.select_screen(SelectedScreen::Home),
-                        },
-                        SelectedScreen::CreateSession => {
-                            match self.proceed_with_question_responses() {
-                                Ok(_) => self.select_screen(SelectedScreen::Home),
-                                Err(_) => panic!("creation failed"), //self.select_screen(SelectedScreen::CreateSession),
+                            Err(e) => self.report_error(e.to_string()),
+                        }
+                    } else {
+                        self.report(format!("Stopped fetching articles for {}", name));
+                    }
+                }
+                _ => self.report_error(String::from("Pick a feed in the feeds pane first")),
+            },
+            Command::FetchArticle => match self.selected_entry() {
+                Some(entry) => {
+                    match block_on(
+                        self.session
+                            .as_mut()
+                            .unwrap()
+                            .fetch_entry_article(&entry.url, &self.http),
+                    ) {
+                        Ok(_) => self.report(format!("Fetched {}", entry.title)),
+                        Err(e) => self.report_error(format!("Fetch failed: {}", e)),
+                    }
+                    self.preview_scroll = 0;
+                }
+                None => self.report_error(String::from("No entry selected")),
+            },
+            Command::OfflineCache { enabled } => match &self.feed_filter {
+                FeedPaneItem::Feed(name) => {
+                    let name = name.clone();
+                    if let Err(e) = session.set_cache_offline(&name, enabled) {
+                        self.report_error(e.to_string());
+                    } else if enabled {
+                        match block_on(session.archive_feed(&name, &self.http)) {
+                            Ok(count) => {
+                                self.report(format!("Cached {} pages for {}", count, name))
                             }
+                            Err(e) => self.report_error(e.to_string()),
                         }
-                        SelectedScreen::SelectSession => {
-                            todo!("procedure")
+                    } else {
+                        self.report(format!("Stopped caching {}", name));
+                    }
+                }
+                _ => self.report_error(String::from("Pick a feed in the feeds pane first")),
+            },
+            Command::Auth { auth } => match &self.feed_filter {
+                FeedPaneItem::Feed(name) => {
+                    let name = name.clone();
+                    let message = match &auth {
+                        Some(Auth::Basic { username, .. }) => {
+                            format!("{} now logs in as {}", name, username)
                         }
-                        SelectedScreen::Procedures => {
-                            match ProceduresOptions::from_string(label) {
-                                ProceduresOptions::Home => {
-                                    self.select_screen(SelectedScreen::Home);
-                                }
-                                ProceduresOptions::AddSource => todo!(),
-                                ProceduresOptions::DumpSessionData => {
-                                    self.session.as_ref().unwrap().dump_to_json()
-                                }
-                                ProceduresOptions::UpdatePosts => block_on(
-                                    self.session.to_owned().unwrap().fetch_all_blog_entries(),
-                                ),
-                            };
+                        Some(Auth::Bearer { .. }) => format!("{} now sends a bearer token", name),
+                        None => format!("Removed the credentials of {}", name),
+                    };
+                    match session.set_feed_auth(&name, auth) {
+                        Ok(_) => self.report(message),
+                        Err(e) => self.report_error(e.to_string()),
+                    }
+                }
+                _ => self.report_error(String::from("Pick a feed in the feeds pane first")),
+            },
+            Command::Header {
+                name: header,
+                value,
+            } => match &self.feed_filter {
+                FeedPaneItem::Feed(name) => {
+                    let name = name.clone();
+                    let message = match &value {
+                        Some(_) => format!("{} now sends {}", name, header),
+                        None => format!("{} no longer sends {}", name, header),
+                    };
+                    match session.set_feed_header(&name, &header, value) {
+                        Ok(_) => self.report(message),
+                        Err(e) => self.report_error(e.to_string()),
+                    }
+                }
+                _ => self.report_error(String::from("Pick a feed in the feeds pane first")),
+            },
+            Command::Details => match &self.feed_filter {
+                FeedPaneItem::Feed(_) => self.select_screen(SelectedScreen::FeedDetails),
+                _ => self.report_error(String::from("Pick a feed in the feeds pane first")),
+            },
+            Command::Unsubscribe => match &self.feed_filter {
+                FeedPaneItem::Feed(name) => {
+                    self.confirmations
+                        .push(Confirmation::Unsubscribe { feed: name.clone() });
+                    self.input_mode = InputMode::Confirm;
+                }
+                _ => self.report_error(String::from("Pick a feed in the feeds pane first")),
+            },
+            Command::Cleanup => {
+                match archive::cleanup(&session.blog_feeds, &self.retention) {
+                    Ok(report) => self.report(format!(
+                        "Removed {} cached pages, freed {}",
+                        report.removed,
+                        archive::format_size(report.freed)
+                    )),
+                    Err(e) => self.report_error(format!("Cleanup failed: {}", e)),
+                }
+                self.load_screen_items();
+            }
+            Command::Download => match self.selected_entry() {
+                Some(entry) if !entry.enclosures.is_empty() => {
+                    for enclosure in entry.enclosures.iter() {
+                        let http = self.session.as_ref().unwrap().link_settings(
+                            &entry.url,
+                            &self.http,
+                            &enclosure.url,
+                        );
+                        if let Err(e) = self.downloads.queue(&entry.title, &enclosure.url, http) {
+                            self.report_error(e.to_string());
+                            return true;
                         }
-                        SelectedScreen::BrowsePosts => self.should_open_link = true,
                     }
+                    self.report(format!("Queued {} for download", entry.title));
                 }
-                _ => {}
+                _ => self.report_error(String::from("The selected entry has no attachments")),
+            },
+            Command::Downloads => self.select_screen(SelectedScreen::Downloads),
+            Command::Play => match self.selected_entry() {
+                Some(entry) if !entry.enclosures.is_empty() => {
+                    let url = &entry.enclosures[0].url;
+                    let target = match self.downloads.downloaded_path(url) {
+                        Some(path) => path.to_string_lossy().to_string(),
+                        None => url.clone(),
+                    };
+                    match self.downloads.play(&target) {
+                        Ok(_) => self.report(format!("Playing {}", entry.title)),
+                        Err(e) => self.report_error(e.to_string()),
+                    }
+                }
+                _ => self.report_error(String::from("The selected entry has no attachments")),
+            },
+            Command::Sync => self.sync_with_server(),
+            Command::Digest => self.write_digest(),
+            Command::ReadLater => self.send_selected_to_read_later(),
+            Command::RetryReadLater => self.retry_read_later(),
+            Command::MarkAllRead => {
+                session.mark_all_read();
+                self.report(String::from("Marked everything read"));
+                self.load_screen_items();
+            }
+            Command::Sort { mode } => {
+                let view = self.view_key();
+                let session = self.session.as_mut().unwrap();
+                let mut order = session.view_order(&view);
+                order.sort = mode;
+                session.set_view_order(&view, order);
+                match &self.script_view {
+                    Some(name) => self.report(format!(
+                        "Sorted by {}, once the view {} is closed",
+                        mode.name(),
+                        name
+                    )),
+                    None => self.report(format!("Sorted by {}", mode.name())),
+                }
+                self.load_screen_items();
+            }
+            Command::Group { mode } => {
+                let view = self.view_key();
+                let session = self.session.as_mut().unwrap();
+                let mut order = session.view_order(&view);
+                order.group = mode;
+                session.set_view_order(&view, order);
+                match mode {
+                    GroupMode::None => self.report(String::from("Stopped grouping")),
+                    mode => self.report(format!("Grouped by {}", mode.name())),
+                }
+                self.load_screen_items();
+            }
+            Command::Script { name } => self.run_script_command(&name),
+            Command::View { name: None } => {
+                self.script_view = None;
+                self.load_screen_items();
+            }
+            Command::View { name: Some(name) } => {
+                if !self.scripts.views().contains(&name) {
+                    self.report_error(format!("No script defines a view called {}", name));
+                    return true;
+                }
+                if !matches!(self.selected_screen, SelectedScreen::BrowsePosts) {
+                    self.browse_posts(self.feed_filter.clone(), None);
+                }
+                self.script_view = Some(name);
+                self.load_screen_items();
+                self.items.state.select(Some(0));
+            }
+            Command::Session { .. } | Command::Quit => {}
+        }
+        true
+    }
+
+    /// Saves the current session and loads the named one in its place.
+    fn switch_session(&mut self, name: &str) {
+        match Session::load_by_name(name) {
+            Ok(session) => {
+                if let Some(current) = self.session.as_ref() {
+                    current.dump_to_json();
+                }
+                self.session = Some(session);
+                self.search_query = None;
+                self.script_view = None;
+                self.feed_filter = FeedPaneItem::AllFeeds;
+                // The old session's screens mean nothing in the new one.
+                self.history = NavigationHistory::default();
+                self.show_screen(SelectedScreen::Home);
+                self.report(format!("Switched to session {}", name));
+            }
+            Err(e) => self.report_error(e.to_string()),
+        }
+    }
+
+    /// Carries out a bound action. Returns false if the app should quit.
+    fn run_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::Quit => return false,
+            Action::Next
+            | Action::Previous
+            | Action::First
+            | Action::Last
+            | Action::HalfPageDown
+            | Action::HalfPageUp => self.move_cursor(action),
+            Action::FocusNextPane => self.cycle_focus(1),
+            Action::FocusPreviousPane => self.cycle_focus(self.visible_panes.len() - 1),
+            Action::GrowPane => resize_pane(&mut self.layout, self.focused_pane, 5),
+            Action::ShrinkPane => resize_pane(&mut self.layout, self.focused_pane, -5),
+            Action::Back => self.go_back(),
+            Action::Forward => self.go_forward(),
+            Action::TogglePopup => self.show_popup = !self.show_popup,
+            Action::Edit => self.input_mode = InputMode::Editing,
+            Action::CommandLine => self.open_command_line(""),
+            Action::Help => self.show_help = !self.show_help,
+            Action::Save => {
+                if let Some(session) = self.session.as_ref() {
+                    session.dump_to_json()
+                }
+            }
+            Action::Refresh => self.refresh_posts(),
+            Action::ToggleStar => self.toggle_star_selected(),
+            Action::ToggleMark => self.toggle_mark_selected(),
+            Action::ReadLater => self.send_selected_to_read_later(),
+            Action::Open => self.open_selected(),
+        }
+        true
+    }
+
+    /// Moves whichever cursor the focused pane owns. The article pane scrolls instead.
+    fn move_cursor(&mut self, action: Action) {
+        let pane = match self.selected_screen {
+            SelectedScreen::BrowsePosts => self.focused_pane,
+            _ => Pane::Entries,
+        };
+        match pane {
+            Pane::Feeds => {
+                step_list(&mut self.feed_pane, action, self.page_size);
+                self.apply_feed_filter();
+            }
+            Pane::Entries => {
+                step_list(&mut self.items, action, self.page_size);
+                self.preview_scroll = 0;
+            }
+            Pane::Preview => {
+                let page = self.page_size as u16;
+                self.preview_scroll = match action {
+                    Action::Next => self.preview_scroll.saturating_add(1),
+                    Action::Previous => self.preview_scroll.saturating_sub(1),
+                    Action::HalfPageDown => self.preview_scroll.saturating_add(page),
+                    Action::HalfPageUp => self.preview_scroll.saturating_sub(page),
+                    Action::First => 0,
+                    _ => self.preview_scroll,
+                };
+            }
+        }
+    }
+
+    /// Moves focus `step` panes along, wrapping around the visible ones.
+    fn cycle_focus(&mut self, step: usize) {
+        let current = self
+            .visible_panes
+            .iter()
+            .position(|pane| *pane == self.focused_pane)
+            .unwrap_or(0);
+        self.focused_pane = self.visible_panes[(current + step) % self.visible_panes.len()];
+    }
+
+    /// Fetch every feed and reload the current screen so new posts show up.
+    fn refresh_posts(&mut self) {
+        if self.sync.is_some() {
+            self.sync_with_server();
+        }
+        if let Some(session) = self.session.as_mut() {
+            let result = block_on(session.fetch_all_blog_entries(
+                true,
+                &self.http,
+                &self.hooks,
+                &self.scripts,
+            ));
+            self.report_fetch(result, String::from("Posts updated"));
+            self.load_screen_items();
+        }
+        if self
+            .read_later
+            .as_ref()
+            .is_some_and(|read_later| read_later.queued() > 0)
+        {
+            self.retry_read_later();
+        }
+    }
+
+    fn sync_with_server(&mut self) {
+        let (sync, session) = match (&self.sync, self.session.as_mut()) {
+            (Some(sync), Some(session)) => (sync, session),
+            (None, _) => {
+                self.report_error(String::from(
+                    "No sync server is set up, add one under sync in the config",
+                ));
+                return;
+            }
+            (_, None) => return,
+        };
+        match block_on(sync.sync(session, &self.http, &self.scripts)) {
+            Ok(report) => self.report(report.to_string()),
+            Err(e) => self.report_error(format!("Sync failed: {}", e)),
+        }
+        self.report_broken_rules();
+        self.load_screen_items();
+    }
+
+    fn write_digest(&mut self) {
+        let (digest, session) = match (&self.digest, self.session.as_mut()) {
+            (Some(digest), Some(session)) => (digest, session),
+            (None, _) => {
+                self.report_error(String::from(
+                    "No digest is set up, add one under digest in the config",
+                ));
+                return;
+            }
+            (_, None) => return,
+        };
+        match digest.run(session) {
+            Ok(report) => {
+                session.dump_to_json();
+                self.report(report.to_string());
+            }
+            Err(e) => self.report_error(format!("Digest failed: {}", e)),
+        }
+        self.load_screen_items();
+    }
+
+    /// Runs a command from the scripts on the entries being shown.
+    fn run_script_command(&mut self, command: &str) {
+        let shown = match self.selected_screen {
+            SelectedScreen::BrowsePosts => self.listed_entries(),
+            _ => vec![],
+        };
+        let selected = self.selected_entry();
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return,
+        };
+        match self
+            .scripts
+            .run_command(command, session, &shown, selected.as_ref())
+        {
+            Ok(changed) => {
+                self.report(format!("Ran {}, {} changes", command, changed));
+                self.load_screen_items();
+            }
+            Err(e) => self.report_error(e.to_string()),
+        }
+    }
+
+    fn send_selected_to_read_later(&mut self) {
+        let read_later = match &self.read_later {
+            Some(read_later) => read_later.clone(),
+            None => {
+                self.report_error(String::from(
+                    "No read-later service is set up, add one under read_later in the config",
+                ));
+                return;
+            }
+        };
+        let entry = match self.selected_entry() {
+            Some(entry) => entry,
+            None => {
+                self.report_error(String::from("Pick an entry first"));
+                return;
+            }
+        };
+        match block_on(read_later.send(&entry.url, &entry.title)) {
+            Ok(_) => self.report(format!(
+                "Sent {} to {}",
+                entry.title,
+                read_later.service_name()
+            )),
+            Err(e) => self.report_error(format!("{:#}", e)),
+        }
+    }
+
+    fn retry_read_later(&mut self) {
+        let read_later = match &self.read_later {
+            Some(read_later) => read_later.clone(),
+            None => return,
+        };
+        match block_on(read_later.retry_queued()) {
+            Ok((0, 0, 0)) => self.report(String::from("Nothing is waiting to be sent")),
+            Ok((sent, 0, 0)) => self.report(format!(
+                "Sent {} queued entries to {}",
+                sent,
+                read_later.service_name()
+            )),
+            Ok((sent, waiting, 0)) => self.report_error(format!(
+                "Sent {} queued entries, {} still waiting for {}",
+                sent,
+                waiting,
+                read_later.service_name()
+            )),
+            Ok((sent, waiting, dropped)) => self.report_error(format!(
+                "Sent {} queued entries, {} still waiting, {} rejected by {} and dropped",
+                sent,
+                waiting,
+                dropped,
+                read_later.service_name()
+            )),
+            Err(e) => self.report_error(format!("{:#}", e)),
+        }
+    }
+
+    /// The lines of the Feed Details screen.
+    fn feed_details(&self, feed: &BlogFeed) -> Vec<String> {
+        let metadata = &feed.metadata;
+        let or_none = |value: &Option<String>| value.clone().unwrap_or(String::from("-"));
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        let schedule = match feed.refresh_interval() {
+            Some(interval) if feed.is_due() => {
+                format!("every {} min, due now", interval.num_minutes())
+            }
+            Some(interval) => format!("every {} min, not due yet", interval.num_minutes()),
+            None => String::from("whenever posts are updated"),
+        };
+        let description = metadata.description.as_ref().map(|description| {
+            description
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ")
+        });
+        vec![
+            format!("Name: {}", feed.name),
+            format!("Url: {}", feed.url),
+            format!("Folder: {}", or_none(&feed.folder)),
+            format!("Title: {}", or_none(&metadata.title)),
+            format!("Description: {}", or_none(&description)),
+            format!("Site: {}", or_none(&metadata.site_link)),
+            format!("Icon: {}", or_none(&metadata.icon)),
+            format!("Language: {}", or_none(&metadata.language)),
+            format!("Generator: {}", or_none(&metadata.generator)),
+            format!("Refresh: {}", schedule),
+            format!("Health: {}", feed.health.summary(&self.health)),
+            format!("Last error: {}", or_none(&feed.last_error)),
+            format!(
+                "Full articles: {}, offline cache: {}",
+                on_off(feed.fetch_full_content),
+                on_off(feed.cache_offline)
+            ),
+            format!(
+                "Entries: {}",
+                feed.entries.as_ref().map_or(0, |entries| entries.len())
+            ),
+        ]
+    }
+
+    /// Reports how a fetch went, going offline if the network couldn't be reached.
+    fn report_fetch(&mut self, result: Result<()>, done: String) {
+        match result {
+            Ok(_) => {
+                self.offline = false;
+                self.report(done);
+            }
+            Err(e) if archive::is_offline_error(&e) => {
+                self.offline = true;
+                self.report_error(String::from("Offline, using cached pages"));
+            }
+            Err(e) => self.report_error(format!("Update failed: {}", e)),
+        }
+        self.report_broken_rules();
+        self.review_feed_changes();
+    }
+
+    /// Rules that don't compile are left out of refreshes rather than stopping
+    /// them, so they're pointed out here.
+    fn report_broken_rules(&mut self) {
+        let broken = match self.session.as_mut() {
+            Some(session) => session.take_broken_rules(),
+            None => return,
+        };
+        if !broken.is_empty() {
+            self.report_error(format!("Skipped {}", broken.join("; ")));
+        }
+    }
+
+    /// Deals with feeds the last refresh found moved or gone. Moves are applied
+    /// straight away if the config says so, everything else is asked about.
+    fn review_feed_changes(&mut self) {
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return,
+        };
+        let moved = session.take_moved_feeds();
+        let gone = session.take_gone_feeds();
+        for (feed, url) in moved {
+            if self.update_moved_feeds {
+                self.move_feed(&feed, &url);
+            } else {
+                self.confirmations
+                    .push(Confirmation::MoveFeed { feed, url });
+            }
+        }
+        for feed in gone {
+            self.confirmations.push(Confirmation::Unsubscribe { feed });
+        }
+        if !self.confirmations.is_empty() {
+            self.input_mode = InputMode::Confirm;
+        }
+    }
+
+    /// `y` accepts the question at the front of the queue, any other key declines it.
+    /// Esc leaves the questions to be asked again after the next refresh, and the
+    /// quit key still quits. Returns false to quit.
+    fn handle_confirmation(&mut self, key: KeyEvent) -> bool {
+        if self.confirmations.is_empty() || key.code == KeyCode::Esc {
+            self.confirmations.clear();
+            self.input_mode = InputMode::Normal;
+            return true;
+        }
+        if let KeyLookup::Matched(Action::Quit) = self.keymap.lookup(&[KeyChord::from_event(key)]) {
+            return false;
+        }
+        let confirmation = self.confirmations.remove(0);
+        let accepted = matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y'));
+        match (confirmation, accepted) {
+            (Confirmation::MoveFeed { feed, url }, true) => self.move_feed(&feed, &url),
+            (Confirmation::MoveFeed { feed, url }, false) => {
+                self.session.as_mut().unwrap().decline_move(&feed, &url);
+                self.report(format!("Keeping {} at its old url", feed));
+            }
+            (Confirmation::Unsubscribe { feed }, true) => self.unsubscribe(&feed),
+            (Confirmation::Unsubscribe { .. }, false) => {}
+        }
+        if self.confirmations.is_empty() {
+            self.input_mode = InputMode::Normal;
+        }
+        true
+    }
+
+    fn move_feed(&mut self, feed: &str, url: &str) {
+        match self.session.as_mut().unwrap().set_feed_url(feed, url) {
+            Ok(old) => self.report(format!("{} moved from {} to {}", feed, old, url)),
+            Err(e) => self.report_error(e.to_string()),
+        }
+    }
+
+    fn unsubscribe(&mut self, feed: &str) {
+        match self.session.as_mut().unwrap().unsubscribe(feed) {
+            Ok(_) => {
+                self.report(format!("Unsubscribed from {}", feed));
+                if self.feed_filter == FeedPaneItem::Feed(feed.to_string()) {
+                    self.feed_filter = FeedPaneItem::AllFeeds;
+                }
+                self.load_feed_pane();
+                self.load_screen_items();
+            }
+            Err(e) => self.report_error(e.to_string()),
+        }
+    }
+
+    /// The entries listed on the BrowsePosts screen, narrowed by any search.
+    fn browse_entries(&self) -> Vec<Entry> {
+        let session = self.session.as_ref().unwrap();
+        let entries = match &self.feed_filter {
+            FeedPaneItem::AllFeeds => session.get_all_blog_entries(),
+            FeedPaneItem::Folder(path) => session.get_folder_entries(path),
+            FeedPaneItem::Feed(name) => session.get_feed_entries(name),
+        };
+        let entries = match &self.search_query {
+            Some(query) => entries
+                .into_iter()
+                .filter(|entry| entry.matches_query(query))
+                .collect(),
+            None => entries,
+        };
+        match &self.script_view {
+            // A view that fails shows everything, with its error in the message log.
+            Some(view) => self
+                .scripts
+                .view(view, entries.clone())
+                .unwrap_or_else(|e| {
+                    self.scripts.report_error(e.to_string());
+                    entries
+                }),
+            None => entries,
+        }
+    }
+
+    /// The key the sort and grouping of the entry list being shown is kept under.
+    fn view_key(&self) -> String {
+        match &self.feed_filter {
+            FeedPaneItem::AllFeeds => String::from("all"),
+            FeedPaneItem::Folder(path) => format!("folder:{}", path),
+            FeedPaneItem::Feed(name) => format!("feed:{}", name),
+        }
+    }
+
+    /// The entries on the BrowsePosts screen in the view's order, under group headers.
+    /// A script view's entries keep the order the script gave them.
+    fn arrange_rows(&self) -> Vec<Row> {
+        let session = self.session.as_ref().unwrap();
+        let order = session.view_order(&self.view_key());
+        let folder_of = |name: &str| session.feed_folder(name);
+        match self.script_view {
+            Some(_) => order.group(self.browse_entries(), folder_of),
+            None => order.arrange(self.browse_entries(), folder_of),
+        }
+    }
+
+    /// The entries on the BrowsePosts screen, in the order they're listed.
+    fn listed_entries(&self) -> Vec<Entry> {
+        self.rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::Entry(entry) => Some(*entry.clone()),
+                Row::Header(_) => None,
+            })
+            .collect()
+    }
+
+    /// The entry under the cursor on the BrowsePosts screen, if there is one.
+    fn selected_entry(&self) -> Option<Entry> {
+        match self.selected_screen {
+            SelectedScreen::BrowsePosts => match self.rows.get(self.items.state.selected()?) {
+                Some(Row::Entry(entry)) => Some(*entry.clone()),
+                _ => None,
             },
+            _ => None,
         }
-        resp
     }
 
-    fn nav_list_generic<B: Backend>(&mut self, f: &mut Frame<B>, title: &str) {
-        let size = f.size();
+    fn toggle_mark_selected(&mut self) {
+        if let Some(entry) = self.selected_entry() {
+            if !self.marked.remove(&entry.url) {
+                self.marked.insert(entry.url);
+            }
+            self.load_screen_items();
+        }
+    }
+
+    /// The entries an export covers, in the order they're listed, with a title
+    /// for the file.
+    fn export_entries(&self, scope: ExportScope) -> (String, Vec<Entry>) {
+        let session = self.session.as_ref().unwrap();
+        match scope {
+            ExportScope::List => {
+                let mut title = match &self.feed_filter {
+                    FeedPaneItem::AllFeeds => String::from("All feeds"),
+                    FeedPaneItem::Folder(path) => path.clone(),
+                    FeedPaneItem::Feed(name) => name.clone(),
+                };
+                if let Some(query) = &self.search_query {
+                    title = format!("{} matching {:?}", title, query);
+                }
+                if let Some(view) = &self.script_view {
+                    title = format!("{} in view {}", title, view);
+                }
+                (title, self.listed_entries())
+            }
+            ExportScope::Marked if self.marked.is_empty() => {
+                let entries: Vec<Entry> = self.selected_entry().into_iter().collect();
+                let title = entries
+                    .first()
+                    .map(|entry| entry.title.clone())
+                    .unwrap_or_default();
+                (title, entries)
+            }
+            ExportScope::Marked => {
+                let mut entries: Vec<Entry> = session
+                    .get_all_blog_entries()
+                    .into_iter()
+                    .filter(|entry| self.marked.contains(&entry.url))
+                    .collect();
+                session.view_order("all").sort.sort(&mut entries);
+                (String::from("Marked entries"), entries)
+            }
+            ExportScope::Starred => {
+                let mut entries: Vec<Entry> = session
+                    .get_all_blog_entries()
+                    .into_iter()
+                    .filter(|entry| entry.starred)
+                    .collect();
+                session.view_order("all").sort.sort(&mut entries);
+                (String::from("Starred entries"), entries)
+            }
+        }
+    }
 
+    fn toggle_star_selected(&mut self) {
+        if let Some(entry) = self.selected_entry() {
+            let session = self.session.as_mut().unwrap();
+            if session.toggle_entry_starred(&entry.url) == Some(true) {
+                if let Some((feed, entry)) = session.find_entry(&entry.url) {
+                    self.hooks.entry_starred(feed, entry);
+                }
+            }
+            self.load_screen_items();
+        }
+    }
+
+    /// Act on the currently highlighted item of whatever screen is showing.
+    fn open_selected(&mut self) {
+        let label = match self
+            .items
+            .state
+            .selected()
+            .and_then(|i| self.items.items.get(i))
+        {
+            Some(label) => label.clone(),
+            None => return,
+        };
+        let label = label.as_str();
+        match self.selected_screen {
+            SelectedScreen::Authors => {}
+            SelectedScreen::Storage => {}
+            SelectedScreen::ViewFeeds => {
+                let index = self.items.state.selected().unwrap_or(0);
+                let feed = self.session.as_ref().unwrap().blog_feeds.get(index);
+                if let Some(feed) = feed {
+                    let name = feed.name.clone();
+                    self.history.visit(self.history_entry());
+                    self.feed_filter = FeedPaneItem::Feed(name);
+                    self.show_screen(SelectedScreen::FeedDetails);
+                }
+            }
+            SelectedScreen::FeedDetails => self.browse_posts(self.feed_filter.clone(), None),
+            SelectedScreen::Downloads => {
+                let index = self.items.state.selected().unwrap_or(0);
+                if let Err(e) = self.downloads.toggle_pause(index) {
+                    self.report_error(e.to_string());
+                }
+            }
+            SelectedScreen::Rules => {
+                let index = self.items.state.selected().unwrap_or(0);
+                self.session.as_mut().unwrap().toggle_rule(index);
+                self.load_screen_items();
+            }
+            SelectedScreen::Folders => {
+                let index = self.items.state.selected().unwrap_or(0);
+                let folders = self.session.as_ref().unwrap().get_folder_paths();
+                if let Some(path) = folders.get(index) {
+                    self.browse_posts(FeedPaneItem::Folder(path.clone()), None);
+                }
+            }
+            SelectedScreen::Home => {
+                match HomeScreenOptions::from_string(label) {
+                    HomeScreenOptions::ViewPosts => {
+                        self.select_screen(SelectedScreen::Posts);
+                    }
+                    HomeScreenOptions::CreateSession => {
+                        self.select_screen(SelectedScreen::CreateSession)
+                    }
+                    HomeScreenOptions::ChangeSession => {
+                        self.select_screen(SelectedScreen::SelectSession)
+                    }
+                    HomeScreenOptions::AddRemoveSources => {
+                        self.select_screen(SelectedScreen::Feeds)
+                    }
+                    HomeScreenOptions::Procedures => self.select_screen(SelectedScreen::Procedures),
+                };
+            }
+            SelectedScreen::Posts => {
+                match PostsOptions::from_string(label) {
+                    PostsOptions::Home => self.select_screen(SelectedScreen::Home),
+                    PostsOptions::Browse => self.browse_posts(FeedPaneItem::AllFeeds, None),
+                    PostsOptions::Folders => self.select_screen(SelectedScreen::Folders),
+                    PostsOptions::Search => self.open_command_line("search "),
+                    PostsOptions::Authors => self.select_screen(SelectedScreen::Authors),
+                    PostsOptions::Categories => {
+                        todo!()
+                    }
+                };
+            }
+            SelectedScreen::Feeds => match FeedsOptions::from_string(label) {
+                FeedsOptions::ViewFeeds => self.select_screen(SelectedScreen::ViewFeeds),
+                FeedsOptions::AddFeed => {
+                    todo!("procedure");
+                }
+                FeedsOptions::Rules => self.select_screen(SelectedScreen::Rules),
+                FeedsOptions::Home => self.select_screen(SelectedScreen::Home),
+            },
+            SelectedScreen::CreateSession => match self.proceed_with_question_responses() {
+                Ok(_) => self.select_screen(SelectedScreen::Home),
+                Err(e) => self.report_error(e.to_string()),
+            },
+            SelectedScreen::SelectSession => self.switch_session(label),
+            SelectedScreen::Procedures => {
+                match ProceduresOptions::from_string(label) {
+                    ProceduresOptions::Home => {
+                        self.select_screen(SelectedScreen::Home);
+                    }
+                    ProceduresOptions::AddSource => todo!(),
+                    ProceduresOptions::DumpSessionData => {
+                        self.session.as_ref().unwrap().dump_to_json()
+                    }
+                    ProceduresOptions::UpdatePosts => self.refresh_posts(),
+                    ProceduresOptions::Storage => self.select_screen(SelectedScreen::Storage),
+                    ProceduresOptions::Downloads => self.select_screen(SelectedScreen::Downloads),
+                };
+            }
+            SelectedScreen::BrowsePosts => match self.focused_pane {
+                Pane::Feeds => self.focused_pane = Pane::Entries,
+                Pane::Entries | Pane::Preview => self.should_open_link = true,
+            },
+        }
+    }
+
+    /// Lists the active key bindings over the top of the current screen.
+    fn render_help<B: Backend>(&self, f: &mut Frame<B>) {
+        let lines: Vec<Spans> = self
+            .keymap
+            .describe()
+            .into_iter()
+            .map(|(keys, action)| {
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{:<24}", keys),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(action),
+                ])
+            })
+            .collect();
+        let help = Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .title("Key Bindings")
+                    .borders(Borders::ALL)
+                    .border_style(self.theme.border),
+            )
+            .wrap(Wrap { trim: false });
+        let area = self.centered_rect(60, 80, f.size());
+        f.render_widget(Clear, area);
+        f.render_widget(help, area);
+    }
+
+    /// One line at the bottom of the screen: the latest message, or the screen name
+    /// and a hint about the help overlay.
+    fn render_status_bar<B: Backend>(&self, f: &mut Frame<B>, title: &str, area: Rect) {
+        if let (InputMode::Confirm, Some(confirmation)) =
+            (&self.input_mode, self.confirmations.first())
+        {
+            let question = format!("{} [y/n]", confirmation.question());
+            f.render_widget(
+                Paragraph::new(question).style(self.theme.status_bar.add_modifier(Modifier::BOLD)),
+                area,
+            );
+            return;
+        }
+        if let InputMode::Command = self.input_mode {
+            let prompt = format!(":{}", self.input);
+            f.set_cursor(area.x + prompt.chars().count() as u16, area.y);
+            f.render_widget(Paragraph::new(prompt), area);
+            return;
+        }
+        let status = match (&self.last_error, self.messages.last()) {
+            (Some(error), _) => Paragraph::new(error.as_str()).style(self.theme.error),
+            (None, Some(message)) => Paragraph::new(message.as_str()).style(self.theme.status_bar),
+            (None, None) if self.offline => {
+                Paragraph::new(format!("{} | offline | ? for help", title))
+                    .style(self.theme.status_bar)
+            }
+            (None, None) => {
+                Paragraph::new(format!("{} | ? for help", title)).style(self.theme.status_bar)
+            }
+        };
+        f.render_widget(status, area);
+    }
+
+    fn nav_list_generic<B: Backend>(&mut self, f: &mut Frame<B>, title: &str, size: Rect) {
         let block = Block::default();
         f.render_widget(block, size);
 
@@ -393,18 +1819,23 @@ impl App {
             .items
             .iter()
             .map(|i| {
-                let mut lines = vec![];
-                lines.push(Spans::from(i.as_str()));
+                let lines = vec![Spans::from(i.as_str())];
                 ListItem::new(lines).style(Style::default())
             })
             .collect();
 
         // Create a List from all list items and highlight the currently selected one
         let items = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title(title))
-            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-            .highlight_symbol("🍄 ");
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.theme.border)
+                    .title(title),
+            )
+            .highlight_style(self.theme.list_highlight)
+            .highlight_symbol(self.theme.highlight_symbol.as_str());
 
+        self.page_size = (size.height as usize / 2).max(1);
         f.render_stateful_widget(items, size, &mut self.items.state)
     }
 
@@ -413,7 +1844,7 @@ impl App {
         title: String,
         question: &QuestionWithResponse,
         question_index: usize,
-    ) -> Paragraph {
+    ) -> Paragraph<'_> {
         let mut paragraph_text = format!(
             "{}{}",
             question.question,
@@ -423,23 +1854,33 @@ impl App {
             paragraph_text = format!("{}{}", paragraph_text, self.input);
             Paragraph::new(paragraph_text)
                 .style(match self.input_mode {
-                    InputMode::Normal => Style::default().fg(Color::Magenta),
-                    InputMode::Editing => Style::default().fg(Color::Yellow),
+                    InputMode::Normal | InputMode::Command | InputMode::Confirm => {
+                        self.theme.input_normal
+                    }
+                    InputMode::Editing => self.theme.input_editing,
                 })
-                .block(Block::default().borders(Borders::ALL).title(title))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(self.theme.border)
+                        .title(title),
+                )
         } else {
             Paragraph::new(paragraph_text)
                 .style(match self.input_mode {
-                    InputMode::Normal => Style::default(),
-                    InputMode::Editing => Style::default(),
+                    InputMode::Normal | InputMode::Confirm => Style::default(),
+                    InputMode::Editing | InputMode::Command => Style::default(),
                 })
-                .block(Block::default().borders(Borders::ALL).title(title))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(self.theme.border)
+                        .title(title),
+                )
         }
     }
 
-    fn user_input_flow<B: Backend>(&mut self, f: &mut Frame<B>, title: &str) {
-        let size = f.size();
-
+    fn user_input_flow<B: Backend>(&mut self, f: &mut Frame<B>, title: &str, size: Rect) {
         let block = Block::default().title(title);
 
         f.render_widget(block, size);
@@ -485,7 +1926,7 @@ impl App {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints(question_chunk_constraints)
-            .split(f.size());
+            .split(size);
 
         for (i, item) in items.iter().enumerate() {
             f.render_widget(item.to_owned(), chunks[i])
@@ -502,65 +1943,259 @@ impl App {
 
     /// Initially made for the BrowsePosts page but could be repurposed for any page with
     /// selectable items.
-    fn nav_list_for_blog_entries<B: Backend>(
-        &mut self,
-        f: &mut Frame<B>,
-        title: &str,
-        preview_items: Vec<Entry>,
-    ) {
-        let size = f.size();
-
+    fn nav_list_for_blog_entries<B: Backend>(&mut self, f: &mut Frame<B>, title: &str, size: Rect) {
         let block = Block::default();
         f.render_widget(block, size);
 
+        let panes = split_panes(&self.layout, size);
+        self.visible_panes = [Pane::Feeds, Pane::Entries, Pane::Preview]
+            .into_iter()
+            .filter(|pane| panes.is_visible(*pane))
+            .collect();
+        if !panes.is_visible(self.focused_pane) {
+            self.focused_pane = Pane::Entries;
+        }
+
+        if let Some(area) = panes.feeds {
+            self.render_feed_pane(f, area);
+        }
+
         let items: Vec<ListItem> = self
             .items
             .items
             .iter()
-            .map(|i| {
-                let mut lines = vec![];
-                lines.push(Spans::from(i.as_str()));
-                ListItem::new(lines).style(Style::default())
+            .zip(self.rows.iter())
+            .map(|(i, row)| {
+                let lines = vec![Spans::from(i.as_str())];
+                let entry = match row {
+                    Row::Header(_) => return ListItem::new(lines).style(self.theme.group_header),
+                    Row::Entry(entry) => entry,
+                };
+                let style = if entry.starred {
+                    self.theme.starred
+                } else if entry.read {
+                    self.theme.read
+                } else {
+                    self.theme.unread
+                };
+                ListItem::new(lines).style(style)
             })
             .collect();
 
         // Create a List from all list items and highlight the currently selected one
         let items = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title(title))
-            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-            .highlight_symbol("🍄 ");
-
-        let displayed_item: Entry = preview_items
-            .get(self.items.state.selected().unwrap())
-            .unwrap()
-            .clone();
+            .block(self.pane_block(title, Pane::Entries))
+            .highlight_style(self.theme.list_highlight)
+            .highlight_symbol(self.theme.highlight_symbol.as_str());
 
-        let paragraph = Paragraph::new(displayed_item.get_feed_content())
-            .block(Block::default().title("Content").borders(Borders::ALL))
-            .alignment(Alignment::Left)
-            .wrap(Wrap { trim: true });
-
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-            .split(f.size());
+        let displayed_item: Option<Entry> =
+            self.items
+                .state
+                .selected()
+                .and_then(|i| match self.rows.get(i) {
+                    Some(Row::Entry(entry)) => Some(*entry.clone()),
+                    _ => None,
+                });
+        let content = displayed_item
+            .as_ref()
+            .map(|entry| {
+                let mut content = entry.get_feed_content();
+                if !entry.also_in.is_empty() {
+                    content.push_str(&format!("\n\nAlso in: {}", entry.also_in.join(", ")));
+                }
+                if !entry.enclosures.is_empty() {
+                    content.push_str("\n\nAttachments:");
+                    for enclosure in entry.enclosures.iter() {
+                        content.push_str(&format!("\n📎 {}", enclosure));
+                    }
+                }
+                content
+            })
+            .unwrap_or_default();
 
-        f.render_stateful_widget(items, chunks[0], &mut self.items.state);
-        f.render_widget(paragraph, chunks[1]);
+        self.page_size = (panes.entries.height as usize / 2).max(1);
+        f.render_stateful_widget(items, panes.entries, &mut self.items.state);
+        if let Some(area) = panes.preview {
+            let paragraph = Paragraph::new(content.clone())
+                .block(self.pane_block("Content", Pane::Preview))
+                .alignment(Alignment::Left)
+                .wrap(Wrap { trim: true })
+                .scroll((self.preview_scroll, 0));
+            f.render_widget(paragraph, area);
+        }
         if self.show_popup {
-            let popup_block = Paragraph::new(displayed_item.get_feed_content())
-                .block(Block::default().title("Popup").borders(Borders::ALL))
+            let popup_block = Paragraph::new(content)
+                .block(
+                    Block::default()
+                        .title("Popup")
+                        .borders(Borders::ALL)
+                        .border_style(self.theme.border),
+                )
                 .wrap(Wrap { trim: true });
             let popup_area = self.centered_rect(60, 80, size);
             f.render_widget(Clear, popup_area); //this clears out the background
             f.render_widget(popup_block, popup_area);
         }
         if self.should_open_link {
-            let url = displayed_item.url;
-            open::that(url).unwrap();
+            if let Some(entry) = displayed_item {
+                let session = self.session.as_ref().unwrap();
+                let cached = session
+                    .feed_name_for_entry(&entry.url)
+                    .and_then(|feed_name| archive::cached_page(&feed_name, &entry.url));
+                match cached {
+                    Some(path) if self.offline => open::that(path).unwrap(),
+                    _ => open::that(entry.url.as_str()).unwrap(),
+                }
+                self.session
+                    .as_mut()
+                    .unwrap()
+                    .set_entry_read(&entry.url, true);
+                self.load_screen_items();
+            }
             self.should_open_link = false;
         }
     }
+
+    /// One progress bar per download, with the selected one highlighted.
+    fn render_downloads<B: Backend>(&mut self, f: &mut Frame<B>, title: &str, area: Rect) {
+        let downloads = self.downloads.snapshot();
+        self.items.items = downloads
+            .iter()
+            .map(|download| download.title.clone())
+            .collect();
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(self.theme.border);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        if downloads.is_empty() {
+            let hint =
+                Paragraph::new("Nothing queued. Use :download on an entry with attachments.");
+            f.render_widget(hint, inner);
+            return;
+        }
+        let rows = inner.height.max(1) as usize;
+        let selected = self.items.state.selected().unwrap_or(0);
+        let first = selected.saturating_sub(rows - 1);
+        for (row, (index, download)) in downloads
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .enumerate()
+        {
+            let state = match &download.status {
+                DownloadStatus::Queued => String::from("queued"),
+                DownloadStatus::Downloading => String::from("downloading"),
+                DownloadStatus::Paused => String::from("paused"),
+                DownloadStatus::Done => String::from("done"),
+                DownloadStatus::Failed(e) => format!("failed: {}", e),
+            };
+            let size = match download.total {
+                Some(total) => format!(
+                    "{}/{}",
+                    archive::format_size(download.downloaded),
+                    archive::format_size(total)
+                ),
+                None => archive::format_size(download.downloaded),
+            };
+            let symbol = if index == selected {
+                self.theme.highlight_symbol.as_str()
+            } else {
+                ""
+            };
+            let style = match download.status {
+                DownloadStatus::Failed(_) => self.theme.error,
+                _ if index == selected => self.theme.list_highlight,
+                _ => self.theme.unread,
+            };
+            let gauge = LineGauge::default()
+                .gauge_style(style)
+                .line_set(symbols::line::THICK)
+                .label(Span::styled(
+                    format!("{}{} {} ({}) ", symbol, download.title, size, state),
+                    style,
+                ))
+                .ratio(download.ratio());
+            let row_area = Rect::new(inner.x, inner.y + row as u16, inner.width, 1);
+            f.render_widget(gauge, row_area);
+        }
+    }
+
+    /// A bordered block for one of the BrowsePosts panes, drawn with the highlight
+    /// style when the pane has focus.
+    fn pane_block<'a>(&self, title: &'a str, pane: Pane) -> Block<'a> {
+        let border_style = if self.focused_pane == pane && self.visible_panes.len() > 1 {
+            self.theme.list_highlight
+        } else {
+            self.theme.border
+        };
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title(title)
+    }
+
+    /// The feed list of the three pane layout, with unread counts.
+    fn render_feed_pane<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let session = self.session.as_ref().unwrap();
+        let items: Vec<ListItem> = self
+            .feed_pane
+            .items
+            .iter()
+            .map(|item| {
+                let (label, unread) = match item {
+                    FeedPaneItem::AllFeeds => (String::from("All feeds"), self.unread.all),
+                    FeedPaneItem::Folder(path) => {
+                        let parts: Vec<&str> = path.split('/').collect();
+                        let label = format!(
+                            "{}▾ {}",
+                            "  ".repeat(parts.len() - 1),
+                            parts[parts.len() - 1]
+                        );
+                        (label, self.unread.folder(path))
+                    }
+                    FeedPaneItem::Feed(name) => {
+                        let depth = session
+                            .blog_feeds
+                            .iter()
+                            .find(|feed| &feed.name == name)
+                            .map_or(0, |feed| feed.folder_path().len());
+                        let mut label = format!("{}{}", "  ".repeat(depth), name);
+                        let dead = session.blog_feeds.iter().any(|feed| {
+                            &feed.name == name && feed.health.is_possibly_dead(&self.health)
+                        });
+                        if dead {
+                            label.push_str(" (dead?)");
+                        }
+                        (label, self.unread.feed(name))
+                    }
+                };
+                let failing = match item {
+                    FeedPaneItem::Feed(name) => session
+                        .blog_feeds
+                        .iter()
+                        .any(|feed| &feed.name == name && feed.last_error.is_some()),
+                    _ => false,
+                };
+                let style = if failing {
+                    self.theme.error
+                } else if unread > 0 {
+                    self.theme.unread
+                } else {
+                    self.theme.read
+                };
+                ListItem::new(Spans::from(format!("{} ({})", label, unread))).style(style)
+            })
+            .collect();
+        let list = List::new(items)
+            .block(self.pane_block("Feeds", Pane::Feeds))
+            .highlight_style(self.theme.list_highlight)
+            .highlight_symbol(self.theme.highlight_symbol.as_str());
+        f.render_stateful_widget(list, area, &mut self.feed_pane.state);
+    }
+
     /// helper function to create a centered rect using up certain percentage of the available rect `r`
     fn centered_rect(&self, percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         let popup_layout = Layout::default()
@@ -588,3 +2223,19 @@ impl App {
             .split(popup_layout[1])[1]
     }
 }
+
+/// Applies a cursor movement to any list.
+fn step_list<T>(list: &mut StatefulList<T>, action: Action, page_size: usize) {
+    if list.items.is_empty() {
+        return;
+    }
+    match action {
+        Action::Next => list.next(),
+        Action::Previous => list.previous(),
+        Action::First => list.first(),
+        Action::Last => list.last(),
+        Action::HalfPageDown => list.forward(page_size),
+        Action::HalfPageUp => list.backward(page_size),
+        _ => {}
+    }
+}
@@ -0,0 +1,81 @@
+use crate::config_and_keymap::keymap::{Action, Keymap};
+use crate::feeds_and_entry::archive::RetentionConfig;
+use crate::feeds_and_entry::downloads::DownloadConfig;
+use crate::feeds_and_entry::health::HealthConfig;
+use crate::feeds_and_entry::hooks::Hook;
+use crate::feeds_and_entry::http::HttpSettings;
+use crate::feeds_and_entry::read_later::ReadLaterConfig;
+use crate::feeds_and_entry::scripts::ScriptConfig;
+use crate::server::ServeConfig;
+use crate::session_and_user::digest::DigestConfig;
+use crate::session_and_user::sync::SyncConfig;
+use crate::ui::layout::LayoutConfig;
+use crate::ui::theme::{Theme, ThemeSpec};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const CONFIG_PATH: &str = ".config.json";
+
+/// User settings, read from `.config.json` next to the session file.
+/// Every field is optional in the file and falls back to its default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Maps an action name such as `half-page-down` to the keys that trigger it,
+    /// e.g. `["Ctrl-d", "J"]`. Configuring an action replaces all of its default keys.
+    pub keymap: HashMap<Action, Vec<String>>,
+    /// Name of a built-in theme (`dark`, `light`, `monochrome`) or one from `themes`.
+    pub theme: Option<String>,
+    pub themes: HashMap<String, ThemeSpec>,
+    /// Arrangement of the BrowsePosts screen: `vertical`, `horizontal` or `three-pane`.
+    pub layout: LayoutConfig,
+    /// How long the offline cache keeps pages, applied by `:cleanup`.
+    pub retention: RetentionConfig,
+    /// Where enclosures are downloaded to and which player opens them.
+    pub downloads: DownloadConfig,
+    /// Default user agent, timeout, proxy, CA bundle, headers and credentials for
+    /// fetching feeds. Each feed can override them in the session file.
+    pub http: HttpSettings,
+    /// When a feed that keeps failing is flagged as possibly dead.
+    pub health: HealthConfig,
+    /// Rewrite a feed's url as soon as it's found to have moved, rather than asking.
+    pub update_moved_feeds: bool,
+    /// The service entries are sent to with "send to read-later", if any.
+    pub read_later: Option<ReadLaterConfig>,
+    /// A server to sync subscriptions and read state with. Its feeds are then
+    /// refreshed through it rather than fetched one by one.
+    pub sync: Option<SyncConfig>,
+    /// Where `:digest` and `j-rss-tui digest` send unread entries as emails.
+    pub digest: Option<DigestConfig>,
+    /// Where `j-rss-tui serve` listens and who it lets in.
+    pub serve: ServeConfig,
+    /// Commands run on new entries, feed errors, finished refreshes and starred
+    /// entries.
+    pub hooks: Vec<Hook>,
+    /// Keys for the commands defined by the scripts in `.scripts`.
+    pub scripts: ScriptConfig,
+}
+
+#[allow(unused)]
+impl Config {
+    /// Loads the config file, falling back to the defaults if there isn't one.
+    pub fn load_from_json() -> Result<Config> {
+        let text = match std::fs::read_to_string(CONFIG_PATH) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut config = serde_json::from_str::<Config>(&text)?;
+        config.layout.clamp();
+        Ok(config)
+    }
+
+    pub fn build_keymap(&self) -> Result<Keymap> {
+        Keymap::new(&self.keymap)?.with_scripts(&self.scripts.keys)
+    }
+
+    pub fn build_theme(&self) -> Result<Theme> {
+        Theme::load(self.theme.as_deref().unwrap_or("dark"), &self.themes)
+    }
+}
@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Everything a key can be bound to in normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Next,
+    Previous,
+    First,
+    Last,
+    HalfPageDown,
+    HalfPageUp,
+    Back,
+    Forward,
+    FocusNextPane,
+    FocusPreviousPane,
+    GrowPane,
+    ShrinkPane,
+    Open,
+    TogglePopup,
+    Edit,
+    CommandLine,
+    Refresh,
+    ToggleStar,
+    ToggleMark,
+    ReadLater,
+    Save,
+    Help,
+    Quit,
+}
+
+impl Action {
+    pub fn all() -> Vec<Action> {
+        vec![
+            Action::Next,
+            Action::Previous,
+            Action::First,
+            Action::Last,
+            Action::HalfPageDown,
+            Action::HalfPageUp,
+            Action::Back,
+            Action::Forward,
+            Action::FocusNextPane,
+            Action::FocusPreviousPane,
+            Action::GrowPane,
+            Action::ShrinkPane,
+            Action::Open,
+            Action::TogglePopup,
+            Action::Edit,
+            Action::CommandLine,
+            Action::Refresh,
+            Action::ToggleStar,
+            Action::ToggleMark,
+            Action::ReadLater,
+            Action::Save,
+            Action::Help,
+            Action::Quit,
+        ]
+    }
+
+    pub fn default_keys(&self) -> Vec<&'static str> {
+        match self {
+            Action::Next => vec!["j", "Down"],
+            Action::Previous => vec!["k", "Up"],
+            Action::First => vec!["gg", "Home"],
+            Action::Last => vec!["G", "End"],
+            Action::HalfPageDown => vec!["Ctrl-d", "PageDown"],
+            Action::HalfPageUp => vec!["Ctrl-u", "PageUp"],
+            Action::Back => vec!["b", "Left"],
+            Action::Forward => vec!["f", "Right"],
+            Action::FocusNextPane => vec!["Tab"],
+            Action::FocusPreviousPane => vec!["BackTab"],
+            Action::GrowPane => vec![">"],
+            Action::ShrinkPane => vec!["<"],
+            Action::Open => vec!["Enter"],
+            Action::TogglePopup => vec!["p"],
+            Action::Edit => vec!["i"],
+            Action::CommandLine => vec![":"],
+            Action::Refresh => vec!["r"],
+            Action::ToggleStar => vec!["s"],
+            Action::ToggleMark => vec!["Space"],
+            Action::ReadLater => vec!["l"],
+            Action::Save => vec!["w"],
+            Action::Help => vec!["?"],
+            Action::Quit => vec!["q"],
+        }
+    }
+
+    /// The name used in the config, as serde spells it.
+    pub fn get_name(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => name,
+            _ => format!("{:?}", self),
+        }
+    }
+}
+
+/// A single key press, e.g. `j` or `Ctrl-d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord { code, modifiers }
+    }
+
+    /// Shift is already baked into the character for printable keys, so it's dropped
+    /// to let `G` in the config match the `Shift+g` the terminal reports.
+    pub fn from_event(key: KeyEvent) -> KeyChord {
+        let modifiers = match key.code {
+            KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+        KeyChord::new(key.code, modifiers)
+    }
+
+    fn parse_named(name: &str) -> Option<KeyCode> {
+        let code = match name.to_lowercase().as_str() {
+            "enter" | "return" | "cr" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" | "bs" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            other => {
+                let number = other.strip_prefix('f')?.parse::<u8>().ok()?;
+                KeyCode::F(number)
+            }
+        };
+        Some(code)
+    }
+
+    /// Parses one chord such as `x`, `Enter`, `Ctrl-d` or `Alt-Shift-Tab`.
+    fn parse(token: &str) -> Result<KeyChord> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = token;
+        while let Some((prefix, remainder)) = rest.split_once('-') {
+            if remainder.is_empty() {
+                break;
+            }
+            let modifier = match prefix.to_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "a" | "m" | "meta" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => break,
+            };
+            modifiers |= modifier;
+            rest = remainder;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => KeyChord::parse_named(rest).ok_or_else(|| anyhow!("unknown key {:?}", token))?,
+        };
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Ok(KeyChord::new(code, modifiers))
+    }
+
+    /// Parses a whitespace separated key sequence. Tokens that aren't a single chord,
+    /// like `gg`, are read one character at a time.
+    pub fn parse_sequence(spec: &str) -> Result<Vec<KeyChord>> {
+        let mut sequence = vec![];
+        for token in spec.split_whitespace() {
+            match KeyChord::parse(token) {
+                Ok(chord) => sequence.push(chord),
+                Err(e) => {
+                    if token.contains('-') || token.chars().any(|c| !c.is_ascii_graphic()) {
+                        return Err(e);
+                    }
+                    sequence.extend(
+                        token
+                            .chars()
+                            .map(|c| KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE)),
+                    );
+                }
+            }
+        }
+        if sequence.is_empty() {
+            return Err(anyhow!("empty key binding"));
+        }
+        Ok(sequence)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+pub enum KeyLookup {
+    Matched(Action),
+    /// A command from the scripts, by name.
+    Script(String),
+    Pending,
+    Unbound,
+}
+
+/// The active set of normal mode bindings.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyChord>, Action)>,
+    script_bindings: Vec<(Vec<KeyChord>, String)>,
+}
+
+impl Keymap {
+    /// Builds the keymap from the defaults, replacing the keys of any action the
+    /// user has configured.
+    pub fn new(overrides: &HashMap<Action, Vec<String>>) -> Result<Keymap> {
+        let mut bindings = vec![];
+        for action in Action::all() {
+            match overrides.get(&action) {
+                Some(specs) => {
+                    for spec in specs {
+                        let sequence = KeyChord::parse_sequence(spec)
+                            .map_err(|e| anyhow!("bad binding for {}: {}", action.get_name(), e))?;
+                        bindings.push((sequence, action));
+                    }
+                }
+                None => {
+                    for spec in action.default_keys() {
+                        bindings.push((KeyChord::parse_sequence(spec)?, action));
+                    }
+                }
+            }
+        }
+        Ok(Keymap {
+            bindings,
+            script_bindings: vec![],
+        })
+    }
+
+    /// Adds the keys bound to script commands. Action bindings win if both use
+    /// the same keys.
+    pub fn with_scripts(mut self, keys: &HashMap<String, Vec<String>>) -> Result<Keymap> {
+        for (command, specs) in keys {
+            for spec in specs {
+                let sequence = KeyChord::parse_sequence(spec)
+                    .map_err(|e| anyhow!("bad binding for script {}: {}", command, e))?;
+                self.script_bindings.push((sequence, command.clone()));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Looks up the keys pressed so far. An exact match wins over a longer binding
+    /// sharing the same prefix.
+    pub fn lookup(&self, pressed: &[KeyChord]) -> KeyLookup {
+        let mut pending = false;
+        for (sequence, action) in self.bindings.iter() {
+            if sequence.as_slice() == pressed {
+                return KeyLookup::Matched(*action);
+            }
+            if sequence.starts_with(pressed) {
+                pending = true;
+            }
+        }
+        for (sequence, command) in self.script_bindings.iter() {
+            if sequence.as_slice() == pressed {
+                return KeyLookup::Script(command.clone());
+            }
+            if sequence.starts_with(pressed) {
+                pending = true;
+            }
+        }
+        if pending {
+            KeyLookup::Pending
+        } else {
+            KeyLookup::Unbound
+        }
+    }
+
+    /// One line per action, listing every key sequence bound to it.
+    pub fn describe(&self) -> Vec<(String, String)> {
+        let describe_sequence = |sequence: &Vec<KeyChord>| {
+            sequence
+                .iter()
+                .map(|chord| chord.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        let mut lines: Vec<(String, String)> = Action::all()
+            .iter()
+            .filter_map(|action| {
+                let keys: Vec<String> = self
+                    .bindings
+                    .iter()
+                    .filter(|(_, bound)| bound == action)
+                    .map(|(sequence, _)| describe_sequence(sequence))
+                    .collect();
+                if keys.is_empty() {
+                    None
+                } else {
+                    Some((keys.join(", "), action.get_name()))
+                }
+            })
+            .collect();
+        for (sequence, command) in self.script_bindings.iter() {
+            lines.push((describe_sequence(sequence), format!("script {}", command)));
+        }
+        lines
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::new(&HashMap::new()).expect("default bindings are valid")
+    }
+}
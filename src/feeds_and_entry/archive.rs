@@ -0,0 +1,340 @@
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::feeds::BlogFeed;
+use crate::feeds_and_entry::http::HttpSettings;
+use crate::feeds_and_entry::readability;
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Cached pages live in `.cache/<feed>-<md5 of its name>/<md5 of the entry url>/index.html`,
+/// with their images alongside.
+pub const CACHE_DIR: &str = ".cache";
+
+/// Images bigger than this aren't worth carrying around.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// How long cached pages are kept, from the `retention` section of the config.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Pages cached longer ago than this are removed by cleanup.
+    pub max_age_days: Option<u64>,
+    /// Once the cache is bigger than this, the oldest pages go first.
+    pub max_size_mb: Option<u64>,
+    /// Starred entries are never cleaned up.
+    pub keep_starred: bool,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> RetentionConfig {
+        RetentionConfig {
+            max_age_days: Some(30),
+            max_size_mb: Some(500),
+            keep_starred: true,
+        }
+    }
+}
+
+/// The name is kept readable, and the hash tells apart feeds like "a.b" and "a b"
+/// that read the same once it's made safe for a path.
+fn feed_dir(feed_name: &str) -> PathBuf {
+    let safe: String = feed_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let hash = format!("{:x}", md5::compute(feed_name.as_bytes()));
+    Path::new(CACHE_DIR).join(format!("{}-{}", safe, &hash[..8]))
+}
+
+fn entry_key(url: &str) -> String {
+    format!("{:x}", md5::compute(url.as_bytes()))
+}
+
+fn entry_dir(feed_name: &str, url: &str) -> PathBuf {
+    feed_dir(feed_name).join(entry_key(url))
+}
+
+/// The cached page for an entry, if it has been archived.
+pub fn cached_page(feed_name: &str, url: &str) -> Option<PathBuf> {
+    let path = entry_dir(feed_name, url).join("index.html");
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// The article text of the cached page, for reading without a connection.
+pub fn cached_article(feed_name: &str, url: &str) -> Option<String> {
+    let html = std::fs::read_to_string(cached_page(feed_name, url)?).ok()?;
+    readability::extract_article(&html)
+}
+
+fn image_extension(url: &Url) -> &'static str {
+    let path = url.path().to_lowercase();
+    for extension in ["png", "jpg", "jpeg", "gif", "webp", "svg", "avif"] {
+        if path.ends_with(&format!(".{}", extension)) {
+            return extension;
+        }
+    }
+    "img"
+}
+
+/// Downloads the entry's page and the images it references, pointing the page at
+/// the local copies.
+/// The headers and credentials in `http` only go to pages on the feed's own host.
+async fn archive_entry(
+    feed_name: &str,
+    feed_url: &str,
+    entry: &Entry,
+    http: &HttpSettings,
+) -> Result<PathBuf> {
+    let page_url = Url::parse(&entry.url)?;
+    let mut html = http
+        .for_link(feed_url, &entry.url)
+        .fetch(&entry.url)
+        .await?
+        .response
+        .text()
+        .await?;
+    let image_urls: Vec<String> = {
+        let document = Html::parse_document(&html);
+        let images = Selector::parse("img[src]").unwrap();
+        let mut urls: Vec<String> = document
+            .select(&images)
+            .filter_map(|image| image.value().attr("src"))
+            .map(|src| src.to_string())
+            .collect();
+        urls.dedup();
+        urls
+    };
+    let dir = entry_dir(feed_name, &entry.url);
+    std::fs::create_dir_all(&dir)?;
+    for (index, src) in image_urls.iter().enumerate() {
+        let image_url = match page_url.join(src) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+        let image = http.for_link(feed_url, image_url.as_str());
+        let bytes = match image.fetch(image_url.as_str()).await {
+            Ok(fetched) if fetched.response.status().is_success() => {
+                match fetched.response.bytes().await {
+                    Ok(bytes) => bytes,
+                    Err(_) => continue,
+                }
+            }
+            _ => continue,
+        };
+        if bytes.len() > MAX_IMAGE_BYTES {
+            continue;
+        }
+        let file_name = format!("img-{}.{}", index, image_extension(&image_url));
+        std::fs::write(dir.join(&file_name), &bytes)?;
+        html = html.replace(
+            &format!("src=\"{}\"", src),
+            &format!("src=\"{}\"", file_name),
+        );
+    }
+    let path = dir.join("index.html");
+    std::fs::write(&path, html)?;
+    Ok(path)
+}
+
+/// Archives every visible entry of the feed that isn't cached yet, returning how
+/// many were added. Entries without content get the article text of their page
+/// so they can be read in the preview offline.
+pub async fn archive_feed(feed: &mut BlogFeed, http: &HttpSettings) -> usize {
+    let mut archived = 0;
+    let feed_name = feed.name.clone();
+    let feed_url = feed.url.clone();
+    let http = http.merged(&feed.http);
+    for entry in feed.entries.iter_mut().flatten() {
+        if entry.hidden || entry.url.is_empty() || cached_page(&feed_name, &entry.url).is_some() {
+            continue;
+        }
+        if archive_entry(&feed_name, &feed_url, entry, &http)
+            .await
+            .is_ok()
+        {
+            archived += 1;
+            if entry.content.is_none() {
+                entry.content = cached_article(&feed_name, &entry.url);
+                entry.full_content = entry.content.is_some();
+            }
+        }
+    }
+    archived
+}
+
+/// True if the error means the network couldn't be reached at all, as opposed to a
+/// feed misbehaving.
+pub fn is_offline_error(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Disk used by one feed's cached pages.
+pub struct FeedUsage {
+    pub feed_name: String,
+    pub pages: usize,
+    pub bytes: u64,
+}
+
+pub fn storage_usage(feeds: &[BlogFeed]) -> Vec<FeedUsage> {
+    feeds
+        .iter()
+        .map(|feed| {
+            let dir = feed_dir(&feed.name);
+            let pages = std::fs::read_dir(&dir)
+                .map(|entries| entries.filter_map(|entry| entry.ok()).count())
+                .unwrap_or(0);
+            FeedUsage {
+                feed_name: feed.name.clone(),
+                pages,
+                bytes: dir_size(&dir),
+            }
+        })
+        .collect()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+struct CachedPage {
+    dir: PathBuf,
+    cached_at: SystemTime,
+    bytes: u64,
+    starred: bool,
+}
+
+/// What a cleanup removed.
+pub struct CleanupReport {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+/// Removes cached pages whose entry or feed is gone, then pages older than the
+/// retention age, then the oldest pages until the cache fits the size limit.
+pub fn cleanup(feeds: &[BlogFeed], retention: &RetentionConfig) -> Result<CleanupReport> {
+    let mut report = CleanupReport {
+        removed: 0,
+        freed: 0,
+    };
+    let mut kept: Vec<CachedPage> = vec![];
+    for feed in feeds {
+        let entries: HashMap<String, &Entry> = feed
+            .entries
+            .iter()
+            .flatten()
+            .map(|entry| (entry_key(&entry.url), entry))
+            .collect();
+        let dir = match std::fs::read_dir(feed_dir(&feed.name)) {
+            Ok(dir) => dir,
+            Err(_) => continue,
+        };
+        for page in dir.filter_map(|page| page.ok()) {
+            let key = page.file_name().to_string_lossy().to_string();
+            let cached = CachedPage {
+                dir: page.path(),
+                cached_at: page.metadata()?.modified()?,
+                bytes: dir_size(&page.path()),
+                starred: entries.get(&key).is_some_and(|entry| entry.starred),
+            };
+            let protected = cached.starred && retention.keep_starred;
+            let expired = retention.max_age_days.is_some_and(|days| {
+                cached
+                    .cached_at
+                    .elapsed()
+                    .is_ok_and(|age| age > Duration::from_secs(days * 24 * 60 * 60))
+            });
+            if !entries.contains_key(&key) || (expired && !protected) {
+                remove_page(&cached, &mut report)?;
+            } else {
+                kept.push(cached);
+            }
+        }
+    }
+    // Folders of feeds that were renamed or unsubscribed.
+    let current: HashSet<PathBuf> = feeds.iter().map(|feed| feed_dir(&feed.name)).collect();
+    for folder in std::fs::read_dir(CACHE_DIR)
+        .into_iter()
+        .flatten()
+        .filter_map(|folder| folder.ok())
+    {
+        if !folder.path().is_dir() || current.contains(&folder.path()) {
+            continue;
+        }
+        for page in std::fs::read_dir(folder.path())?.filter_map(|page| page.ok()) {
+            let cached = CachedPage {
+                dir: page.path(),
+                cached_at: page.metadata()?.modified()?,
+                bytes: dir_size(&page.path()),
+                starred: false,
+            };
+            remove_page(&cached, &mut report)?;
+        }
+        let _ = std::fs::remove_dir(folder.path());
+    }
+    if let Some(max_size_mb) = retention.max_size_mb {
+        let limit = max_size_mb * 1024 * 1024;
+        let mut total: u64 = kept.iter().map(|page| page.bytes).sum();
+        kept.sort_by_key(|page| page.cached_at);
+        for page in kept.iter() {
+            if total <= limit {
+                break;
+            }
+            if page.starred && retention.keep_starred {
+                continue;
+            }
+            remove_page(page, &mut report)?;
+            total -= page.bytes;
+        }
+    }
+    Ok(report)
+}
+
+fn remove_page(page: &CachedPage, report: &mut CleanupReport) -> Result<()> {
+    std::fs::remove_dir_all(&page.dir)
+        .map_err(|e| anyhow!("couldn't remove {}: {}", page.dir.display(), e))?;
+    report.removed += 1;
+    report.freed += page.bytes;
+    Ok(())
+}
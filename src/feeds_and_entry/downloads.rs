@@ -0,0 +1,320 @@
+use crate::feeds_and_entry::http::HttpSettings;
+use anyhow::{anyhow, Result};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// The whole transfer has to fit in the request timeout, so files get far longer
+/// than feeds. One that runs out picks up where it left off when it's resumed.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Where enclosures go and how to play them, from the `downloads` section of the config.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct DownloadConfig {
+    pub directory: String,
+    /// How many files download at once; the rest wait in the queue.
+    pub max_concurrent: usize,
+    /// Command to play an enclosure with, e.g. `mpv --no-video`. A `{}` is replaced
+    /// by the file or url, otherwise it's added on the end.
+    pub player: Option<String>,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> DownloadConfig {
+        DownloadConfig {
+            directory: String::from("downloads"),
+            max_concurrent: 2,
+            player: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadStatus {
+    Queued,
+    Downloading,
+    Paused,
+    Done,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub title: String,
+    pub url: String,
+    pub path: PathBuf,
+    pub status: DownloadStatus,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    /// How the file is requested, from the settings of the feed it came from.
+    http: HttpSettings,
+    /// Bumped every time the download is (re)started, so a worker left over from a
+    /// pause that was quickly undone knows to stop.
+    attempt: u64,
+}
+
+impl Download {
+    /// How far along the download is, between 0 and 1.
+    pub fn ratio(&self) -> f64 {
+        match (self.status.clone(), self.total) {
+            (DownloadStatus::Done, _) => 1.0,
+            (_, Some(total)) if total > 0 => (self.downloaded as f64 / total as f64).min(1.0),
+            _ => 0.0,
+        }
+    }
+
+    fn part_path(&self) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(".part");
+        PathBuf::from(name)
+    }
+}
+
+/// Downloads enclosures in the background. Clones share the same queue, so the
+/// screen can read progress while the workers update it.
+#[derive(Clone)]
+pub struct DownloadManager {
+    config: DownloadConfig,
+    downloads: Arc<Mutex<Vec<Download>>>,
+    slots: Arc<Semaphore>,
+}
+
+impl DownloadManager {
+    pub fn new(config: &DownloadConfig) -> DownloadManager {
+        DownloadManager {
+            config: config.clone(),
+            downloads: Arc::new(Mutex::new(vec![])),
+            slots: Arc::new(Semaphore::new(config.max_concurrent.max(1))),
+        }
+    }
+
+    /// The file an enclosure url is saved to. The name starts with a hash of the
+    /// url, since podcasts love calling every episode `episode.mp3`.
+    fn path_for(&self, url: &str) -> PathBuf {
+        let hash = format!("{:x}", md5::compute(url.as_bytes()));
+        let name = reqwest::Url::parse(url).ok().and_then(|url| {
+            url.path_segments()?
+                .rfind(|segment| !segment.is_empty())
+                .map(|segment| segment.to_string())
+        });
+        let name: String = match name {
+            Some(name) => format!("{}-{}", &hash[..8], name),
+            None => hash,
+        }
+        .chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+        Path::new(&self.config.directory).join(name)
+    }
+
+    /// Adds the url to the queue unless it's already there, and starts it as soon as
+    /// a slot is free. `http` is what `link_settings` gives for the url.
+    pub fn queue(&self, title: &str, url: &str, http: HttpSettings) -> Result<()> {
+        let index = {
+            let mut downloads = self.downloads.lock().unwrap();
+            if downloads.iter().any(|download| download.url == url) {
+                return Ok(());
+            }
+            downloads.push(Download {
+                title: title.to_string(),
+                url: url.to_string(),
+                path: self.path_for(url),
+                status: DownloadStatus::Queued,
+                downloaded: 0,
+                total: None,
+                http,
+                attempt: 0,
+            });
+            downloads.len() - 1
+        };
+        self.start(index)
+    }
+
+    fn start(&self, index: usize) -> Result<()> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|_| anyhow!("downloads need the async runtime"))?;
+        let mut attempt = 0;
+        self.update(index, |download| {
+            download.attempt += 1;
+            attempt = download.attempt;
+        });
+        let manager = self.clone();
+        runtime.spawn(async move {
+            let _slot = manager.slots.acquire().await;
+            if !manager.is_current(index, attempt, DownloadStatus::Queued) {
+                return;
+            }
+            if let Err(e) = manager.run(index, attempt).await {
+                manager.update(index, |download| {
+                    download.status = DownloadStatus::Failed(e.to_string())
+                });
+            }
+        });
+        Ok(())
+    }
+
+    fn is_current(&self, index: usize, attempt: u64, status: DownloadStatus) -> bool {
+        let downloads = self.downloads.lock().unwrap();
+        downloads
+            .get(index)
+            .is_some_and(|download| download.attempt == attempt && download.status == status)
+    }
+
+    fn update(&self, index: usize, change: impl FnOnce(&mut Download)) {
+        if let Some(download) = self.downloads.lock().unwrap().get_mut(index) {
+            change(download);
+        }
+    }
+
+    /// Fetches the file into a `.part` file, picking up where an earlier attempt
+    /// left off if the server supports ranges.
+    async fn run(&self, index: usize, attempt: u64) -> Result<()> {
+        let download = self.downloads.lock().unwrap()[index].clone();
+        let part_path = download.part_path();
+        if let Some(parent) = part_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let existing = std::fs::metadata(&part_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        let range = (existing > 0).then(|| format!("bytes={}-", existing));
+        let adjust = |request: RequestBuilder| {
+            let request = request.timeout(DOWNLOAD_TIMEOUT);
+            match &range {
+                Some(range) => request.header(RANGE, range),
+                None => request,
+            }
+        };
+        let mut response = match download.http.fetch_with(&download.url, &adjust).await {
+            Ok(fetched) => fetched.response,
+            Err(e) if existing > 0 && is_range_not_satisfiable(&e) => {
+                // The part file already holds the whole thing.
+                std::fs::rename(&part_path, &download.path)?;
+                self.update(index, |download| {
+                    download.status = DownloadStatus::Done;
+                    download.downloaded = existing;
+                    download.total = Some(existing);
+                });
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+        let total = if resumed {
+            response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|range| range.to_str().ok())
+                .and_then(|range| range.rsplit('/').next())
+                .and_then(|total| total.parse::<u64>().ok())
+                .or(response.content_length().map(|length| length + existing))
+        } else {
+            response.content_length()
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&part_path)?;
+        let mut downloaded = if resumed { existing } else { 0 };
+        self.update(index, |download| {
+            download.status = DownloadStatus::Downloading;
+            download.downloaded = downloaded;
+            download.total = total;
+        });
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk)?;
+            downloaded += chunk.len() as u64;
+            if !self.is_current(index, attempt, DownloadStatus::Downloading) {
+                return Ok(());
+            }
+            self.update(index, |download| download.downloaded = downloaded);
+        }
+        file.flush()?;
+        std::fs::rename(&part_path, &download.path)?;
+        self.update(index, |download| download.status = DownloadStatus::Done);
+        Ok(())
+    }
+
+    /// Pauses a running or queued download, or resumes a paused or failed one.
+    pub fn toggle_pause(&self, index: usize) -> Result<()> {
+        let restart = {
+            let mut downloads = self.downloads.lock().unwrap();
+            let download = downloads
+                .get_mut(index)
+                .ok_or_else(|| anyhow!("no download {}", index))?;
+            match download.status {
+                DownloadStatus::Queued | DownloadStatus::Downloading => {
+                    download.status = DownloadStatus::Paused;
+                    false
+                }
+                DownloadStatus::Paused | DownloadStatus::Failed(_) => {
+                    download.status = DownloadStatus::Queued;
+                    true
+                }
+                DownloadStatus::Done => false,
+            }
+        };
+        if restart {
+            self.start(index)?;
+        }
+        Ok(())
+    }
+
+    /// A copy of the queue to draw from.
+    pub fn snapshot(&self) -> Vec<Download> {
+        self.downloads.lock().unwrap().clone()
+    }
+
+    /// The downloaded file for a url, if it's finished.
+    pub fn downloaded_path(&self, url: &str) -> Option<PathBuf> {
+        let path = self.path_for(url);
+        if path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Starts the configured player on a file or url without waiting for it.
+    pub fn play(&self, target: &str) -> Result<()> {
+        let player = self
+            .config
+            .player
+            .as_deref()
+            .ok_or_else(|| anyhow!("set downloads.player in the config to play enclosures"))?;
+        let mut words: Vec<String> = player.split_whitespace().map(String::from).collect();
+        if words.iter().any(|word| word.contains("{}")) {
+            for word in words.iter_mut() {
+                *word = word.replace("{}", target);
+            }
+        } else {
+            words.push(target.to_string());
+        }
+        let (program, args) = words
+            .split_first()
+            .ok_or_else(|| anyhow!("the player command is empty"))?;
+        std::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("couldn't start {}: {}", program, e))?;
+        Ok(())
+    }
+}
+
+fn is_range_not_satisfiable(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .and_then(|e| e.status())
+        == Some(StatusCode::RANGE_NOT_SATISFIABLE)
+}
@@ -0,0 +1,96 @@
+use crate::feeds_and_entry::entry::Entry;
+use reqwest::Url;
+use std::collections::HashMap;
+
+/// Query parameters that only say where a link was clicked, not what it points at.
+const TRACKING_PARAMETERS: [&str; 7] = [
+    "fbclid", "gclid", "mc_cid", "mc_eid", "ref", "ref_src", "igshid",
+];
+
+/// Titles shorter than this are too generic ("Weekly links", "New release") to call
+/// two entries the same article.
+const MIN_TITLE_WORDS: usize = 4;
+
+/// The entry url without its scheme, `www.`, fragment, tracking parameters or
+/// trailing slash, so mirrors of the same link compare equal.
+pub fn canonical_url(url: &str) -> String {
+    let parsed = match Url::parse(url.trim()) {
+        Ok(parsed) => parsed,
+        Err(_) => return url.trim().to_lowercase(),
+    };
+    let host = parsed.host_str().unwrap_or("").to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let query: Vec<String> = parsed
+        .query_pairs()
+        .filter(|(name, _)| {
+            let name = name.to_lowercase();
+            !name.starts_with("utm_") && !TRACKING_PARAMETERS.contains(&name.as_str())
+        })
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let path = parsed.path().trim_end_matches('/');
+    if query.is_empty() {
+        format!("{}{}", host, path)
+    } else {
+        format!("{}{}?{}", host, path, query.join("&"))
+    }
+}
+
+/// The title's words in lower case without punctuation, so titles that only differ
+/// in case, quotes or spacing compare equal. None if it's too short to trust.
+pub fn title_key(title: &str) -> Option<String> {
+    let words: Vec<String> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    if words.len() < MIN_TITLE_WORDS {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+/// Groups the entries that are copies of each other, by canonical url or by title.
+/// Each group holds indexes into `entries` in their original order, and every entry
+/// is in exactly one group.
+pub fn group(entries: &[&Entry]) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..entries.len()).collect();
+    fn root(parents: &mut [usize], index: usize) -> usize {
+        let mut index = index;
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+    let mut by_url: HashMap<String, usize> = HashMap::new();
+    let mut by_title: HashMap<String, usize> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let mut matches = vec![];
+        if !entry.url.is_empty() {
+            matches.push(*by_url.entry(canonical_url(&entry.url)).or_insert(index));
+        }
+        if let Some(key) = title_key(&entry.title) {
+            matches.push(*by_title.entry(key).or_insert(index));
+        }
+        for other in matches {
+            let (a, b) = (root(&mut parents, index), root(&mut parents, other));
+            // The earlier entry stays the root so it's the one shown.
+            parents[a.max(b)] = a.min(b);
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = vec![];
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    for index in 0..entries.len() {
+        let root = root(&mut parents, index);
+        match group_of_root.get(&root) {
+            Some(group) => groups[*group].push(index),
+            None => {
+                group_of_root.insert(root, groups.len());
+                groups.push(vec![index]);
+            }
+        }
+    }
+    groups
+}
@@ -1,16 +1,94 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// A file attached to an entry, like a podcast episode.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime_type: Option<String>,
+    /// Size in bytes, as advertised by the feed.
+    pub length: Option<u64>,
+    /// Running time in seconds.
+    pub duration: Option<u64>,
+}
+
+impl fmt::Display for Enclosure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut details: Vec<String> = vec![];
+        if let Some(mime_type) = &self.mime_type {
+            details.push(mime_type.to_string());
+        }
+        if let Some(length) = self.length {
+            details.push(format!("{:.1} MB", length as f64 / (1024.0 * 1024.0)));
+        }
+        if let Some(duration) = self.duration {
+            details.push(format!(
+                "{}:{:02}:{:02}",
+                duration / 3600,
+                duration / 60 % 60,
+                duration % 60
+            ));
+        }
+        if details.is_empty() {
+            write!(f, "{}", self.url)
+        } else {
+            write!(f, "{} ({})", self.url, details.join(", "))
+        }
+    }
+}
+
+/// An entry's id on the sync server and the state both sides agreed on at the last
+/// sync, so a change can be told apart from the other side's.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SyncedState {
+    pub id: String,
+    pub read: bool,
+    pub starred: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Entry {
+    /// A number for the API server to refer to the entry by, given out in the order
+    /// entries arrive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
     pub title: String,
     pub authors: String,
     pub blurb: String,
     pub url: String,
     pub content: Option<String>,
+    /// The content is the article fetched from the entry's page, not what the feed
+    /// sent.
+    #[serde(default)]
+    pub full_content: bool,
     // updated_at: Option<DateTime<Utc>>,
     pub updated_at: Option<String>,
+    #[serde(default)]
+    pub read: bool,
+    #[serde(default)]
+    pub starred: bool,
+    /// Hidden by a filter rule, so left out of every list.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Already sent out in a digest.
+    #[serde(default)]
+    pub digested: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub enclosures: Vec<Enclosure>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync: Option<SyncedState>,
+    /// The feed the entry was listed from, filled in with `also_in`.
+    #[serde(skip)]
+    pub feed_name: String,
+    /// The other feeds carrying a copy of this entry, filled in when entries from
+    /// several feeds are listed together.
+    #[serde(skip)]
+    pub also_in: Vec<String>,
 }
 
 #[allow(unused)]
@@ -23,20 +101,49 @@ impl Entry {
         content: Option<String>,
         updated_at: Option<DateTime<Utc>>,
     ) -> Entry {
-        let updated_at_str: String;
-        match updated_at {
-            Some(x) => updated_at_str = x.to_string(),
-            None => updated_at_str = String::from(""),
-        }
+        let updated_at_str = match updated_at {
+            Some(x) => x.to_string(),
+            None => String::from(""),
+        };
         Entry {
+            id: None,
             title: String::from(title),
             authors: String::from(authors),
             blurb: String::from(blurb),
             url: String::from(url),
             content,
+            full_content: false,
             updated_at: Some(updated_at_str),
+            read: false,
+            starred: false,
+            hidden: false,
+            digested: false,
+            tags: vec![],
+            categories: vec![],
+            enclosures: vec![],
+            sync: None,
+            feed_name: String::new(),
+            also_in: vec![],
         }
     }
+    /// True if the title, authors or blurb contain the query, ignoring case.
+    pub fn matches_query(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.title.to_lowercase().contains(&query)
+            || self.authors.to_lowercase().contains(&query)
+            || self.blurb.to_lowercase().contains(&query)
+    }
+
+    /// When the entry was published or last updated, if the feed said.
+    pub fn updated(&self) -> Option<DateTime<Utc>> {
+        let text = self.updated_at.as_deref()?.trim_matches('"');
+        // Stored as `DateTime<Utc>` prints it, e.g. `2023-04-01 09:30:00 UTC`.
+        NaiveDateTime::parse_from_str(text.trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S%.f")
+            .map(|date| DateTime::<Utc>::from_utc(date, Utc))
+            .or_else(|_| DateTime::parse_from_rfc3339(text).map(|date| date.with_timezone(&Utc)))
+            .ok()
+    }
+
     pub fn get_feed_content(&self) -> String {
         match &self.content {
             Some(x) => x.to_string(),
@@ -0,0 +1,404 @@
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::opml::escape_xml;
+use crate::feeds_and_entry::readability;
+use crate::feeds_and_entry::source::expand_home;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use scraper::{ElementRef, Html, Node};
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Kept with their text, minus any attribute not in `KEPT_ATTRIBUTES`. Other
+/// tags are dropped but their text is kept.
+const KEPT_TAGS: &str = "p br hr h1 h2 h3 h4 h5 h6 a img ul ol li blockquote pre code em strong \
+b i u s sub sup table thead tbody tr th td figure figcaption div span dl dt dd small mark";
+/// Dropped along with everything inside them.
+const DROPPED_TAGS: [&str; 10] = [
+    "script", "style", "iframe", "object", "embed", "form", "noscript", "svg", "video", "audio",
+];
+const KEPT_ATTRIBUTES: [&str; 6] = ["href", "src", "alt", "title", "colspan", "rowspan"];
+const VOID_TAGS: [&str; 3] = ["br", "hr", "img"];
+
+pub(crate) const STYLE: &str = "body { max-width: 42em; margin: 2em auto; padding: 0 1em; \
+font-family: Georgia, serif; line-height: 1.5; }
+article { margin-bottom: 3em; }
+.meta { color: #666; font-size: 0.9em; }
+img { max-width: 100%; height: auto; }
+pre { overflow-x: auto; }
+";
+
+/// What entries can be exported as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Epub,
+}
+
+impl ExportFormat {
+    pub const NAMES: [&'static str; 3] = ["markdown", "html", "epub"];
+
+    pub fn from_name(name: &str) -> Result<ExportFormat> {
+        match name {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "epub" => Ok(ExportFormat::Epub),
+            other => Err(anyhow!(
+                "can't export as {:?}, try {}",
+                other,
+                ExportFormat::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Writes the entries to `path` in the format, under the title. The language is
+/// the feeds' own, for the formats that record one.
+pub fn export(
+    path: &str,
+    format: ExportFormat,
+    title: &str,
+    language: Option<&str>,
+    entries: &[Entry],
+) -> Result<()> {
+    if entries.is_empty() {
+        return Err(anyhow!("nothing to export"));
+    }
+    let path = expand_home(path)?;
+    match format {
+        ExportFormat::Markdown => std::fs::write(path, to_markdown(title, entries))?,
+        ExportFormat::Html => std::fs::write(path, to_html(title, entries))?,
+        ExportFormat::Epub => {
+            std::fs::write(path, to_epub(title, language.unwrap_or("en"), entries)?)?
+        }
+    }
+    Ok(())
+}
+
+/// The date and authors line shown under each entry's title.
+fn byline(entry: &Entry) -> String {
+    let mut parts = vec![];
+    if !entry.feed_name.is_empty() {
+        parts.push(entry.feed_name.clone());
+    }
+    if !entry.authors.is_empty() {
+        parts.push(entry.authors.clone());
+    }
+    if let Some(date) = entry.updated() {
+        parts.push(date.format("%Y-%m-%d %H:%M").to_string());
+    }
+    parts.join(" · ")
+}
+
+fn is_html(text: &str) -> bool {
+    text.contains('<') && text.contains('>')
+}
+
+/// The entry's full content if it has been fetched, otherwise its summary.
+fn body(entry: &Entry) -> &str {
+    entry
+        .content
+        .as_deref()
+        .filter(|content| !content.trim().is_empty())
+        .unwrap_or(&entry.blurb)
+}
+
+/// Markdown with a heading per entry, linking to it.
+pub fn to_markdown(title: &str, entries: &[Entry]) -> String {
+    let mut resp = format!("# {}\n\n", title);
+    resp.push_str(&format!(
+        "Exported {} with {} entries.\n\n",
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        entries.len()
+    ));
+    for entry in entries {
+        resp.push_str(&entry_markdown(entry));
+    }
+    resp
+}
+
+/// One entry as a linked heading, its byline, tags and content as text.
+pub fn entry_markdown(entry: &Entry) -> String {
+    let title = entry.title.trim().replace('[', "\\[").replace(']', "\\]");
+    let mut resp = format!("## [{}]({})\n\n", title, entry.url);
+    let byline = byline(entry);
+    if !byline.is_empty() {
+        resp.push_str(&format!("*{}*\n\n", byline));
+    }
+    if !entry.tags.is_empty() {
+        resp.push_str(&format!("Tags: {}\n\n", entry.tags.join(", ")));
+    }
+    let text = body(entry);
+    let text = if is_html(text) {
+        readability::html_to_text(text)
+    } else {
+        text.to_string()
+    };
+    if !text.trim().is_empty() {
+        resp.push_str(text.trim());
+        resp.push_str("\n\n");
+    }
+    resp.push_str("---\n\n");
+    resp
+}
+
+/// Re-serializes the node's children as well formed XHTML, keeping only safe
+/// tags and attributes. With `images` off, images become their alt text.
+fn write_clean(element: ElementRef, images: bool, out: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => out.push_str(&escape_xml(text)),
+            Node::Element(tag) => {
+                let name = tag.name();
+                let child = match ElementRef::wrap(child) {
+                    Some(child) => child,
+                    None => continue,
+                };
+                if DROPPED_TAGS.contains(&name) {
+                    continue;
+                }
+                if name == "img" && !images {
+                    if let Some(alt) = tag.attr("alt").filter(|alt| !alt.trim().is_empty()) {
+                        out.push_str(&format!("[{}]", escape_xml(alt)));
+                    }
+                    continue;
+                }
+                if !KEPT_TAGS.split_whitespace().any(|kept| kept == name) {
+                    write_clean(child, images, out);
+                    continue;
+                }
+                out.push('<');
+                out.push_str(name);
+                for (attribute, value) in tag.attrs() {
+                    let unsafe_link = value.trim().to_lowercase().starts_with("javascript:");
+                    if KEPT_ATTRIBUTES.contains(&attribute) && !unsafe_link {
+                        out.push_str(&format!(" {}=\"{}\"", attribute, escape_xml(value)));
+                    }
+                }
+                if VOID_TAGS.contains(&name) {
+                    out.push_str("/>");
+                } else {
+                    out.push('>');
+                    write_clean(child, images, out);
+                    out.push_str(&format!("</{}>", name));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The entry's content as an XHTML fragment safe to put in a page of its own.
+/// Plain text is split into paragraphs at blank lines.
+pub fn content_html(entry: &Entry, images: bool) -> String {
+    let text = body(entry);
+    if is_html(text) {
+        let fragment = Html::parse_fragment(text);
+        let mut out = String::new();
+        write_clean(fragment.root_element(), images, &mut out);
+        out
+    } else {
+        text.split("\n\n")
+            .map(str::trim)
+            .filter(|paragraph| !paragraph.is_empty())
+            .map(|paragraph| format!("<p>{}</p>\n", escape_xml(paragraph)))
+            .collect()
+    }
+}
+
+/// One entry as an `<article>`: its linked title, byline, tags and content.
+pub fn article_html(entry: &Entry, id: &str, images: bool) -> String {
+    let mut resp = format!("<article id=\"{}\">\n", id);
+    resp.push_str(&format!(
+        "<h2><a href=\"{}\">{}</a></h2>\n",
+        escape_xml(&entry.url),
+        escape_xml(entry.title.trim())
+    ));
+    let byline = byline(entry);
+    if !byline.is_empty() {
+        resp.push_str(&format!("<p class=\"meta\">{}</p>\n", escape_xml(&byline)));
+    }
+    if !entry.tags.is_empty() {
+        resp.push_str(&format!(
+            "<p class=\"meta\">Tags: {}</p>\n",
+            escape_xml(&entry.tags.join(", "))
+        ));
+    }
+    resp.push_str(&content_html(entry, images));
+    resp.push_str(&format!(
+        "\n<p class=\"meta\"><a href=\"{}\">Read the original</a></p>\n",
+        escape_xml(&entry.url)
+    ));
+    resp.push_str("</article>\n");
+    resp
+}
+
+/// A page that works on its own: styles inline, a contents list at the top.
+pub fn to_html(title: &str, entries: &[Entry]) -> String {
+    let mut resp = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\"/>\n");
+    resp.push_str(&format!("<title>{}</title>\n", escape_xml(title)));
+    resp.push_str(&format!("<style>\n{}</style>\n</head>\n<body>\n", STYLE));
+    resp.push_str(&format!("<h1>{}</h1>\n", escape_xml(title)));
+    resp.push_str(&format!(
+        "<p class=\"meta\">Exported {} with {} entries.</p>\n<ol>\n",
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        entries.len()
+    ));
+    for (index, entry) in entries.iter().enumerate() {
+        resp.push_str(&format!(
+            "<li><a href=\"#entry-{}\">{}</a></li>\n",
+            index + 1,
+            escape_xml(entry.title.trim())
+        ));
+    }
+    resp.push_str("</ol>\n");
+    for (index, entry) in entries.iter().enumerate() {
+        resp.push_str(&article_html(entry, &format!("entry-{}", index + 1), true));
+    }
+    resp.push_str("</body>\n</html>\n");
+    resp
+}
+
+/// A urn:uuid made from the title and time, since nothing else needs a uuid.
+fn book_id(title: &str) -> String {
+    let digest = format!(
+        "{:x}",
+        md5::compute(format!("{}{}", title, Utc::now().to_rfc3339()))
+    );
+    format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &digest[0..8],
+        &digest[8..12],
+        &digest[12..16],
+        &digest[16..20],
+        &digest[20..32]
+    )
+}
+
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+<head>\n<meta charset=\"utf-8\"/>\n<title>{}</title>\n\
+<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_xml(title),
+        body
+    )
+}
+
+/// An EPUB 3 book with a chapter per entry. It also carries an NCX table of
+/// contents for older readers. Images are left out, since they'd be fetched
+/// from the web and most e-readers won't.
+pub fn to_epub(title: &str, language: &str, entries: &[Entry]) -> Result<Vec<u8>> {
+    let id = book_id(title);
+    let modified = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // Readers look for the mimetype first, uncompressed.
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(
+        b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+  <rootfiles>\n\
+    <rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n\
+  </rootfiles>\n\
+</container>\n",
+    )?;
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(STYLE.as_bytes())?;
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut nav = String::new();
+    let mut nav_points = String::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let number = index + 1;
+        let file = format!("entry-{}.xhtml", number);
+        zip.start_file(format!("OEBPS/{}", file), deflated)?;
+        zip.write_all(
+            xhtml_page(
+                &entry.title,
+                &article_html(entry, &format!("entry-{}", number), false),
+            )
+            .as_bytes(),
+        )?;
+        manifest.push_str(&format!(
+            "    <item id=\"entry-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            number, file
+        ));
+        spine.push_str(&format!("    <itemref idref=\"entry-{}\"/>\n", number));
+        nav.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            file,
+            escape_xml(entry.title.trim())
+        ));
+        nav_points.push_str(&format!(
+            "    <navPoint id=\"nav-{0}\" playOrder=\"{0}\">\n      <navLabel><text>{1}</text></navLabel>\n      <content src=\"{2}\"/>\n    </navPoint>\n",
+            number,
+            escape_xml(entry.title.trim()),
+            file
+        ));
+    }
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(
+        xhtml_page(
+            title,
+            &format!(
+                "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{}</ol>\n</nav>\n",
+                escape_xml(title),
+                nav
+            ),
+        )
+        .as_bytes(),
+    )?;
+    zip.start_file("OEBPS/toc.ncx", deflated)?;
+    zip.write_all(
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n\
+  <head><meta name=\"dtb:uid\" content=\"{}\"/></head>\n\
+  <docTitle><text>{}</text></docTitle>\n\
+  <navMap>\n{}  </navMap>\n\
+</ncx>\n",
+            id,
+            escape_xml(title),
+            nav_points
+        )
+        .as_bytes(),
+    )?;
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n\
+  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+    <dc:identifier id=\"book-id\">{}</dc:identifier>\n\
+    <dc:title>{}</dc:title>\n\
+    <dc:language>{}</dc:language>\n\
+    <dc:creator>j-rss-tui</dc:creator>\n\
+    <meta property=\"dcterms:modified\">{}</meta>\n\
+  </metadata>\n\
+  <manifest>\n\
+    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+    <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n\
+    <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n\
+{}  </manifest>\n\
+  <spine toc=\"ncx\">\n{}  </spine>\n\
+</package>\n",
+            id,
+            escape_xml(title),
+            escape_xml(language),
+            modified,
+            manifest,
+            spine
+        )
+        .as_bytes(),
+    )?;
+    Ok(zip.finish()?.into_inner())
+}
@@ -1,15 +1,62 @@
 use chrono::{DateTime, Utc};
 use feed_rs::{self, model::{Person, Content}};
 use std::fmt;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::entry::{Enclosure, Entry};
+use crate::feeds_and_entry::archive;
+use crate::feeds_and_entry::health::FeedHealth;
+use crate::feeds_and_entry::http::{Gone, HttpSettings};
+use crate::feeds_and_entry::json_feed;
+use crate::feeds_and_entry::metadata::FeedMetadata;
+use crate::feeds_and_entry::source::FeedSource;
+use crate::feeds_and_entry::readability;
+use crate::feeds_and_entry::rules::RuleSet;
+
+/// Feeds that ask to be polled less often than this are still refreshed this often.
+const MAX_HINTED_INTERVAL_MINUTES: u32 = 24 * 60;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct BlogFeed {
     pub name: String,
     pub url: String,
     pub entries: Option<Vec<Entry>>,
+    /// Folder path such as `Rust/Async`, or none for a top level feed.
+    #[serde(default)]
+    pub folder: Option<String>,
+    /// Download each entry's page and keep the article text, for feeds that only
+    /// publish a summary.
+    #[serde(default)]
+    pub fetch_full_content: bool,
+    /// Keep a copy of each entry's page and images to read offline.
+    #[serde(default)]
+    pub cache_offline: bool,
+    /// Why the last refresh failed, cleared once one succeeds.
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub health: FeedHealth,
+    #[serde(default)]
+    pub metadata: FeedMetadata,
+    /// A new url the feed has permanently moved to, found by the last refresh and
+    /// waiting to be accepted or declined.
+    #[serde(skip)]
+    pub moved_to: Option<String>,
+    /// A move that was declined, so it isn't suggested again.
+    #[serde(default)]
+    pub declined_move: Option<String>,
+    /// The server said the feed is gone for good on the last refresh.
+    #[serde(skip)]
+    pub gone: bool,
+    /// Headers, credentials and so on for this feed, over the config's `http` section.
+    #[serde(default, skip_serializing_if = "HttpSettings::is_empty")]
+    pub http: HttpSettings,
+    /// The subscription's id on the sync server, for feeds that come from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_id: Option<String>,
+    /// Urls of the entries the last refresh found that weren't there before.
+    #[serde(skip)]
+    pub fresh: Vec<String>,
 }
 
 #[allow(unused)]
@@ -19,17 +66,114 @@ impl BlogFeed {
             name: String::from(name),
             url: String::from(url),
             entries: None,
+            folder: None,
+            fetch_full_content: false,
+            cache_offline: false,
+            last_error: None,
+            health: FeedHealth::default(),
+            metadata: FeedMetadata::default(),
+            moved_to: None,
+            declined_move: None,
+            gone: false,
+            http: HttpSettings::default(),
+            sync_id: None,
+            fresh: vec![],
+        }
+    }
+
+    /// The folder split into its parts, empty for a top level feed.
+    pub fn folder_path(&self) -> Vec<&str> {
+        match &self.folder {
+            Some(folder) => folder.split('/').filter(|part| !part.is_empty()).collect(),
+            None => vec![],
+        }
+    }
+
+    /// True if the feed is in the folder at `path` or any folder beneath it.
+    pub fn is_in_folder(&self, path: &str) -> bool {
+        let wanted: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        self.folder_path().starts_with(&wanted)
+    }
+
+    /// Fetches the feed from wherever its url points and rebuilds the entries,
+    /// keeping any failure in `last_error`. `http` holds the global settings, which
+    /// the feed's own settings override.
+    pub async fn refresh(&mut self, rules: &RuleSet, http: &HttpSettings) -> Result<()> {
+        let started = std::time::Instant::now();
+        let result = self.fetch_and_parse(rules, http).await;
+        self.gone = false;
+        match &result {
+            Ok(_) => self.health.record_success(started.elapsed()),
+            Err(e) => {
+                self.gone = e.downcast_ref::<Gone>().is_some();
+                self.health.record_failure();
+            }
         }
+        self.last_error = result.as_ref().err().map(|e| e.to_string());
+        result
     }
 
-    pub async fn get_rss_feed(&self) -> Result<feed_rs::model::Feed> {
-        let content = reqwest::get(&self.url).await?.bytes().await?;
-        let content_str = String::from_utf8_lossy(&content);
-        let feed = feed_rs::parser::parse(content_str.as_bytes());
-        Ok(feed.unwrap())
+    /// JSON Feeds are read directly, anything else goes through feed-rs.
+    async fn fetch_and_parse(&mut self, rules: &RuleSet, http: &HttpSettings) -> Result<()> {
+        let document = FeedSource::parse(&self.url)?
+            .fetch(&http.merged(&self.http))
+            .await?;
+        let self_url = if json_feed::is_json(&document.content) {
+            let parsed = json_feed::parse(&document.content)?;
+            self.merge_entries(parsed.entries, rules);
+            self.metadata = parsed.metadata;
+            parsed.feed_url
+        } else {
+            let content_str = String::from_utf8_lossy(&document.content);
+            let feed = feed_rs::parser::parse(content_str.as_bytes())?;
+            self.populate_entries(&feed, rules);
+            self.metadata = FeedMetadata::from_feed(&feed, &document.content);
+            feed.links
+                .iter()
+                .find(|link| link.rel.as_deref() == Some("self"))
+                .map(|link| link.href.clone())
+        };
+        self.suggest_move(document.moved_to.or(self_url));
+        Ok(())
     }
 
-    pub fn populate_entries(&mut self, feed: &feed_rs::model::Feed)  -> Option<Vec<Entry>> {
+    /// How long the feed asks to be left between refreshes, from its `ttl` or
+    /// syndication hints, capped at a day.
+    pub fn refresh_interval(&self) -> Option<chrono::Duration> {
+        let minutes = self
+            .metadata
+            .ttl_minutes
+            .into_iter()
+            .chain(self.metadata.update_period_minutes)
+            .max()?;
+        Some(chrono::Duration::minutes(
+            minutes.min(MAX_HINTED_INTERVAL_MINUTES) as i64,
+        ))
+    }
+
+    /// False if the feed was fetched recently enough that it has asked not to be
+    /// fetched again yet.
+    pub fn is_due(&self) -> bool {
+        match (self.refresh_interval(), self.health.last_success) {
+            (Some(interval), Some(last_success)) => Utc::now() - last_success >= interval,
+            _ => true,
+        }
+    }
+
+    /// Remembers a new location for the feed, unless it's where the feed already is,
+    /// it isn't a web address, or the user turned it down before.
+    fn suggest_move(&mut self, url: Option<String>) {
+        let is_web = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+        self.moved_to = url.filter(|url| {
+            is_web(url)
+                && is_web(&self.url)
+                && url.trim_end_matches('/') != self.url.trim_end_matches('/')
+                && self.declined_move.as_ref() != Some(url)
+        });
+    }
+
+    /// Rebuilds the entries from a freshly fetched feed.
+    pub fn populate_entries(&mut self, feed: &feed_rs::model::Feed, rules: &RuleSet)  -> Option<Vec<Entry>> {
         let mut entries: Vec<Entry> = vec![];
         for entry in feed.entries.iter() {
             // Deleted markdown conversion as i dont think it was doing anything
@@ -37,7 +181,14 @@ impl BlogFeed {
             // I just wanted to remove the dependency ok
 
             // let markdown_title = &html2md::parse_html(entry.title.as_ref().unwrap().content.as_str());
-            let markdown_title = &entry.title.as_ref().unwrap().content;
+            let markdown_title = match &entry.title {
+                Some(title) => title.content.clone(),
+                None => entry
+                    .summary
+                    .as_ref()
+                    .map(|summary| summary.content.chars().take(80).collect())
+                    .unwrap_or(String::from("(untitled)")),
+            };
 
             let unprocessed_authors: Vec<Person> = entry.authors.to_owned();
             let mut html_authors: Vec<String> = vec![];
@@ -64,51 +215,121 @@ impl BlogFeed {
                 .to_owned()
                 .unwrap_or(String::from(""));
 
-            let unprocessed_url: String = entry.links.to_owned()[0].to_owned().href;
+            let unprocessed_url: String = entry
+                .links
+                .first()
+                .map(|link| link.href.to_owned())
+                .unwrap_or(entry.id.to_owned());
 
-            let unprocessed_date: Option<DateTime<Utc>> = entry.updated.to_owned();
+            let unprocessed_date: Option<DateTime<Utc>> = entry.updated.or(entry.published);
+
+            let mut new_entry = Entry::new(
+                markdown_title.as_str(),
+                markdown_authors.as_str(),
+                markdown_content.as_str(),
+                unprocessed_url.as_str(),
+                None,
+                unprocessed_date
+            );
+            new_entry.categories = entry
+                .categories
+                .iter()
+                .map(|category| category.label.clone().unwrap_or(category.term.clone()))
+                .collect();
+            // RSS enclosures show up here too, as media objects with a single content.
+            for media in entry.media.iter() {
+                for content in media.content.iter() {
+                    if let Some(url) = &content.url {
+                        new_entry.enclosures.push(Enclosure {
+                            url: url.to_string(),
+                            mime_type: content.content_type.as_ref().map(|m| m.to_string()),
+                            length: content.size,
+                            duration: content.duration.or(media.duration).map(|d| d.as_secs()),
+                        });
+                    }
+                }
+            }
+            entries.push(new_entry);
 
-            entries.push(
-                Entry::new(
-                    markdown_title.as_str(),
-                    markdown_authors.as_str(),
-                    markdown_content.as_str(),
-                    unprocessed_url.as_str(),
-                    None,
-                    unprocessed_date
-                )
-                );
+        }
+        self.merge_entries(entries, rules)
+    }
 
+    /// Replaces the entries with freshly parsed ones. The rules only run on
+    /// entries that weren't there before, so they never undo what the user changed.
+    pub fn merge_entries(&mut self, mut entries: Vec<Entry>, rules: &RuleSet) -> Option<Vec<Entry>> {
+        // Keep what the user has already read or starred across refreshes.
+        // A feed's first fetch is everything it has, not what's new.
+        let first_fetch = self.entries.is_none();
+        let previous = self.entries.take().unwrap_or_default();
+        self.fresh.clear();
+        for entry in entries.iter_mut() {
+            rules.transform(entry, &self.name);
+            match previous.iter().find(|old| old.url == entry.url) {
+                Some(old) => {
+                    entry.read = old.read;
+                    entry.starred = old.starred;
+                    entry.hidden = old.hidden;
+                    entry.digested = old.digested;
+                    entry.tags = old.tags.clone();
+                    // A fetched article is kept, unless the feed now sends content of its own.
+                    if old.full_content && entry.content.is_none() {
+                        entry.content = old.content.clone();
+                        entry.full_content = true;
+                    }
+                    entry.sync = old.sync.clone();
+                    entry.id = old.id;
+                }
+                None => {
+                    rules.apply(entry, &self.name);
+                    if !first_fetch {
+                        self.fresh.push(entry.url.clone());
+                    }
+                }
+            }
         }
         self.entries = Some(entries);
         self.entries.clone()
     }
 
-    pub fn format_feed_entries(&self) -> String {
-        let mut resp = String::from("");
-        let default_entries: Vec<Entry> = vec![];
-        for entry in self.entries.as_ref().unwrap_or(&default_entries).iter() {
-            resp.push('[');
-            let title = format!("{:?}", entry.title);
-            resp.push_str(&title[1..title.len()-1]);
-            resp.push(']');
-            resp.push('(');
-            let url = format!("{:?}", entry.url);
-            resp.push_str(&url[1..url.len()-1]);
-            resp.push(')');
-            if let Some(update_time) = &entry.updated_at {
-                resp.push('\n');
-                let update_str = format!("{:?}", update_time.to_string());
-                resp.push_str(&update_str[1..update_str.len()-1]);
-                resp.push_str("\n\n");
-            } else {
-                resp.push_str("\n\n");
+    /// Fetches the article behind each entry that doesn't have its content yet,
+    /// returning how many were filled in. Pages that fail to load come from the
+    /// offline cache if they're there, otherwise they're left for the next refresh.
+    pub async fn fetch_full_articles(&mut self, http: &HttpSettings) -> usize {
+        let http = http.merged(&self.http);
+        let mut fetched = 0;
+        for entry in self.entries.iter_mut().flatten() {
+            if entry.content.is_some() || entry.hidden || entry.url.is_empty() {
+                continue;
+            }
+            let page = http.for_link(&self.url, &entry.url);
+            let content = match fetch_article(&entry.url, &page).await {
+                Ok(content) => Some(content),
+                Err(_) => archive::cached_article(&self.name, &entry.url),
+            };
+            if content.is_some() {
+                entry.content = content;
+                entry.full_content = true;
+                fetched += 1;
             }
-            let blurb = format!("{:?}", entry.blurb);
-            resp.push_str(&blurb[1..blurb.len()-1]);
-            resp.push_str("\n\n");
         }
-        resp
+        fetched
+    }
+
+    /// The settings for a page or file this feed links to, as `HttpSettings::for_link`.
+    pub fn link_settings(&self, http: &HttpSettings, url: &str) -> HttpSettings {
+        http.merged(&self.http).for_link(&self.url, url)
+    }
+
+    /// The optional work done after the entries are refreshed: full articles and
+    /// the offline cache.
+    pub async fn fetch_extras(&mut self, http: &HttpSettings) {
+        if self.fetch_full_content {
+            self.fetch_full_articles(http).await;
+        }
+        if self.cache_offline {
+            archive::archive_feed(self, http).await;
+        }
     }
 
     pub fn get_feed_content(&self) -> Vec<String> {
@@ -132,6 +353,13 @@ impl BlogFeed {
     }
 }
 
+/// Downloads a page and pulls out its article text. `http` is what `for_link`
+/// gives for the page.
+pub async fn fetch_article(url: &str, http: &HttpSettings) -> Result<String> {
+    let html = http.fetch(url).await?.response.text().await?;
+    readability::extract_article(&html).ok_or_else(|| anyhow!("no article found at {}", url))
+}
+
 impl fmt::Display for BlogFeed {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", &self.name)
@@ -0,0 +1,271 @@
+use crate::feeds_and_entry::http::{self, HttpSettings};
+use anyhow::{anyhow, Result};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// Entries are fetched this many at a time.
+const PAGE_SIZE: usize = 250;
+/// A first sync stops after this many pages rather than pulling the whole history.
+const MAX_PAGES: usize = 8;
+/// Enough to cover every unread or starred entry a server is likely to hold.
+const MAX_IDS: usize = 10_000;
+
+pub const READ: &str = "user/-/state/com.google/read";
+pub const STARRED: &str = "user/-/state/com.google/starred";
+const READING_LIST: &str = "user/-/state/com.google/reading-list";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Subscription {
+    /// e.g. `feed/12`.
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Category {
+    pub label: String,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionList {
+    subscriptions: Vec<Subscription>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Link {
+    pub href: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Text {
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Origin {
+    #[serde(rename = "streamId")]
+    pub stream_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Item {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Seconds since the epoch.
+    #[serde(default)]
+    pub published: Option<i64>,
+    #[serde(default)]
+    pub canonical: Vec<Link>,
+    #[serde(default)]
+    pub alternate: Vec<Link>,
+    #[serde(default)]
+    pub summary: Option<Text>,
+    #[serde(default)]
+    pub content: Option<Text>,
+    #[serde(default)]
+    pub author: Option<String>,
+    pub origin: Origin,
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+impl Item {
+    /// The item id in the short decimal form the id lists use.
+    pub fn short_id(&self) -> String {
+        short_id(&self.id)
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.canonical
+            .first()
+            .or(self.alternate.first())
+            .map(|link| link.href.as_str())
+    }
+
+    pub fn has_state(&self, state: &str) -> bool {
+        self.categories.iter().any(|category| category == state)
+    }
+}
+
+#[derive(Deserialize)]
+struct Stream {
+    #[serde(default)]
+    items: Vec<Item>,
+    continuation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ItemRef {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ItemRefs {
+    #[serde(default, rename = "itemRefs")]
+    item_refs: Vec<ItemRef>,
+}
+
+/// Item ids come as `tag:google.com,2005:reader/item/<hex>` in streams and as
+/// signed decimals in id lists.
+fn short_id(id: &str) -> String {
+    match id.rsplit_once("reader/item/") {
+        Some((_, hex)) => match u64::from_str_radix(hex, 16) {
+            Ok(number) => (number as i64).to_string(),
+            Err(_) => id.to_string(),
+        },
+        None => id.to_string(),
+    }
+}
+
+/// A logged in session with a server speaking the Google Reader API, such as
+/// FreshRSS at `https://rss.example.org/api/greader.php`.
+pub struct GoogleReader {
+    base: String,
+    client: Client,
+    auth: String,
+}
+
+impl GoogleReader {
+    /// Logs in with the username and the password's secret reference.
+    pub async fn login(
+        url: &str,
+        username: &str,
+        password: &str,
+        http: &HttpSettings,
+    ) -> Result<GoogleReader> {
+        // The feed headers and credentials are meant for feeds, not for the server.
+        let client = HttpSettings {
+            headers: BTreeMap::new(),
+            auth: None,
+            ..http.clone()
+        }
+        .client()?;
+        let base = url.trim_end_matches('/').to_string();
+        let response = client
+            .post(format!("{}/accounts/ClientLogin", base))
+            .form(&[
+                ("Email", username.to_string()),
+                ("Passwd", http::resolve_secret(password)?),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "{} refused to log in ({}), check the username and password",
+                base,
+                response.status()
+            ));
+        }
+        let body = response.text().await?;
+        let auth = body
+            .lines()
+            .find_map(|line| line.strip_prefix("Auth="))
+            .ok_or_else(|| anyhow!("{} didn't hand out a login token", base))?
+            .trim()
+            .to_string();
+        Ok(GoogleReader { base, client, auth })
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.client
+            .get(format!("{}/reader/api/0/{}", self.base, path))
+            .header("Authorization", format!("GoogleLogin auth={}", self.auth))
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.client
+            .post(format!("{}/reader/api/0/{}", self.base, path))
+            .header("Authorization", format!("GoogleLogin auth={}", self.auth))
+    }
+
+    async fn json<T: for<'de> Deserialize<'de>>(&self, request: RequestBuilder) -> Result<T> {
+        let response = checked(request.send().await?)?;
+        Ok(serde_json::from_slice(&response.bytes().await?)?)
+    }
+
+    pub async fn subscriptions(&self) -> Result<Vec<Subscription>> {
+        let list: SubscriptionList = self
+            .json(self.get("subscription/list").query(&[("output", "json")]))
+            .await?;
+        Ok(list.subscriptions)
+    }
+
+    /// Entries from every subscription, newest first, published after `since`
+    /// (seconds since the epoch) or as far back as a first sync goes.
+    pub async fn items(&self, since: Option<i64>) -> Result<Vec<Item>> {
+        let mut items = vec![];
+        let mut continuation: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            let mut query = vec![
+                ("output", String::from("json")),
+                ("n", PAGE_SIZE.to_string()),
+            ];
+            if let Some(since) = since {
+                query.push(("ot", since.to_string()));
+            }
+            if let Some(continuation) = &continuation {
+                query.push(("c", continuation.clone()));
+            }
+            let path = format!("stream/contents/{}", READING_LIST);
+            let stream: Stream = self.json(self.get(&path).query(&query)).await?;
+            items.extend(stream.items);
+            continuation = stream.continuation;
+            if continuation.is_none() {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    async fn ids(&self, query: &[(&str, &str)]) -> Result<HashSet<String>> {
+        let max = MAX_IDS.to_string();
+        let mut query = query.to_vec();
+        query.extend([("n", max.as_str()), ("output", "json")]);
+        let refs: ItemRefs = self
+            .json(self.get("stream/items/ids").query(&query))
+            .await?;
+        Ok(refs.item_refs.into_iter().map(|item| item.id).collect())
+    }
+
+    pub async fn unread_ids(&self) -> Result<HashSet<String>> {
+        self.ids(&[("s", READING_LIST), ("xt", READ)]).await
+    }
+
+    pub async fn starred_ids(&self) -> Result<HashSet<String>> {
+        self.ids(&[("s", STARRED)]).await
+    }
+
+    /// Adds the state, e.g. [`READ`], to the items, or takes it away.
+    pub async fn set_state(&self, ids: &[String], state: &str, on: bool) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let token = checked(self.get("token").send().await?)?.text().await?;
+        for chunk in ids.chunks(PAGE_SIZE) {
+            let mut form = vec![
+                ("T", token.trim().to_string()),
+                (if on { "a" } else { "r" }, state.to_string()),
+            ];
+            form.extend(chunk.iter().map(|id| ("i", id.clone())));
+            checked(self.post("edit-tag").form(&form).send().await?)?;
+        }
+        Ok(())
+    }
+}
+
+fn checked(response: Response) -> Result<Response> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(anyhow!(
+            "{} answered {}",
+            response.url().path(),
+            response.status()
+        ))
+    }
+}
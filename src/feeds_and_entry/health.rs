@@ -0,0 +1,94 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// When a failing feed is flagged, from the `health` section of the config.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// A feed that hasn't fetched successfully for this many days is flagged as
+    /// possibly dead.
+    pub dead_after_days: i64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> HealthConfig {
+        HealthConfig { dead_after_days: 7 }
+    }
+}
+
+/// How reliably a feed has been fetching, updated on every refresh.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FeedHealth {
+    pub consecutive_failures: u32,
+    pub last_success: Option<DateTime<Utc>>,
+    /// When the current run of failures started.
+    pub failing_since: Option<DateTime<Utc>>,
+    /// Time to fetch and parse the feed, weighted towards recent refreshes.
+    pub average_latency_ms: Option<f64>,
+}
+
+/// How much a new latency counts towards the average.
+const LATENCY_WEIGHT: f64 = 0.3;
+
+impl FeedHealth {
+    pub fn record_success(&mut self, latency: std::time::Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        self.average_latency_ms = Some(match self.average_latency_ms {
+            Some(average) => average + LATENCY_WEIGHT * (latency_ms - average),
+            None => latency_ms,
+        });
+        self.consecutive_failures = 0;
+        self.failing_since = None;
+        self.last_success = Some(Utc::now());
+    }
+
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+        if self.failing_since.is_none() {
+            self.failing_since = Some(Utc::now());
+        }
+    }
+
+    /// True once the feed has been failing for longer than the configured days.
+    pub fn is_possibly_dead(&self, config: &HealthConfig) -> bool {
+        self.failing_since
+            .is_some_and(|since| Utc::now() - since >= Duration::days(config.dead_after_days))
+    }
+
+    /// One line for the feed management screen.
+    pub fn summary(&self, config: &HealthConfig) -> String {
+        let latency = match self.average_latency_ms {
+            Some(latency) => format!("{:.0} ms avg", latency),
+            None => String::from("no successful fetch yet"),
+        };
+        let last_success = match self.last_success {
+            Some(time) => format!("last ok {}", format_age(Utc::now() - time)),
+            None => String::from("never fetched"),
+        };
+        let failures = match self.consecutive_failures {
+            1 => String::from("1 failure"),
+            count => format!("{} failures", count),
+        };
+        let status = if self.is_possibly_dead(config) {
+            format!("POSSIBLY DEAD, {}", failures)
+        } else if self.consecutive_failures > 0 {
+            format!("{} in a row", failures)
+        } else {
+            String::from("ok")
+        };
+        format!("{}, {}, {}", status, latency, last_success)
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    if age.num_days() > 0 {
+        format!("{}d ago", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{}h ago", age.num_hours())
+    } else if age.num_minutes() > 0 {
+        format!("{}m ago", age.num_minutes())
+    } else {
+        String::from("just now")
+    }
+}
@@ -0,0 +1,242 @@
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::feeds::BlogFeed;
+use crate::feeds_and_entry::rules::{Condition, Matcher};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+/// A hook that hasn't finished by then is killed.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long exiting waits for the queued hooks before giving up on them.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Something that happened that a hook can run on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    NewEntry,
+    FeedError,
+    RefreshFinished,
+    EntryStarred,
+}
+
+impl HookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            HookEvent::NewEntry => "new-entry",
+            HookEvent::FeedError => "feed-error",
+            HookEvent::RefreshFinished => "refresh-finished",
+            HookEvent::EntryStarred => "entry-starred",
+        }
+    }
+}
+
+/// A command run on an event, from the `hooks` list of the config, e.g.
+/// `{ "event": "new-entry", "command": "notify-send \"$(jq -r .entry.title)\"" }`.
+/// The event is passed as JSON on stdin and its name in `JRSS_EVENT`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Hook {
+    pub event: HookEvent,
+    /// Run with `sh -c`.
+    pub command: String,
+    /// For entry events, only run on entries matching this, written like a filter
+    /// rule's condition.
+    #[serde(default)]
+    pub when: Option<Condition>,
+}
+
+/// The configured hooks, ready to run. Hooks run in the background one at a time,
+/// in the order their events happened. Clones share the same queue.
+#[derive(Clone)]
+pub struct Hooks {
+    hooks: Arc<Vec<(Hook, Option<Matcher>)>>,
+    queue: Arc<Semaphore>,
+    /// Hooks queued or running.
+    pending: Arc<AtomicUsize>,
+    failures: Arc<Mutex<Vec<String>>>,
+}
+
+impl Hooks {
+    pub fn new(hooks: &[Hook]) -> Result<Hooks> {
+        let mut compiled = vec![];
+        for hook in hooks {
+            let matcher = match &hook.when {
+                Some(condition) => Some(
+                    Matcher::compile(condition)
+                        .map_err(|e| anyhow!("hook {:?}: {}", hook.command, e))?,
+                ),
+                None => None,
+            };
+            compiled.push((hook.clone(), matcher));
+        }
+        Ok(Hooks {
+            hooks: Arc::new(compiled),
+            ..Hooks::none()
+        })
+    }
+
+    /// No hooks at all.
+    pub fn none() -> Hooks {
+        Hooks {
+            hooks: Arc::new(vec![]),
+            queue: Arc::new(Semaphore::new(1)),
+            pending: Arc::new(AtomicUsize::new(0)),
+            failures: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Why the hooks that failed since the last call failed.
+    pub fn take_failures(&self) -> Vec<String> {
+        std::mem::take(&mut *self.failures.lock().unwrap())
+    }
+
+    /// Waits for every queued hook to finish, for when the process is about to
+    /// exit and would otherwise kill them. Gives up after `FINISH_TIMEOUT`, so a
+    /// long queue can't keep the process from exiting.
+    pub async fn finished(&self) {
+        let drained = async {
+            while self.pending.load(Ordering::SeqCst) > 0 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        let _ = tokio::time::timeout(FINISH_TIMEOUT, drained).await;
+    }
+
+    pub fn new_entry(&self, feed: &BlogFeed, entry: &Entry) {
+        self.entry_event(HookEvent::NewEntry, feed, entry);
+    }
+
+    pub fn entry_starred(&self, feed: &BlogFeed, entry: &Entry) {
+        self.entry_event(HookEvent::EntryStarred, feed, entry);
+    }
+
+    pub fn feed_error(&self, feed: &BlogFeed, error: &str) {
+        self.fire(
+            HookEvent::FeedError,
+            None,
+            json!({
+                "feed": feed_json(feed),
+                "error": error,
+                "consecutive_failures": feed.health.consecutive_failures,
+            }),
+        );
+    }
+
+    pub fn refresh_finished(
+        &self,
+        feeds: usize,
+        new_entries: usize,
+        errors: &[String],
+        unread: usize,
+    ) {
+        self.fire(
+            HookEvent::RefreshFinished,
+            None,
+            json!({
+                "feeds": feeds,
+                "new_entries": new_entries,
+                "errors": errors,
+                "unread": unread,
+            }),
+        );
+    }
+
+    fn entry_event(&self, event: HookEvent, feed: &BlogFeed, entry: &Entry) {
+        self.fire(
+            event,
+            Some((entry, &feed.name)),
+            json!({
+                "feed": feed_json(feed),
+                "entry": {
+                    "title": entry.title,
+                    "url": entry.url,
+                    "authors": entry.authors,
+                    "published": entry.updated().map(|date| date.to_rfc3339()),
+                    "summary": entry.blurb,
+                    "content": entry.content,
+                    "categories": entry.categories,
+                    "tags": entry.tags,
+                    "read": entry.read,
+                    "starred": entry.starred,
+                },
+            }),
+        );
+    }
+
+    /// Queues every hook for the event whose `when` matches the entry.
+    fn fire(&self, event: HookEvent, entry: Option<(&Entry, &str)>, mut payload: Value) {
+        payload["event"] = json!(event.name());
+        for (hook, matcher) in self.hooks.iter() {
+            if hook.event != event {
+                continue;
+            }
+            if let (Some(matcher), Some((entry, feed_name))) = (matcher, entry) {
+                if !matcher.is_match(entry, feed_name) {
+                    continue;
+                }
+            }
+            let command = hook.command.clone();
+            let input = payload.to_string();
+            let queue = self.queue.clone();
+            let failures = self.failures.clone();
+            let pending = self.pending.clone();
+            pending.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let _turn = queue.acquire().await;
+                if let Err(e) = run(&command, event, &input).await {
+                    failures
+                        .lock()
+                        .unwrap()
+                        .push(format!("hook {:?} failed: {}", command, e));
+                }
+                pending.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+}
+
+fn feed_json(feed: &BlogFeed) -> Value {
+    json!({
+        "name": feed.name,
+        "url": feed.url,
+        "folder": feed.folder,
+    })
+}
+
+async fn run(command: &str, event: HookEvent, input: &str) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("JRSS_EVENT", event.name())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdin = child.stdin.take();
+    // The input is written inside the timeout too, since a hook that never reads
+    // it would otherwise block the write once the pipe is full.
+    let finished = async move {
+        if let Some(mut stdin) = stdin {
+            // A hook that doesn't read its input isn't an error.
+            let _ = stdin.write_all(input.as_bytes()).await;
+        }
+        child.wait_with_output().await
+    };
+    let output = tokio::time::timeout(HOOK_TIMEOUT, finished)
+        .await
+        .map_err(|_| anyhow!("still running after {}s", HOOK_TIMEOUT.as_secs()))??;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow!("{}: {}", output.status, stderr.trim()))
+    }
+}
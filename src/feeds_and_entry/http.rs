@@ -0,0 +1,349 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, LOCATION, RETRY_AFTER};
+use reqwest::redirect::Policy;
+use reqwest::{Certificate, Client, Proxy, RequestBuilder, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Named secrets, kept out of the session file so it can be shared or synced.
+const CREDENTIALS_PATH: &str = ".credentials.json";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_RETRIES: u32 = 2;
+/// The first retry waits up to this long, doubling with every retry after it.
+const RETRY_BASE: Duration = Duration::from_secs(1);
+/// The backoff stops doubling after this many retries.
+const MAX_BACKOFF_DOUBLINGS: u32 = 5;
+/// A feed spends at most this long waiting between retries, since the refresh
+/// waits on it. A server asking for longer is left until the next refresh.
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(15);
+
+const MAX_REDIRECTS: usize = 10;
+
+/// How feeds are requested. The `http` section of the config holds the defaults
+/// and each feed's own settings override them field by field.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HttpSettings {
+    pub user_agent: Option<String>,
+    pub timeout_secs: Option<u64>,
+    /// How long an `exec:` feed's command gets to print the feed, 30s if unset.
+    pub exec_timeout_secs: Option<u64>,
+    /// How many times a failed fetch is retried before giving up.
+    pub retries: Option<u32>,
+    /// e.g. `http://proxy.internal:3128` or `socks5://localhost:1080`.
+    pub proxy: Option<String>,
+    /// A PEM file of extra certificate authorities to trust.
+    pub ca_bundle: Option<String>,
+    /// Sent with every request. Values written as secrets, e.g. `env:API_KEY`, are
+    /// looked up when the request is made.
+    pub headers: BTreeMap<String, String>,
+    pub auth: Option<Auth>,
+}
+
+/// Credentials for a feed. The password and token are secret references such as
+/// `env:FEED_TOKEN` or `credential:work-wiki`, never the secret itself.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Auth {
+    Basic { username: String, password: String },
+    Bearer { token: String },
+}
+
+/// The server answered 410: the feed has been taken down for good.
+#[derive(Debug)]
+pub struct Gone;
+
+impl std::fmt::Display for Gone {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "the feed is gone (410)")
+    }
+}
+
+impl std::error::Error for Gone {}
+
+/// A successful response, and where the feed lives now if every redirect on the
+/// way was permanent.
+pub struct Fetched {
+    pub response: Response,
+    pub moved_to: Option<String>,
+}
+
+fn is_secret_reference(value: &str) -> bool {
+    value.starts_with("env:") || value.starts_with("credential:")
+}
+
+fn not_a_reference() -> anyhow::Error {
+    anyhow!("secrets are given as env:NAME or credential:NAME so they stay out of the session file")
+}
+
+/// Checks that a value names a secret rather than holding one.
+pub fn check_secret_reference(value: &str) -> Result<()> {
+    if is_secret_reference(value) {
+        Ok(())
+    } else {
+        Err(not_a_reference())
+    }
+}
+
+/// Looks up an `env:` or `credential:` reference.
+pub fn resolve_secret(reference: &str) -> Result<String> {
+    if let Some(name) = reference.strip_prefix("env:") {
+        return std::env::var(name).map_err(|_| anyhow!("environment variable {} isn't set", name));
+    }
+    if let Some(name) = reference.strip_prefix("credential:") {
+        let text = std::fs::read_to_string(CREDENTIALS_PATH)
+            .map_err(|e| anyhow!("couldn't read {}: {}", CREDENTIALS_PATH, e))?;
+        let credentials: HashMap<String, String> = serde_json::from_str(&text)?;
+        return credentials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("no credential called {:?} in {}", name, CREDENTIALS_PATH));
+    }
+    Err(not_a_reference())
+}
+
+/// Rate limiting and the server errors that tend to clear up by themselves.
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// The wait asked for by a `Retry-After` header, given in seconds or as a date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Exponential backoff with jitter, so feeds on the same host don't retry in step.
+fn backoff(attempt: u32) -> Duration {
+    (RETRY_BASE * 2u32.pow(attempt.min(MAX_BACKOFF_DOUBLINGS)))
+        .mul_f64(rand::thread_rng().gen_range(0.5..1.0))
+}
+
+/// Adds the extra authorities of a PEM bundle, which may hold several certificates.
+fn add_ca_bundle(
+    mut builder: reqwest::ClientBuilder,
+    path: &str,
+) -> Result<reqwest::ClientBuilder> {
+    let pem =
+        std::fs::read_to_string(path).map_err(|e| anyhow!("couldn't read {}: {}", path, e))?;
+    let end = "-----END CERTIFICATE-----";
+    let mut added = 0;
+    for block in pem.split_inclusive(end).filter(|block| block.contains(end)) {
+        builder = builder.add_root_certificate(Certificate::from_pem(block.trim().as_bytes())?);
+        added += 1;
+    }
+    if added == 0 {
+        return Err(anyhow!("no certificates found in {}", path));
+    }
+    Ok(builder)
+}
+
+impl HttpSettings {
+    /// These settings with a feed's own settings laid over them.
+    pub fn merged(&self, feed: &HttpSettings) -> HttpSettings {
+        let mut headers = self.headers.clone();
+        headers.extend(feed.headers.clone());
+        HttpSettings {
+            user_agent: feed.user_agent.clone().or(self.user_agent.clone()),
+            timeout_secs: feed.timeout_secs.or(self.timeout_secs),
+            exec_timeout_secs: feed.exec_timeout_secs.or(self.exec_timeout_secs),
+            retries: feed.retries.or(self.retries),
+            proxy: feed.proxy.clone().or(self.proxy.clone()),
+            ca_bundle: feed.ca_bundle.clone().or(self.ca_bundle.clone()),
+            headers,
+            auth: feed.auth.clone().or(self.auth.clone()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &HttpSettings::default()
+    }
+
+    pub fn client(&self) -> Result<Client> {
+        let user_agent = self
+            .user_agent
+            .clone()
+            .unwrap_or_else(|| format!("j-rss-tui/{}", env!("CARGO_PKG_VERSION")));
+        // Redirects are followed by `fetch` so it can tell permanent ones apart.
+        let mut builder = Client::builder()
+            .user_agent(user_agent)
+            .redirect(Policy::none())
+            .timeout(
+                self.timeout_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_TIMEOUT),
+            );
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(ca_bundle) = &self.ca_bundle {
+            builder = add_ca_bundle(builder, ca_bundle)?;
+        }
+        Ok(builder.build()?)
+    }
+
+    fn header_map(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.iter() {
+            let value = if is_secret_reference(value) {
+                resolve_secret(value)?
+            } else {
+                value.clone()
+            };
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(&value)?,
+            );
+        }
+        Ok(headers)
+    }
+
+    /// A GET request for the url with the headers and credentials filled in.
+    pub fn get(&self, url: &str) -> Result<RequestBuilder> {
+        let mut request = self.client()?.get(url).headers(self.header_map()?);
+        request = match &self.auth {
+            Some(Auth::Basic { username, password }) => {
+                request.basic_auth(username, Some(resolve_secret(password)?))
+            }
+            Some(Auth::Bearer { token }) => request.bearer_auth(resolve_secret(token)?),
+            None => request,
+        };
+        Ok(request)
+    }
+
+    /// These settings without the headers and credentials, for requests that go
+    /// somewhere other than the feed.
+    pub fn anonymous(&self) -> HttpSettings {
+        HttpSettings {
+            headers: BTreeMap::new(),
+            auth: None,
+            ..self.clone()
+        }
+    }
+
+    /// These settings for a page or file linked from the feed at `feed_url`, like
+    /// an article or an enclosure. The headers and credentials only go along if it's
+    /// on the feed's scheme, host and port.
+    pub fn for_link(&self, feed_url: &str, url: &str) -> HttpSettings {
+        match (Url::parse(feed_url), Url::parse(url)) {
+            (Ok(feed), Ok(link)) if feed.origin() == link.origin() => self.clone(),
+            _ => self.anonymous(),
+        }
+    }
+
+    /// Fetches the url, following redirects. Headers and credentials are only sent
+    /// to the scheme, host and port the url points at, so never over plain http
+    /// after a redirect from https.
+    pub async fn fetch(&self, url: &str) -> Result<Fetched> {
+        self.fetch_with(url, &|request| request).await
+    }
+
+    /// Like `fetch`, with every request adjusted before it's sent, e.g. to ask for
+    /// a range.
+    pub async fn fetch_with(
+        &self,
+        url: &str,
+        adjust: &(dyn Fn(RequestBuilder) -> RequestBuilder + Sync),
+    ) -> Result<Fetched> {
+        let mut current = Url::parse(url)?;
+        let origin = current.origin();
+        let mut moved_to = None;
+        let mut permanent = true;
+        for _ in 0..MAX_REDIRECTS {
+            let response = if current.origin() == origin {
+                self.send_with_retry(current.as_str(), adjust).await?
+            } else {
+                self.anonymous()
+                    .send_with_retry(current.as_str(), adjust)
+                    .await?
+            };
+            let status = response.status();
+            if status == StatusCode::GONE {
+                return Err(Gone.into());
+            }
+            if !status.is_redirection() || status == StatusCode::NOT_MODIFIED {
+                let response = response.error_for_status()?;
+                return Ok(Fetched { response, moved_to });
+            }
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| anyhow!("{} redirected without saying where", current))?;
+            let next = current.join(location)?;
+            permanent = permanent
+                && matches!(
+                    status,
+                    StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+                );
+            if permanent {
+                moved_to = Some(next.to_string());
+            }
+            current = next;
+        }
+        Err(anyhow!("{} redirected too many times", url))
+    }
+
+    /// Sends the request, retrying connection failures, timeouts, 429s and server
+    /// errors. `Retry-After` is honoured, otherwise the wait backs off exponentially.
+    async fn send_with_retry(
+        &self,
+        url: &str,
+        adjust: &(dyn Fn(RequestBuilder) -> RequestBuilder + Sync),
+    ) -> Result<Response> {
+        let retries = self.retries.unwrap_or(DEFAULT_RETRIES);
+        let mut attempt = 0;
+        let mut waited = Duration::ZERO;
+        loop {
+            let wait = match adjust(self.get(url)?).send().await {
+                Ok(response) if !is_retryable_status(response.status()) => return Ok(response),
+                Ok(response) => {
+                    let wait = retry_after(&response);
+                    if let Some(wait) = wait.filter(|wait| waited + *wait > MAX_RETRY_WAIT) {
+                        return Err(anyhow!(
+                            "{} asked us to wait {}s before trying again",
+                            url,
+                            wait.as_secs()
+                        ));
+                    }
+                    let wait = wait.unwrap_or_else(|| backoff(attempt));
+                    if attempt >= retries || waited + wait > MAX_RETRY_WAIT {
+                        return Ok(response);
+                    }
+                    wait
+                }
+                Err(e) if attempt < retries && (e.is_connect() || e.is_timeout()) => {
+                    let wait = backoff(attempt);
+                    if waited + wait > MAX_RETRY_WAIT {
+                        return Err(e.into());
+                    }
+                    wait
+                }
+                Err(e) => return Err(e.into()),
+            };
+            tokio::time::sleep(wait).await;
+            waited += wait;
+            attempt += 1;
+        }
+    }
+}
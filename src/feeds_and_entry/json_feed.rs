@@ -0,0 +1,158 @@
+use crate::feeds_and_entry::entry::{Enclosure, Entry};
+use crate::feeds_and_entry::metadata::FeedMetadata;
+use crate::feeds_and_entry::readability;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+/// A JSON Feed document, version 1.0 or 1.1. See https://www.jsonfeed.org/version/1.1/
+#[derive(Debug, Deserialize)]
+struct JsonFeed {
+    version: String,
+    title: Option<String>,
+    description: Option<String>,
+    home_page_url: Option<String>,
+    icon: Option<String>,
+    favicon: Option<String>,
+    /// Only in 1.1.
+    language: Option<String>,
+    /// Where the feed itself lives, like an Atom `rel="self"` link.
+    feed_url: Option<String>,
+    /// 1.0 had a single author, 1.1 replaced it with a list.
+    author: Option<JsonAuthor>,
+    authors: Option<Vec<JsonAuthor>>,
+    #[serde(default)]
+    items: Vec<JsonItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonAuthor {
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonItem {
+    /// A string in the spec, but plenty of 1.0 feeds use numbers.
+    id: serde_json::Value,
+    url: Option<String>,
+    external_url: Option<String>,
+    title: Option<String>,
+    content_html: Option<String>,
+    content_text: Option<String>,
+    summary: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    author: Option<JsonAuthor>,
+    authors: Option<Vec<JsonAuthor>>,
+    tags: Option<Vec<String>>,
+    attachments: Option<Vec<JsonAttachment>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonAttachment {
+    url: String,
+    mime_type: Option<String>,
+    size_in_bytes: Option<u64>,
+    duration_in_seconds: Option<f64>,
+}
+
+/// True if the document looks like JSON rather than XML.
+pub fn is_json(content: &[u8]) -> bool {
+    content
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .is_some_and(|byte| *byte == b'{')
+}
+
+fn author_names(author: &Option<JsonAuthor>, authors: &Option<Vec<JsonAuthor>>) -> Vec<String> {
+    let mut names: Vec<String> = authors
+        .iter()
+        .flatten()
+        .filter_map(|author| author.name.clone())
+        .collect();
+    if names.is_empty() {
+        names.extend(author.as_ref().and_then(|author| author.name.clone()));
+    }
+    names
+}
+
+fn parse_date(date: &Option<String>) -> Option<DateTime<Utc>> {
+    let date = date.as_ref()?;
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// What's read out of a JSON Feed document.
+pub struct JsonFeedDocument {
+    pub entries: Vec<Entry>,
+    pub feed_url: Option<String>,
+    pub metadata: FeedMetadata,
+}
+
+/// Reads the entries out of a JSON Feed. Items take the feed's authors when they
+/// don't have their own, and attachments become enclosures.
+pub fn parse(content: &[u8]) -> Result<JsonFeedDocument> {
+    let feed: JsonFeed = serde_json::from_slice(content)?;
+    if !feed.version.starts_with("https://jsonfeed.org/version/") {
+        return Err(anyhow!("unsupported JSON Feed version {:?}", feed.version));
+    }
+    let feed_authors = author_names(&feed.author, &feed.authors);
+    let mut entries: Vec<Entry> = vec![];
+    for item in feed.items {
+        let id = match &item.id {
+            serde_json::Value::String(id) => id.clone(),
+            other => other.to_string(),
+        };
+        let text = item
+            .content_text
+            .clone()
+            .or_else(|| item.content_html.as_deref().map(readability::html_to_text));
+        let blurb = item
+            .summary
+            .clone()
+            .or_else(|| text.clone())
+            .unwrap_or_default();
+        let title = item
+            .title
+            .clone()
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| blurb.chars().take(80).collect());
+        let mut authors = author_names(&item.author, &item.authors);
+        if authors.is_empty() {
+            authors = feed_authors.clone();
+        }
+        let url = item
+            .url
+            .clone()
+            .or_else(|| item.external_url.clone())
+            .unwrap_or(id);
+        let updated = parse_date(&item.date_modified).or(parse_date(&item.date_published));
+        let mut entry = Entry::new(&title, &authors.join(", "), &blurb, &url, text, updated);
+        entry.categories = item.tags.unwrap_or_default();
+        entry.enclosures = item
+            .attachments
+            .unwrap_or_default()
+            .into_iter()
+            .map(|attachment| Enclosure {
+                url: attachment.url,
+                mime_type: attachment.mime_type,
+                length: attachment.size_in_bytes,
+                duration: attachment.duration_in_seconds.map(|d| d as u64),
+            })
+            .collect();
+        entries.push(entry);
+    }
+    Ok(JsonFeedDocument {
+        entries,
+        feed_url: feed.feed_url,
+        metadata: FeedMetadata {
+            title: feed.title,
+            description: feed.description,
+            site_link: feed.home_page_url,
+            icon: feed.favicon.or(feed.icon),
+            language: feed.language,
+            ..FeedMetadata::default()
+        },
+    })
+}
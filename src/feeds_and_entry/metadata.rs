@@ -0,0 +1,107 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+
+/// What a feed says about itself, kept up to date on every refresh.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FeedMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The website the feed belongs to.
+    pub site_link: Option<String>,
+    pub icon: Option<String>,
+    pub language: Option<String>,
+    pub generator: Option<String>,
+    /// How long the feed may be cached before it's fetched again, from RSS `ttl`.
+    pub ttl_minutes: Option<u32>,
+    /// How often the feed says it changes, from `sy:updatePeriod` and
+    /// `sy:updateFrequency`.
+    pub update_period_minutes: Option<u32>,
+}
+
+impl FeedMetadata {
+    /// The metadata feed-rs parsed, plus the syndication hints it doesn't know about.
+    pub fn from_feed(feed: &feed_rs::model::Feed, content: &[u8]) -> FeedMetadata {
+        FeedMetadata {
+            title: feed
+                .title
+                .as_ref()
+                .map(|title| title.content.trim().to_string()),
+            description: feed
+                .description
+                .as_ref()
+                .map(|description| description.content.trim().to_string()),
+            site_link: feed
+                .links
+                .iter()
+                .find(|link| link.rel.as_deref().unwrap_or("alternate") == "alternate")
+                .map(|link| link.href.clone()),
+            icon: feed
+                .icon
+                .as_ref()
+                .or(feed.logo.as_ref())
+                .map(|image| image.uri.clone()),
+            language: feed.language.clone(),
+            generator: feed
+                .generator
+                .as_ref()
+                .map(|generator| match &generator.version {
+                    Some(version) => format!("{} {}", generator.content.trim(), version),
+                    None => generator.content.trim().to_string(),
+                }),
+            ttl_minutes: feed.ttl,
+            update_period_minutes: syndication_period(content),
+        }
+    }
+}
+
+/// Minutes between updates according to the RSS syndication module, e.g.
+/// `<sy:updatePeriod>daily</sy:updatePeriod>` with `<sy:updateFrequency>2</sy:updateFrequency>`
+/// is every 720 minutes.
+fn syndication_period(content: &[u8]) -> Option<u32> {
+    let mut reader = Reader::from_reader(content);
+    let mut buffer = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+    let mut period: Option<u32> = None;
+    let mut frequency: u32 = 1;
+    loop {
+        match reader.read_event_into(&mut buffer) {
+            Ok(Event::Start(element)) => {
+                let name = element.local_name().as_ref().to_vec();
+                // The channel's own elements come before its items.
+                if name == b"item" || name == b"entry" {
+                    break;
+                }
+                current = Some(name);
+            }
+            Ok(Event::Text(text)) => {
+                let text = text
+                    .unescape()
+                    .map(|text| text.trim().to_string())
+                    .unwrap_or_default();
+                match current.as_deref() {
+                    Some(b"updatePeriod") => {
+                        period = match text.as_str() {
+                            "hourly" => Some(60),
+                            "daily" => Some(24 * 60),
+                            "weekly" => Some(7 * 24 * 60),
+                            "monthly" => Some(30 * 24 * 60),
+                            "yearly" => Some(365 * 24 * 60),
+                            _ => None,
+                        }
+                    }
+                    Some(b"updateFrequency") => {
+                        frequency = text.parse().ok().filter(|f| *f > 0).unwrap_or(1)
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::End(_)) => current = None,
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+    period.map(|period| period / frequency)
+}
@@ -1,2 +1,19 @@
+pub mod archive;
+pub mod downloads;
+pub mod duplicates;
 pub mod entry;
+pub mod export;
 pub mod feeds;
+pub mod greader;
+pub mod health;
+pub mod hooks;
+pub mod http;
+pub mod json_feed;
+pub mod metadata;
+pub mod opml;
+pub mod ordering;
+pub mod read_later;
+pub mod readability;
+pub mod rules;
+pub mod scripts;
+pub mod source;
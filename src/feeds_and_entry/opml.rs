@@ -0,0 +1,130 @@
+use crate::feeds_and_entry::feeds::BlogFeed;
+use anyhow::{anyhow, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn feed_outline(feed: &BlogFeed, indent: &str) -> String {
+    format!(
+        "{}<outline type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"/>\n",
+        indent,
+        escape_xml(&feed.name),
+        escape_xml(&feed.name),
+        escape_xml(&feed.url)
+    )
+}
+
+/// Writes the outlines for everything in the folder at `path`, recursing into
+/// its subfolders.
+fn folder_outlines(feeds: &[BlogFeed], path: &[&str], depth: usize) -> String {
+    let indent = "  ".repeat(depth + 2);
+    let mut resp = String::from("");
+    let mut subfolders: Vec<&str> = vec![];
+    for feed in feeds {
+        let folder: Vec<&str> = feed.folder_path();
+        if folder == path {
+            resp.push_str(&feed_outline(feed, &indent));
+        } else if folder.len() > path.len() && folder.starts_with(path) {
+            let child = folder[path.len()];
+            if !subfolders.contains(&child) {
+                subfolders.push(child);
+            }
+        }
+    }
+    for child in subfolders {
+        let mut child_path = path.to_vec();
+        child_path.push(child);
+        resp.push_str(&format!(
+            "{}<outline text=\"{}\" title=\"{}\">\n",
+            indent,
+            escape_xml(child),
+            escape_xml(child)
+        ));
+        resp.push_str(&folder_outlines(feeds, &child_path, depth + 1));
+        resp.push_str(&format!("{}</outline>\n", indent));
+    }
+    resp
+}
+
+/// Writes the feeds as an OPML 2.0 subscription list, with folders as nested outlines.
+pub fn to_opml(title: &str, feeds: &[BlogFeed]) -> String {
+    let mut resp = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    resp.push_str("<opml version=\"2.0\">\n");
+    resp.push_str("  <head>\n");
+    resp.push_str(&format!("    <title>{}</title>\n", escape_xml(title)));
+    resp.push_str("  </head>\n");
+    resp.push_str("  <body>\n");
+    resp.push_str(&folder_outlines(feeds, &[], 0));
+    resp.push_str("  </body>\n");
+    resp.push_str("</opml>\n");
+    resp
+}
+
+fn get_attribute(element: &BytesStart, name: &[u8]) -> Result<Option<String>> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if attribute.key.as_ref() == name {
+            let value = std::str::from_utf8(&attribute.value)?;
+            return Ok(Some(quick_xml::escape::unescape(value)?.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the feeds out of an OPML document. Outlines without an `xmlUrl` are
+/// folders, and the feeds inside them get that folder's path.
+pub fn from_opml(text: &str) -> Result<Vec<BlogFeed>> {
+    let mut reader = Reader::from_str(text);
+    reader.trim_text(true);
+    let mut feeds: Vec<BlogFeed> = vec![];
+    // One entry per open outline: the folder name if it was a folder.
+    let mut open_outlines: Vec<Option<String>> = vec![];
+    loop {
+        let (element, has_children) = match reader.read_event()? {
+            Event::Start(element) => (element, true),
+            Event::Empty(element) => (element, false),
+            Event::End(element) => {
+                if element.name().as_ref() == b"outline" {
+                    open_outlines.pop();
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        if element.name().as_ref() != b"outline" {
+            continue;
+        }
+        let text = match get_attribute(&element, b"title")? {
+            Some(title) => Some(title),
+            None => get_attribute(&element, b"text")?,
+        };
+        match get_attribute(&element, b"xmlUrl")? {
+            Some(url) => {
+                let mut feed = BlogFeed::new(&url, text.as_deref().unwrap_or(&url));
+                let folders: Vec<String> = open_outlines.iter().flatten().cloned().collect();
+                if !folders.is_empty() {
+                    feed.folder = Some(folders.join("/"));
+                }
+                feeds.push(feed);
+                if has_children {
+                    open_outlines.push(None);
+                }
+            }
+            None => {
+                if has_children {
+                    let name = text.ok_or_else(|| anyhow!("folder outline has no name"))?;
+                    open_outlines.push(Some(name.replace('/', "-")));
+                }
+            }
+        }
+    }
+    Ok(feeds)
+}
@@ -0,0 +1,199 @@
+use crate::feeds_and_entry::entry::Entry;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Local};
+use serde::{Deserialize, Serialize};
+
+/// The order entries are listed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortMode {
+    #[default]
+    Newest,
+    Oldest,
+    Feed,
+    Author,
+    Title,
+    UnreadFirst,
+}
+
+impl SortMode {
+    pub const NAMES: [&'static str; 6] = [
+        "newest",
+        "oldest",
+        "feed",
+        "author",
+        "title",
+        "unread-first",
+    ];
+
+    pub fn from_name(name: &str) -> Result<SortMode> {
+        let mode = match name {
+            "newest" => SortMode::Newest,
+            "oldest" => SortMode::Oldest,
+            "feed" => SortMode::Feed,
+            "author" => SortMode::Author,
+            "title" => SortMode::Title,
+            "unread-first" | "unread" => SortMode::UnreadFirst,
+            _ => {
+                return Err(anyhow!(
+                    "unknown sort {:?}, try one of {}",
+                    name,
+                    SortMode::NAMES.join(", ")
+                ))
+            }
+        };
+        Ok(mode)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortMode::Newest => "newest",
+            SortMode::Oldest => "oldest",
+            SortMode::Feed => "feed",
+            SortMode::Author => "author",
+            SortMode::Title => "title",
+            SortMode::UnreadFirst => "unread-first",
+        }
+    }
+
+    pub fn sort(&self, entries: &mut [Entry]) {
+        entries.sort_by_cached_key(|entry| self.key(entry));
+    }
+
+    /// What an entry sorts by: read, then the lowercased text, then its date.
+    /// Worked out once per entry, since parsing the dates is the slow part.
+    fn key(&self, entry: &Entry) -> (bool, String, bool, i64) {
+        let date = entry.updated();
+        // Undated entries go last either way.
+        let undated = date.is_none();
+        let newest = date.map_or(0, |date| -date.timestamp_micros());
+        let text = |text: &str| text.to_lowercase();
+        match self {
+            SortMode::Newest => (false, String::new(), undated, newest),
+            SortMode::Oldest => (false, String::new(), undated, -newest),
+            SortMode::Feed => (false, text(&entry.feed_name), undated, newest),
+            SortMode::Author => (false, text(&entry.authors), undated, newest),
+            SortMode::Title => (false, text(&entry.title), false, 0),
+            SortMode::UnreadFirst => (entry.read, String::new(), undated, newest),
+        }
+    }
+}
+
+/// How entries are split into groups, each under its own header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupMode {
+    #[default]
+    None,
+    Day,
+    Feed,
+    Folder,
+}
+
+impl GroupMode {
+    pub const NAMES: [&'static str; 4] = ["none", "day", "feed", "folder"];
+
+    pub fn from_name(name: &str) -> Result<GroupMode> {
+        let mode = match name {
+            "none" | "off" => GroupMode::None,
+            "day" => GroupMode::Day,
+            "feed" => GroupMode::Feed,
+            "folder" => GroupMode::Folder,
+            _ => {
+                return Err(anyhow!(
+                    "unknown grouping {:?}, try one of {}",
+                    name,
+                    GroupMode::NAMES.join(", ")
+                ))
+            }
+        };
+        Ok(mode)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupMode::None => "none",
+            GroupMode::Day => "day",
+            GroupMode::Feed => "feed",
+            GroupMode::Folder => "folder",
+        }
+    }
+}
+
+/// How one view of the entry list is sorted and grouped. Kept in the session for
+/// each view, so every feed and folder can have its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewOrder {
+    pub sort: SortMode,
+    pub group: GroupMode,
+}
+
+/// A line of the entry list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    Header(String),
+    Entry(Box<Entry>),
+}
+
+impl ViewOrder {
+    /// Sorts the entries and, when grouping, starts each group with a header. Groups
+    /// come in the order of their first entry, so newest first grouped by day starts
+    /// with today. `folder_of` gives the folder of a feed.
+    pub fn arrange(
+        &self,
+        mut entries: Vec<Entry>,
+        folder_of: impl Fn(&str) -> Option<String>,
+    ) -> Vec<Row> {
+        self.sort.sort(&mut entries);
+        self.group(entries, folder_of)
+    }
+
+    /// Like `arrange`, but keeps the entries in the order they came in, for lists
+    /// that are already in the order they should be shown in.
+    pub fn group(
+        &self,
+        entries: Vec<Entry>,
+        folder_of: impl Fn(&str) -> Option<String>,
+    ) -> Vec<Row> {
+        let mut groups: Vec<(Option<String>, Vec<Entry>)> = vec![];
+        for entry in entries {
+            let header = match self.group {
+                GroupMode::None => None,
+                GroupMode::Day => Some(day_header(&entry)),
+                GroupMode::Feed => Some(entry.feed_name.clone()),
+                GroupMode::Folder => {
+                    Some(folder_of(&entry.feed_name).unwrap_or_else(|| String::from("No folder")))
+                }
+            };
+            match groups.iter_mut().find(|(name, _)| *name == header) {
+                Some((_, group)) => group.push(entry),
+                None => groups.push((header, vec![entry])),
+            }
+        }
+        groups
+            .into_iter()
+            .flat_map(|(header, group)| {
+                header
+                    .map(Row::Header)
+                    .into_iter()
+                    .chain(group.into_iter().map(|entry| Row::Entry(Box::new(entry))))
+            })
+            .collect()
+    }
+}
+
+fn day_header(entry: &Entry) -> String {
+    let day = match entry.updated() {
+        Some(date) => date.with_timezone(&Local).date_naive(),
+        None => return String::from("Undated"),
+    };
+    let today = Local::now().date_naive();
+    if day == today {
+        String::from("Today")
+    } else if day == today - Duration::days(1) {
+        String::from("Yesterday")
+    } else {
+        day.format("%A %-d %B %Y").to_string()
+    }
+}
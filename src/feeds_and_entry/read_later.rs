@@ -0,0 +1,460 @@
+use crate::feeds_and_entry::http::{self, HttpSettings};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Sends that failed, waiting to be tried again.
+const QUEUE_PATH: &str = ".read_later_queue.json";
+
+/// A token is renewed this long before the service says it expires.
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// The service "send to read-later" saves entries to, from the `read_later` section
+/// of the config, e.g. `{ "type": "wallabag", "url": "https://bag.example.org", ... }`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ReadLaterConfig {
+    Wallabag(WallabagConfig),
+}
+
+impl ReadLaterConfig {
+    pub fn service(&self, http: &HttpSettings) -> Arc<dyn ReadLaterService> {
+        match self {
+            ReadLaterConfig::Wallabag(config) => Arc::new(Wallabag::new(config, http)),
+        }
+    }
+}
+
+/// Somewhere entries can be saved to read later.
+pub trait ReadLaterService: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn save<'a>(&'a self, url: &'a str, title: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// A Wallabag instance and the API client registered on it. The client secret and
+/// password are secret references such as `env:WALLABAG_PASSWORD`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WallabagConfig {
+    pub url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// The service wouldn't log in. Nothing gets past that until the config is fixed,
+/// so it isn't worth queueing for.
+#[derive(Debug)]
+pub struct LoginRefused(String);
+
+impl fmt::Display for LoginRefused {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for LoginRefused {}
+
+/// Whether a failed send could go through later: the service couldn't be reached
+/// or had trouble of its own. A refused login or a rejected entry won't.
+fn is_temporary(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|cause| match cause.downcast_ref::<reqwest::Error>() {
+            Some(e) => {
+                e.is_connect()
+                    || e.is_timeout()
+                    || e.is_request()
+                    || e.status().is_some_and(|status| status.is_server_error())
+            }
+            None => false,
+        })
+}
+
+struct Token {
+    access_token: String,
+    expires: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+pub struct Wallabag {
+    config: WallabagConfig,
+    http: HttpSettings,
+    token: Mutex<Option<Token>>,
+}
+
+impl Wallabag {
+    pub fn new(config: &WallabagConfig, http: &HttpSettings) -> Wallabag {
+        // The feed headers and credentials are meant for feeds, not for Wallabag.
+        let http = HttpSettings {
+            headers: BTreeMap::new(),
+            auth: None,
+            ..http.clone()
+        };
+        Wallabag {
+            config: config.clone(),
+            http,
+            token: Mutex::new(None),
+        }
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.config.url.trim_end_matches('/'), path)
+    }
+
+    /// The cached access token, or a new one from the password grant.
+    async fn access_token(&self, client: &Client) -> Result<String> {
+        if let Some(token) = self.token.lock().unwrap().as_ref() {
+            if token.expires > Instant::now() {
+                return Ok(token.access_token.clone());
+            }
+        }
+        let form = [
+            ("grant_type", String::from("password")),
+            ("client_id", self.config.client_id.clone()),
+            (
+                "client_secret",
+                http::resolve_secret(&self.config.client_secret)?,
+            ),
+            ("username", self.config.username.clone()),
+            ("password", http::resolve_secret(&self.config.password)?),
+        ];
+        let response = client
+            .post(self.endpoint("oauth/v2/token"))
+            .form(&form)
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(anyhow::Error::new(response.error_for_status().unwrap_err())
+                .context("Wallabag couldn't log in"));
+        }
+        if !status.is_success() {
+            return Err(LoginRefused(format!(
+                "Wallabag refused to log in ({}), check the client and password",
+                status
+            ))
+            .into());
+        }
+        let token: TokenResponse = serde_json::from_slice(&response.bytes().await?)?;
+        let lifetime = Duration::from_secs(token.expires_in.unwrap_or(3600));
+        *self.token.lock().unwrap() = Some(Token {
+            access_token: token.access_token.clone(),
+            expires: Instant::now() + lifetime.saturating_sub(TOKEN_MARGIN),
+        });
+        Ok(token.access_token)
+    }
+
+    async fn add_entry(&self, url: &str, title: &str) -> Result<()> {
+        let client = self.http.client()?;
+        // A token revoked early is only found out when it's used, so that gets one
+        // more try with a fresh token.
+        for _ in 0..2 {
+            let token = self.access_token(&client).await?;
+            let response = client
+                .post(self.endpoint("api/entries.json"))
+                .bearer_auth(token)
+                .form(&[("url", url), ("title", title)])
+                .send()
+                .await?;
+            match response.status() {
+                StatusCode::UNAUTHORIZED => *self.token.lock().unwrap() = None,
+                status if status.is_success() => return Ok(()),
+                status if status.is_server_error() => {
+                    return Err(anyhow::Error::new(response.error_for_status().unwrap_err())
+                        .context("Wallabag couldn't save the entry"))
+                }
+                status => return Err(anyhow!("Wallabag couldn't save the entry ({})", status)),
+            }
+        }
+        Err(anyhow!("Wallabag keeps rejecting its own access token"))
+    }
+}
+
+impl ReadLaterService for Wallabag {
+    fn name(&self) -> &str {
+        "Wallabag"
+    }
+
+    fn save<'a>(&'a self, url: &'a str, title: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.add_entry(url, title))
+    }
+}
+
+/// An entry that couldn't be sent, kept in `.read_later_queue.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueuedSend {
+    pub url: String,
+    pub title: String,
+    pub attempts: u32,
+    pub last_error: String,
+}
+
+fn load_queue(path: &Path) -> Vec<QueuedSend> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save_queue(path: &Path, queue: &[QueuedSend]) -> Result<()> {
+    if queue.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    std::fs::write(path, serde_json::to_string_pretty(queue)?)?;
+    Ok(())
+}
+
+/// Sends entries to the configured service, queueing the ones that fail for
+/// reasons that may clear up so they can be retried later.
+#[derive(Clone)]
+pub struct ReadLater {
+    service: Arc<dyn ReadLaterService>,
+    queue_path: PathBuf,
+}
+
+impl ReadLater {
+    pub fn new(config: &ReadLaterConfig, http: &HttpSettings) -> ReadLater {
+        ReadLater {
+            service: config.service(http),
+            queue_path: PathBuf::from(QUEUE_PATH),
+        }
+    }
+
+    pub fn service_name(&self) -> &str {
+        self.service.name()
+    }
+
+    /// Sends the entry, or queues it if the service couldn't be reached. The error
+    /// still comes back so it can be shown.
+    pub async fn send(&self, url: &str, title: &str) -> Result<()> {
+        let error = match self.service.save(url, title).await {
+            Ok(_) => return Ok(()),
+            Err(e) if is_temporary(&e) => e,
+            Err(e) => return Err(e),
+        };
+        let mut queue = load_queue(&self.queue_path);
+        if !queue.iter().any(|queued| queued.url == url) {
+            queue.push(QueuedSend {
+                url: url.to_string(),
+                title: title.to_string(),
+                attempts: 1,
+                last_error: error.to_string(),
+            });
+            save_queue(&self.queue_path, &queue)?;
+        }
+        Err(error.context("queued to try again later"))
+    }
+
+    /// Tries every queued send again, returning how many went through, how many
+    /// are still waiting and how many the service rejected and were dropped. A
+    /// refused login stops the retry and keeps the whole queue.
+    pub async fn retry_queued(&self) -> Result<(usize, usize, usize)> {
+        let mut queue = load_queue(&self.queue_path).into_iter();
+        let mut waiting = vec![];
+        let mut sent = 0;
+        let mut dropped = 0;
+        while let Some(mut queued) = queue.next() {
+            match self.service.save(&queued.url, &queued.title).await {
+                Ok(_) => sent += 1,
+                Err(e) if e.is::<LoginRefused>() => {
+                    waiting.push(queued);
+                    waiting.extend(queue);
+                    save_queue(&self.queue_path, &waiting)?;
+                    return Err(e);
+                }
+                Err(e) if is_temporary(&e) => {
+                    queued.attempts += 1;
+                    queued.last_error = format!("{:#}", e);
+                    waiting.push(queued);
+                }
+                Err(_) => dropped += 1,
+            }
+        }
+        save_queue(&self.queue_path, &waiting)?;
+        Ok((sent, waiting.len(), dropped))
+    }
+
+    pub fn queued(&self) -> usize {
+        load_queue(&self.queue_path).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::AUTHORIZATION;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+
+    /// What the mock Wallabag answers, in order, and what it was sent.
+    #[derive(Default)]
+    struct Mock {
+        login_statuses: VecDeque<u16>,
+        entry_statuses: VecDeque<u16>,
+        logins: Vec<String>,
+        entries: Vec<(Option<String>, String)>,
+    }
+
+    async fn respond(
+        mock: Arc<Mutex<Mock>>,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, Infallible> {
+        let path = request.uri().path().to_string();
+        let authorization = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let mut mock = mock.lock().unwrap();
+        let (status, reply) = match path.as_str() {
+            "/oauth/v2/token" => {
+                mock.logins.push(body);
+                let token = format!(
+                    r#"{{"access_token":"token-{}","expires_in":3600}}"#,
+                    mock.logins.len()
+                );
+                (mock.login_statuses.pop_front().unwrap_or(200), token)
+            }
+            "/api/entries.json" => {
+                mock.entries.push((authorization, body));
+                let status = mock.entry_statuses.pop_front().unwrap_or(200);
+                (status, String::from("{}"))
+            }
+            _ => (404, String::new()),
+        };
+        Ok(Response::builder()
+            .status(status)
+            .body(Body::from(reply))
+            .unwrap())
+    }
+
+    /// Starts the mock on a free port and returns its url.
+    fn serve(mock: Arc<Mutex<Mock>>) -> String {
+        let make_service = make_service_fn(move |_| {
+            let mock = mock.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| respond(mock.clone(), request))) }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    fn read_later(url: &str, queue: &str) -> ReadLater {
+        std::env::set_var("J_RSS_TUI_TEST_SECRET", "hunter2");
+        let config = WallabagConfig {
+            url: url.to_string(),
+            client_id: String::from("client"),
+            client_secret: String::from("env:J_RSS_TUI_TEST_SECRET"),
+            username: String::from("reader"),
+            password: String::from("env:J_RSS_TUI_TEST_SECRET"),
+        };
+        let queue_path =
+            std::env::temp_dir().join(format!("j-rss-tui-{}-{}.json", queue, std::process::id()));
+        let _ = std::fs::remove_file(&queue_path);
+        ReadLater {
+            service: Arc::new(Wallabag::new(&config, &HttpSettings::default())),
+            queue_path,
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_in_once_and_reuses_the_token() {
+        let mock = Arc::new(Mutex::new(Mock::default()));
+        let read_later = read_later(&serve(mock.clone()), "token");
+        read_later.send("https://a.example/1", "One").await.unwrap();
+        read_later.send("https://a.example/2", "Two").await.unwrap();
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.logins.len(), 1);
+        assert!(mock.logins[0].contains("grant_type=password"));
+        assert!(mock.logins[0].contains("username=reader"));
+        assert!(mock.logins[0].contains("password=hunter2"));
+        assert_eq!(mock.entries.len(), 2);
+        for (authorization, _) in mock.entries.iter() {
+            assert_eq!(authorization.as_deref(), Some("Bearer token-1"));
+        }
+        assert!(mock.entries[0]
+            .1
+            .contains("url=https%3A%2F%2Fa.example%2F1"));
+    }
+
+    #[tokio::test]
+    async fn logs_in_again_when_the_token_is_revoked() {
+        let mock = Arc::new(Mutex::new(Mock {
+            entry_statuses: VecDeque::from([401]),
+            ..Mock::default()
+        }));
+        let read_later = read_later(&serve(mock.clone()), "relogin");
+        read_later.send("https://a.example/1", "One").await.unwrap();
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.logins.len(), 2);
+        assert_eq!(mock.entries[1].0.as_deref(), Some("Bearer token-2"));
+        assert_eq!(read_later.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn queues_server_errors_and_sends_them_on_retry() {
+        let mock = Arc::new(Mutex::new(Mock {
+            entry_statuses: VecDeque::from([503]),
+            ..Mock::default()
+        }));
+        let read_later = read_later(&serve(mock.clone()), "retry");
+        assert!(read_later.send("https://a.example/1", "One").await.is_err());
+        assert_eq!(read_later.queued(), 1);
+
+        assert_eq!(read_later.retry_queued().await.unwrap(), (1, 0, 0));
+        assert_eq!(read_later.queued(), 0);
+        assert!(!read_later.queue_path.exists());
+        assert_eq!(mock.lock().unwrap().entries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn queues_sends_to_a_service_that_is_down() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let read_later = read_later(&url, "down");
+        assert!(read_later.send("https://a.example/1", "One").await.is_err());
+        assert_eq!(read_later.queued(), 1);
+        let _ = std::fs::remove_file(&read_later.queue_path);
+    }
+
+    #[tokio::test]
+    async fn does_not_queue_refused_logins_or_rejected_entries() {
+        let mock = Arc::new(Mutex::new(Mock {
+            login_statuses: VecDeque::from([400]),
+            entry_statuses: VecDeque::from([422]),
+            ..Mock::default()
+        }));
+        let read_later = read_later(&serve(mock.clone()), "rejected");
+        let error = read_later
+            .send("https://a.example/1", "One")
+            .await
+            .unwrap_err();
+        assert!(error.is::<LoginRefused>());
+        assert!(read_later.send("https://a.example/1", "One").await.is_err());
+        assert_eq!(read_later.queued(), 0);
+    }
+}
@@ -0,0 +1,166 @@
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+
+/// Tags whose text is never part of the article.
+const SKIPPED_TAGS: [&str; 10] = [
+    "script", "style", "noscript", "nav", "header", "footer", "aside", "form", "button", "iframe",
+];
+
+/// Class and id fragments that suggest the article body, or something around it.
+const POSITIVE_HINTS: [&str; 8] = [
+    "article", "body", "content", "entry", "main", "page", "post", "text",
+];
+const NEGATIVE_HINTS: [&str; 12] = [
+    "comment", "sidebar", "footer", "nav", "menu", "share", "social", "related", "promo",
+    "sponsor", "banner", "widget",
+];
+
+/// A paragraph needs at least this many characters to count towards its container.
+const MIN_PARAGRAPH_LENGTH: usize = 25;
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn element_text(element: &ElementRef) -> String {
+    collapse_whitespace(&element.text().collect::<String>())
+}
+
+fn is_skipped(element: &ElementRef) -> bool {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .chain(std::iter::once(*element))
+        .any(|e| SKIPPED_TAGS.contains(&e.value().name()))
+}
+
+/// Positive or negative weight from the element's class and id.
+fn class_weight(element: &ElementRef) -> f64 {
+    let names = format!(
+        "{} {}",
+        element.value().attr("class").unwrap_or(""),
+        element.value().attr("id").unwrap_or("")
+    )
+    .to_lowercase();
+    let mut weight = 0.0;
+    if NEGATIVE_HINTS.iter().any(|hint| names.contains(hint)) {
+        weight -= 25.0;
+    }
+    if POSITIVE_HINTS.iter().any(|hint| names.contains(hint)) {
+        weight += 25.0;
+    }
+    weight
+}
+
+/// The share of an element's text that sits inside links. Menus and link lists
+/// score close to 1.
+fn link_density(element: &ElementRef) -> f64 {
+    let text_length = element_text(element).len();
+    if text_length == 0 {
+        return 1.0;
+    }
+    let links = Selector::parse("a").unwrap();
+    let link_length: usize = element
+        .select(&links)
+        .map(|link| element_text(&link).len())
+        .sum();
+    link_length as f64 / text_length as f64
+}
+
+/// Picks the element most likely to hold the article: every paragraph scores its
+/// parent fully and its grandparent by half, by length and number of commas.
+fn best_candidate(document: &Html) -> Option<ElementRef<'_>> {
+    let paragraphs = Selector::parse("p, pre, td").unwrap();
+    let mut scores = HashMap::new();
+    for paragraph in document.select(&paragraphs) {
+        if is_skipped(&paragraph) {
+            continue;
+        }
+        let text = element_text(&paragraph);
+        if text.len() < MIN_PARAGRAPH_LENGTH {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (text.len() as f64 / 100.0).min(3.0);
+        let parents = paragraph.ancestors().filter_map(ElementRef::wrap).take(2);
+        for (depth, parent) in parents.enumerate() {
+            let base = scores
+                .entry(parent.id())
+                .or_insert_with(|| class_weight(&parent));
+            *base += if depth == 0 { score } else { score / 2.0 };
+        }
+    }
+    scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((element, score * (1.0 - link_density(&element))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(element, _)| element)
+}
+
+/// True if the block, or anything between it and the candidate, looks like a share
+/// bar, comment section or similar.
+fn in_unlikely_container(block: &ElementRef, candidate: &ElementRef) -> bool {
+    std::iter::once(*block)
+        .chain(block.ancestors().filter_map(ElementRef::wrap))
+        .take_while(|element| element.id() != candidate.id())
+        .any(|element| class_weight(&element) < 0.0)
+}
+
+/// Turns the candidate into plain text, one block per paragraph, heading or list item.
+fn block_text(candidate: &ElementRef) -> String {
+    let blocks = Selector::parse("p, pre, blockquote, h1, h2, h3, h4, h5, h6, li").unwrap();
+    let mut lines: Vec<String> = vec![];
+    for block in candidate.select(&blocks) {
+        if is_skipped(&block) || in_unlikely_container(&block, candidate) {
+            continue;
+        }
+        // Nested blocks like a paragraph inside a list item are picked up on their own.
+        let has_inner_block = block
+            .descendants()
+            .skip(1)
+            .filter_map(ElementRef::wrap)
+            .any(|inner| blocks.matches(&inner));
+        if has_inner_block {
+            continue;
+        }
+        let text = match block.value().name() {
+            "pre" => block.text().collect::<String>().trim_end().to_string(),
+            _ => element_text(&block),
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let line = match block.value().name() {
+            "li" => format!("• {}", text),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => text.to_uppercase(),
+            _ => text,
+        };
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        return element_text(candidate);
+    }
+    lines.join("\n\n")
+}
+
+/// Plain text for an html fragment such as a feed item's content, keeping its
+/// paragraphs apart.
+pub fn html_to_text(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    block_text(&fragment.root_element())
+}
+
+/// Pulls the main article text out of a web page, or nothing if the page doesn't
+/// seem to have one.
+pub fn extract_article(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let candidate = best_candidate(&document)?;
+    let text = block_text(&candidate);
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
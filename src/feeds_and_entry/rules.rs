@@ -0,0 +1,234 @@
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::scripts::Scripts;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// The part of an entry a pattern is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Field {
+    Title,
+    Author,
+    Category,
+    Feed,
+    Content,
+}
+
+impl Field {
+    pub fn from_name(name: &str) -> Result<Field> {
+        let field = match name {
+            "title" => Field::Title,
+            "author" => Field::Author,
+            "category" => Field::Category,
+            "feed" => Field::Feed,
+            "content" => Field::Content,
+            _ => return Err(anyhow!("unknown field {:?}", name)),
+        };
+        Ok(field)
+    }
+}
+
+/// When a rule fires, written in the session file as e.g.
+/// `{ "any": [{ "matches": { "field": "title", "pattern": "(?i)sponsored" } }] }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Condition {
+    Matches { field: Field, pattern: String },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+/// What a rule does to the entries it matches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuleAction {
+    Hide,
+    MarkRead,
+    Star,
+    Tag(String),
+}
+
+impl RuleAction {
+    pub fn from_name(name: &str) -> Result<RuleAction> {
+        let action = match name {
+            "hide" => RuleAction::Hide,
+            "read" | "mark-read" => RuleAction::MarkRead,
+            "star" => RuleAction::Star,
+            other => match other.strip_prefix("tag:") {
+                Some(tag) if !tag.is_empty() => RuleAction::Tag(tag.to_string()),
+                _ => return Err(anyhow!("unknown rule action {:?}", name)),
+            },
+        };
+        Ok(action)
+    }
+
+    pub fn get_name(&self) -> String {
+        match self {
+            RuleAction::Hide => String::from("hide"),
+            RuleAction::MarkRead => String::from("mark read"),
+            RuleAction::Star => String::from("star"),
+            RuleAction::Tag(tag) => format!("tag {}", tag),
+        }
+    }
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    pub condition: Condition,
+    pub actions: Vec<RuleAction>,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+impl Rule {
+    pub fn new(name: &str, condition: Condition, actions: Vec<RuleAction>) -> Rule {
+        Rule {
+            name: String::from(name),
+            condition,
+            actions,
+            enabled: true,
+        }
+    }
+}
+
+pub(crate) enum Matcher {
+    Matches(Field, Regex),
+    All(Vec<Matcher>),
+    Any(Vec<Matcher>),
+    Not(Box<Matcher>),
+}
+
+impl Matcher {
+    pub(crate) fn compile(condition: &Condition) -> Result<Matcher> {
+        let matcher = match condition {
+            Condition::Matches { field, pattern } => Matcher::Matches(
+                *field,
+                Regex::new(pattern).map_err(|e| anyhow!("bad pattern {:?}: {}", pattern, e))?,
+            ),
+            Condition::All(conditions) => Matcher::All(
+                conditions
+                    .iter()
+                    .map(Matcher::compile)
+                    .collect::<Result<_>>()?,
+            ),
+            Condition::Any(conditions) => Matcher::Any(
+                conditions
+                    .iter()
+                    .map(Matcher::compile)
+                    .collect::<Result<_>>()?,
+            ),
+            Condition::Not(condition) => Matcher::Not(Box::new(Matcher::compile(condition)?)),
+        };
+        Ok(matcher)
+    }
+
+    pub(crate) fn is_match(&self, entry: &Entry, feed_name: &str) -> bool {
+        match self {
+            Matcher::Matches(field, regex) => match field {
+                Field::Title => regex.is_match(&entry.title),
+                Field::Author => regex.is_match(&entry.authors),
+                Field::Category => entry.categories.iter().any(|c| regex.is_match(c)),
+                Field::Feed => regex.is_match(feed_name),
+                Field::Content => {
+                    regex.is_match(&entry.blurb)
+                        || entry.content.as_ref().is_some_and(|c| regex.is_match(c))
+                }
+            },
+            Matcher::All(matchers) => matchers.iter().all(|m| m.is_match(entry, feed_name)),
+            Matcher::Any(matchers) => matchers.iter().any(|m| m.is_match(entry, feed_name)),
+            Matcher::Not(matcher) => !matcher.is_match(entry, feed_name),
+        }
+    }
+}
+
+/// The enabled rules of a session with their patterns compiled, ready to run
+/// over a batch of entries, along with any script transforms.
+pub struct RuleSet {
+    rules: Vec<(Matcher, Vec<RuleAction>)>,
+    scripts: Option<Scripts>,
+}
+
+impl RuleSet {
+    /// Fails on the first rule that doesn't compile.
+    pub fn new(rules: &[Rule]) -> Result<RuleSet> {
+        let (rule_set, broken) = RuleSet::skipping_broken(rules);
+        match broken.into_iter().next() {
+            Some(error) => Err(anyhow!(error)),
+            None => Ok(rule_set),
+        }
+    }
+
+    /// Leaves out the rules that don't compile, returning why each one didn't, so
+    /// one bad pattern doesn't stop every refresh.
+    pub fn skipping_broken(rules: &[Rule]) -> (RuleSet, Vec<String>) {
+        let mut compiled = vec![];
+        let mut broken = vec![];
+        for rule in rules.iter().filter(|rule| rule.enabled) {
+            match Matcher::compile(&rule.condition) {
+                Ok(matcher) => compiled.push((matcher, rule.actions.clone())),
+                Err(e) => broken.push(format!("rule {:?}: {}", rule.name, e)),
+            }
+        }
+        let rule_set = RuleSet {
+            rules: compiled,
+            scripts: None,
+        };
+        (rule_set, broken)
+    }
+
+    /// Also runs the scripts' transforms over every fetched entry.
+    pub fn with_scripts(mut self, scripts: &Scripts) -> RuleSet {
+        self.scripts = Some(scripts.clone());
+        self
+    }
+
+    /// Runs the script transforms over an entry as it comes in, new or not, so a
+    /// rewritten url still matches the stored copy.
+    pub fn transform(&self, entry: &mut Entry, feed_name: &str) {
+        if let Some(scripts) = &self.scripts {
+            scripts.transform(entry, feed_name);
+        }
+    }
+
+    /// Runs every rule over the entry, returning true if any of them matched.
+    pub fn apply(&self, entry: &mut Entry, feed_name: &str) -> bool {
+        let mut matched = false;
+        for (matcher, actions) in self.rules.iter() {
+            if !matcher.is_match(entry, feed_name) {
+                continue;
+            }
+            matched = true;
+            for action in actions {
+                match action {
+                    RuleAction::Hide => entry.hidden = true,
+                    RuleAction::MarkRead => entry.read = true,
+                    RuleAction::Star => entry.starred = true,
+                    RuleAction::Tag(tag) => {
+                        if !entry.tags.contains(tag) {
+                            entry.tags.push(tag.clone());
+                        }
+                    }
+                }
+            }
+        }
+        matched
+    }
+}
+
+/// How many of the given entries a single rule matches, whether or not it's enabled.
+pub fn count_matches<'a>(
+    rule: &Rule,
+    entries: impl Iterator<Item = (&'a Entry, &'a str)>,
+) -> Result<usize> {
+    let matcher = Matcher::compile(&rule.condition)?;
+    Ok(entries
+        .filter(|(entry, feed_name)| matcher.is_match(entry, feed_name))
+        .count())
+}
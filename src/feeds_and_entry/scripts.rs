@@ -0,0 +1,442 @@
+//! User scripts written in Rhai, loaded from `.scripts/*.rhai` next to the config.
+//!
+//! A script can define any of:
+//!
+//! - `fn transform(entry, feed)`: runs on every entry as it's fetched, before
+//!   it's matched against the entries already stored. Return the entry with
+//!   changes, e.g. a rewritten title or a url without tracking parameters.
+//! - `fn command_NAME(view)`: run with `:script NAME` or a key from the
+//!   `scripts.keys` config. `view` has the `entries` being shown, the
+//!   `selected` entry and the `feeds`.
+//! - `fn view_NAME(entries)`: shown with `:view NAME`. Returns the entries to
+//!   list, in order.
+//!
+//! Entries are maps with `title`, `url`, `authors`, `summary`, `content`,
+//! `published`, `feed`, `tags`, `categories`, `read`, `starred` and `hidden`.
+//! Commands change the session with `mark_read(url, bool)`,
+//! `set_starred(url, bool)`, `add_tag(url, tag)` and `hide(url)`; `print`
+//! goes to the message log. Scripts can't touch files, the network or other
+//! processes, and each call is cut off after a fixed number of operations.
+
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::feeds::BlogFeed;
+use crate::session_and_user::session::Session;
+use anyhow::{anyhow, Result};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const SCRIPTS_DIR: &str = ".scripts";
+/// Enough for a view over a few thousand entries, not enough to hang the app.
+const MAX_OPERATIONS: u64 = 5_000_000;
+
+const TRANSFORM: &str = "transform";
+const COMMAND_PREFIX: &str = "command_";
+const VIEW_PREFIX: &str = "view_";
+
+/// The `scripts` section of the config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ScriptConfig {
+    /// Maps a script command such as `mark-old-read` to the keys that run it.
+    pub keys: HashMap<String, Vec<String>>,
+}
+
+/// A change a command asked for, made once it has finished.
+#[derive(Debug, Clone)]
+enum ScriptAction {
+    SetRead(String, bool),
+    SetStarred(String, bool),
+    AddTag(String, String),
+    Hide(String),
+}
+
+/// The loaded scripts. Clones share the engine and the message log.
+#[derive(Clone)]
+pub struct Scripts {
+    engine: Arc<Engine>,
+    /// Each file's name and compiled script, in name order.
+    scripts: Arc<Vec<(String, AST)>>,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+    messages: Arc<Mutex<Vec<String>>>,
+    errors: Arc<Mutex<Vec<String>>>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Scripts {
+    /// No scripts at all.
+    pub fn none() -> Scripts {
+        let actions = Arc::new(Mutex::new(vec![]));
+        let messages = Arc::new(Mutex::new(vec![]));
+        Scripts {
+            engine: Arc::new(sandboxed_engine(actions.clone(), messages.clone())),
+            scripts: Arc::new(vec![]),
+            actions,
+            messages,
+            errors: Arc::new(Mutex::new(vec![])),
+            last_error: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Compiles every script in the scripts directory. A script that doesn't
+    /// compile is left out and its error kept for the message log.
+    pub fn load() -> Scripts {
+        let mut scripts = Scripts::none();
+        let mut paths: Vec<_> = match std::fs::read_dir(SCRIPTS_DIR) {
+            Ok(dir) => dir
+                .flatten()
+                .map(|file| file.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+                .collect(),
+            Err(_) => return scripts,
+        };
+        paths.sort();
+        let mut compiled = vec![];
+        for path in paths {
+            let name = script_name(&path);
+            match std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| scripts.engine.compile(text).map_err(|e| e.to_string()))
+            {
+                Ok(ast) => compiled.push((name, ast)),
+                Err(e) => scripts.report_error(format!("script {} not loaded: {}", name, e)),
+            }
+        }
+        scripts.scripts = Arc::new(compiled);
+        scripts
+    }
+
+    /// Errors since the last call, and anything the scripts printed.
+    pub fn take_messages(&self) -> (Vec<String>, Vec<String>) {
+        (
+            std::mem::take(&mut *self.messages.lock().unwrap()),
+            std::mem::take(&mut *self.errors.lock().unwrap()),
+        )
+    }
+
+    /// Keeps an error for the message log. A failing transform or view fails the
+    /// same way on every entry or redraw, so an error repeating the last one is
+    /// dropped.
+    pub fn report_error(&self, message: String) {
+        let mut last_error = self.last_error.lock().unwrap();
+        if last_error.as_ref() == Some(&message) {
+            return;
+        }
+        *last_error = Some(message.clone());
+        self.errors.lock().unwrap().push(message);
+    }
+
+    /// The names of the commands the scripts define, as typed after `:script`.
+    pub fn commands(&self) -> Vec<String> {
+        self.names(COMMAND_PREFIX)
+    }
+
+    /// The names of the views the scripts define, as typed after `:view`.
+    pub fn views(&self) -> Vec<String> {
+        self.names(VIEW_PREFIX)
+    }
+
+    fn names(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .scripts
+            .iter()
+            .flat_map(|(_, ast)| ast.iter_functions())
+            .filter_map(|function| function.name.strip_prefix(prefix).map(display_name))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The first script defining the function, if any does.
+    fn find(&self, function: &str, params: usize) -> Option<&(String, AST)> {
+        self.scripts.iter().find(|(_, ast)| {
+            ast.iter_functions()
+                .any(|f| f.name == function && f.params.len() == params)
+        })
+    }
+
+    /// Runs every script's `transform` over the entry, in turn.
+    pub fn transform(&self, entry: &mut Entry, feed_name: &str) {
+        for (name, ast) in self.scripts.iter() {
+            if !ast
+                .iter_functions()
+                .any(|f| f.name == TRANSFORM && f.params.len() == 2)
+            {
+                continue;
+            }
+            let result = self.engine.call_fn::<Dynamic>(
+                &mut Scope::new(),
+                ast,
+                TRANSFORM,
+                (entry_map(entry, feed_name), feed_name.to_string()),
+            );
+            match result {
+                // Returning anything but a map, such as nothing, leaves the entry alone.
+                Ok(changed) => {
+                    if let Some(changed) = changed.try_cast::<Map>() {
+                        update_entry(entry, &changed);
+                    }
+                }
+                Err(e) => self.report_error(format!("{}: transform failed: {}", name, e)),
+            }
+        }
+    }
+
+    /// Runs a script command, then makes the changes it asked for. Returns how
+    /// many entries it changed.
+    pub fn run_command(
+        &self,
+        command: &str,
+        session: &mut Session,
+        shown: &[Entry],
+        selected: Option<&Entry>,
+    ) -> Result<usize> {
+        let function = format!("{}{}", COMMAND_PREFIX, function_name(command));
+        let (name, ast) = self
+            .find(&function, 1)
+            .ok_or_else(|| anyhow!("no script defines a command called {:?}", command))?;
+        let mut view = Map::new();
+        view.insert("entries".into(), entry_array(shown).into());
+        view.insert(
+            "selected".into(),
+            selected
+                .map(|entry| Dynamic::from_map(entry_map(entry, &entry.feed_name)))
+                .unwrap_or(Dynamic::UNIT),
+        );
+        let unread = session.unread_counts();
+        view.insert(
+            "feeds".into(),
+            session
+                .blog_feeds
+                .iter()
+                .map(|feed| Dynamic::from_map(feed_map(feed, unread.feed(&feed.name))))
+                .collect::<Array>()
+                .into(),
+        );
+        self.actions.lock().unwrap().clear();
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), ast, &function, (view,));
+        let actions = std::mem::take(&mut *self.actions.lock().unwrap());
+        if let Err(e) = result {
+            return Err(anyhow!("{}: {} failed: {}", name, command, e));
+        }
+        let changed = actions.len();
+        // Read and starred changes follow copies of the entry in other feeds, which
+        // is slow to work out, so they're applied together at the end.
+        let mut read = vec![];
+        let mut starred = vec![];
+        for action in actions {
+            match action {
+                ScriptAction::SetRead(url, value) => read.push((url, value)),
+                ScriptAction::SetStarred(url, value) => starred.push((url, value)),
+                ScriptAction::AddTag(url, tag) => session.tag_entry(&url, &tag),
+                ScriptAction::Hide(url) => session.hide_entry(&url),
+            }
+        }
+        session.set_entries_read(read);
+        session.set_entries_starred(starred);
+        Ok(changed)
+    }
+
+    /// The entries a script view picks out of `entries`, in the order it gave them.
+    pub fn view(&self, view: &str, entries: Vec<Entry>) -> Result<Vec<Entry>> {
+        let function = format!("{}{}", VIEW_PREFIX, function_name(view));
+        let (name, ast) = self
+            .find(&function, 1)
+            .ok_or_else(|| anyhow!("no script defines a view called {:?}", view))?;
+        let picked: Array = self
+            .engine
+            .call_fn(&mut Scope::new(), ast, &function, (entry_array(&entries),))
+            .map_err(|e| anyhow!("{}: view {} failed: {}", name, view, e))?;
+        let mut by_url: HashMap<String, Entry> = entries
+            .into_iter()
+            .map(|entry| (entry.url.clone(), entry))
+            .collect();
+        Ok(picked
+            .into_iter()
+            .filter_map(|entry| entry.try_cast::<Map>())
+            .filter_map(|entry| by_url.remove(&string_field(&entry, "url")?))
+            .collect())
+    }
+}
+
+/// An engine that can't reach outside the app: no modules from disk, no `eval`,
+/// and limits on how long and how deep a call can go.
+fn sandboxed_engine(
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+    messages: Arc<Mutex<Vec<String>>>,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1 << 20);
+    engine.set_max_array_size(100_000);
+    engine.set_max_map_size(10_000);
+
+    let printed = messages.clone();
+    engine.on_print(move |text| printed.lock().unwrap().push(text.to_string()));
+    engine.on_debug(move |text, _, _| messages.lock().unwrap().push(text.to_string()));
+
+    let queue = actions.clone();
+    engine.register_fn("mark_read", move |url: &str, read: bool| {
+        queue
+            .lock()
+            .unwrap()
+            .push(ScriptAction::SetRead(url.to_string(), read))
+    });
+    let queue = actions.clone();
+    engine.register_fn("set_starred", move |url: &str, starred: bool| {
+        queue
+            .lock()
+            .unwrap()
+            .push(ScriptAction::SetStarred(url.to_string(), starred))
+    });
+    let queue = actions.clone();
+    engine.register_fn("add_tag", move |url: &str, tag: &str| {
+        queue
+            .lock()
+            .unwrap()
+            .push(ScriptAction::AddTag(url.to_string(), tag.to_string()))
+    });
+    engine.register_fn("hide", move |url: &str| {
+        actions
+            .lock()
+            .unwrap()
+            .push(ScriptAction::Hide(url.to_string()))
+    });
+    engine
+}
+
+fn script_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// `mark_old_read` is typed as `mark-old-read`.
+fn display_name(function: &str) -> String {
+    function.replace('_', "-")
+}
+
+fn function_name(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+fn strings(values: &[String]) -> Dynamic {
+    values
+        .iter()
+        .map(|value| Dynamic::from(value.clone()))
+        .collect::<Array>()
+        .into()
+}
+
+fn entry_map(entry: &Entry, feed_name: &str) -> Map {
+    let mut map = Map::new();
+    map.insert("title".into(), entry.title.clone().into());
+    map.insert("url".into(), entry.url.clone().into());
+    map.insert("authors".into(), entry.authors.clone().into());
+    map.insert("summary".into(), entry.blurb.clone().into());
+    map.insert(
+        "content".into(),
+        entry.content.clone().map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map.insert(
+        "published".into(),
+        entry
+            .updated()
+            .map_or(Dynamic::UNIT, |date| date.to_rfc3339().into()),
+    );
+    map.insert("feed".into(), feed_name.to_string().into());
+    map.insert("tags".into(), strings(&entry.tags));
+    map.insert("categories".into(), strings(&entry.categories));
+    map.insert("read".into(), entry.read.into());
+    map.insert("starred".into(), entry.starred.into());
+    map.insert("hidden".into(), entry.hidden.into());
+    map
+}
+
+fn entry_array(entries: &[Entry]) -> Array {
+    entries
+        .iter()
+        .map(|entry| Dynamic::from_map(entry_map(entry, &entry.feed_name)))
+        .collect()
+}
+
+fn feed_map(feed: &BlogFeed, unread: usize) -> Map {
+    let mut map = Map::new();
+    map.insert("name".into(), feed.name.clone().into());
+    map.insert("url".into(), feed.url.clone().into());
+    map.insert(
+        "folder".into(),
+        feed.folder.clone().map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map.insert("unread".into(), (unread as i64).into());
+    map.insert(
+        "last_error".into(),
+        feed.last_error.clone().map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map
+}
+
+fn string_field(map: &Map, key: &str) -> Option<String> {
+    map.get(key)?.clone().into_string().ok()
+}
+
+fn strings_field(map: &Map, key: &str) -> Option<Vec<String>> {
+    let array = map.get(key)?.clone().into_array().ok()?;
+    Some(
+        array
+            .into_iter()
+            .filter_map(|value| value.into_string().ok())
+            .collect(),
+    )
+}
+
+/// Takes back the fields a transform may change. Anything missing or of the
+/// wrong type is left as it was.
+fn update_entry(entry: &mut Entry, map: &Map) {
+    if let Some(title) = string_field(map, "title") {
+        entry.title = title;
+    }
+    if let Some(url) = string_field(map, "url") {
+        entry.url = url;
+    }
+    if let Some(authors) = string_field(map, "authors") {
+        entry.authors = authors;
+    }
+    if let Some(summary) = string_field(map, "summary") {
+        entry.blurb = summary;
+    }
+    match map.get("content") {
+        Some(content) if content.is_unit() => entry.content = None,
+        Some(_) => {
+            if let Some(content) = string_field(map, "content") {
+                entry.content = Some(content);
+            }
+        }
+        None => {}
+    }
+    if let Some(tags) = strings_field(map, "tags") {
+        entry.tags = tags;
+    }
+    if let Some(categories) = strings_field(map, "categories") {
+        entry.categories = categories;
+    }
+    for (key, flag) in [
+        ("read", &mut entry.read),
+        ("starred", &mut entry.starred),
+        ("hidden", &mut entry.hidden),
+    ] {
+        if let Some(value) = map.get(key).and_then(|value| value.as_bool().ok()) {
+            *flag = value;
+        }
+    }
+}
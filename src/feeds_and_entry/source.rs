@@ -0,0 +1,131 @@
+use crate::feeds_and_entry::http::HttpSettings;
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// How long an `exec:` command gets to print its feed before it's killed, unless
+/// `exec_timeout_secs` says otherwise.
+const DEFAULT_EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a feed's document comes from, worked out from `BlogFeed.url`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeedSource {
+    Http(String),
+    /// A `file://` url or a plain path on disk.
+    File(PathBuf),
+    /// `exec:<command>`: a shell command whose output is the feed, for wrapping
+    /// scrapers and tools that aren't feeds themselves.
+    Exec(String),
+}
+
+impl FeedSource {
+    pub fn parse(location: &str) -> Result<FeedSource> {
+        let location = location.trim();
+        if let Some(command) = location.strip_prefix("exec:") {
+            if command.trim().is_empty() {
+                return Err(anyhow!("exec: needs a command to run"));
+            }
+            return Ok(FeedSource::Exec(command.trim().to_string()));
+        }
+        let lower = location.to_lowercase();
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            return Ok(FeedSource::Http(location.to_string()));
+        }
+        if lower.starts_with("file://") {
+            let path = Url::parse(location)?
+                .to_file_path()
+                .map_err(|_| anyhow!("{} isn't a local file", location))?;
+            return Ok(FeedSource::File(path));
+        }
+        if location.contains("://") {
+            return Err(anyhow!("don't know how to fetch {}", location));
+        }
+        Ok(FeedSource::File(expand_home(location)?))
+    }
+
+    /// True for http and https feeds. Feeds from anywhere but the user, like an
+    /// imported OPML file or the sync server, must be these, since the other
+    /// sources read local files or run commands.
+    pub fn is_web(location: &str) -> bool {
+        matches!(FeedSource::parse(location), Ok(FeedSource::Http(_)))
+    }
+
+    /// The raw feed document. `http` only applies to web feeds, apart from the
+    /// timeout for `exec:` commands.
+    pub async fn fetch(&self, http: &HttpSettings) -> Result<Document> {
+        match self {
+            FeedSource::Http(url) => {
+                let fetched = http.fetch(url).await?;
+                Ok(Document {
+                    content: fetched.response.bytes().await?.to_vec(),
+                    moved_to: fetched.moved_to,
+                })
+            }
+            FeedSource::File(path) => Ok(Document {
+                content: std::fs::read(path)
+                    .map_err(|e| anyhow!("couldn't read {}: {}", path.display(), e))?,
+                moved_to: None,
+            }),
+            FeedSource::Exec(command) => Ok(Document {
+                content: run_command(
+                    command,
+                    http.exec_timeout_secs
+                        .map(Duration::from_secs)
+                        .unwrap_or(DEFAULT_EXEC_TIMEOUT),
+                )
+                .await?,
+                moved_to: None,
+            }),
+        }
+    }
+}
+
+/// The path with a leading `~/` swapped for the home directory.
+pub fn expand_home(path: &str) -> Result<PathBuf> {
+    match path.strip_prefix("~/") {
+        Some(rest) => Ok(PathBuf::from(
+            std::env::var("HOME").map_err(|_| anyhow!("HOME isn't set"))?,
+        )
+        .join(rest)),
+        None => Ok(PathBuf::from(path)),
+    }
+}
+
+/// A fetched feed document.
+pub struct Document {
+    pub content: Vec<u8>,
+    /// Set when a web feed was permanently redirected somewhere else.
+    pub moved_to: Option<String>,
+}
+
+/// Runs the command through the shell and returns what it printed. A failure or
+/// timeout comes back as an error carrying whatever it wrote to stderr.
+async fn run_command(command: &str, timeout: Duration) -> Result<Vec<u8>> {
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("couldn't run {:?}: {}", command, e))?;
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| anyhow!("{:?} took longer than {}s", command, timeout.as_secs()))??;
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let problem = if !output.status.success() {
+        Some(format!("failed with {}", output.status))
+    } else if output.stdout.iter().all(|byte| byte.is_ascii_whitespace()) {
+        Some(String::from("printed nothing"))
+    } else {
+        None
+    };
+    match (problem, stderr.is_empty()) {
+        (None, _) => Ok(output.stdout),
+        (Some(problem), true) => Err(anyhow!("{:?} {}", command, problem)),
+        (Some(problem), false) => Err(anyhow!("{:?} {}: {}", command, problem, stderr)),
+    }
+}
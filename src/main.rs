@@ -11,23 +11,38 @@ use tui::{
     Terminal,
 };
 // Our stuff
+mod config_and_keymap;
 mod session_and_user;
 mod feeds_and_entry;
 mod ui;
 mod app;
+mod server;
 use app::App;
 use crate::session_and_user::session::Session;
+use crate::config_and_keymap::config::Config;
+use crate::feeds_and_entry::hooks::Hooks;
+use crate::feeds_and_entry::scripts::Scripts;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let tick_rate = Duration::from_millis(30);
+    // Load the config before touching the terminal so a bad file reports cleanly.
+    let config = Config::load_from_json()?;
+    if std::env::args().nth(1).as_deref() == Some("serve") {
+        let session = Session::load_from_json()
+            .map_err(|e| anyhow::anyhow!("no session to serve, run the TUI first ({})", e))?;
+        return server::serve(session, config).await;
+    }
+    if std::env::args().nth(1).as_deref() == Some("digest") {
+        return digest(config).await;
+    }
+    let session = Session::load_from_json();
+    let mut app = App::new(session.ok(), config)?;
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen,EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    let session = Session::load_from_json();
-    let mut app = App::new(session.ok());
     terminal.clear()?;
     app.run(&mut terminal, tick_rate)?;
     disable_raw_mode()?;
@@ -38,5 +53,62 @@ async fn main() -> Result<()> {
     )?;
     terminal.show_cursor()?;
     terminal.set_cursor(0, 0)?;
+    app.finished().await;
+    Ok(())
+}
+
+/// `j-rss-tui digest [--refresh] [--force]`, for cron: writes a digest if one is
+/// due, refreshing the feeds first with `--refresh`.
+async fn digest(config: Config) -> Result<()> {
+    let flags: Vec<String> = std::env::args().skip(2).collect();
+    if let Some(flag) = flags
+        .iter()
+        .find(|flag| *flag != "--refresh" && *flag != "--force")
+    {
+        return Err(anyhow::anyhow!(
+            "unknown option {}, expected --refresh or --force",
+            flag
+        ));
+    }
+    let digest = config.digest.clone().ok_or_else(|| {
+        anyhow::anyhow!("no digest is set up, add one under digest in the config")
+    })?;
+    let mut session = Session::load_from_json()
+        .map_err(|e| anyhow::anyhow!("no session to digest, run the TUI first ({})", e))?;
+    if !flags.iter().any(|flag| flag == "--force") && !digest.every.is_due(session.last_digest) {
+        if let Some(last) = session.last_digest {
+            println!(
+                "No digest due until {}",
+                digest.every.next_after(last).format("%Y-%m-%d %H:%M UTC")
+            );
+        }
+        return Ok(());
+    }
+    if flags.iter().any(|flag| flag == "--refresh") {
+        let hooks = Hooks::new(&config.hooks)?;
+        let scripts = Scripts::load();
+        let result = session
+            .fetch_all_blog_entries(false, &config.http, &hooks, &scripts)
+            .await;
+        hooks.finished().await;
+        let (printed, errors) = scripts.take_messages();
+        for message in hooks
+            .take_failures()
+            .into_iter()
+            .chain(printed)
+            .chain(errors)
+        {
+            eprintln!("{}", message);
+        }
+        if let Err(e) = result {
+            eprintln!("{:#}", e);
+        }
+        for broken in session.take_broken_rules() {
+            eprintln!("Skipped {}", broken);
+        }
+    }
+    let report = digest.run(&mut session)?;
+    session.dump_to_json();
+    println!("{}", report);
     Ok(())
 }
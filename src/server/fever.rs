@@ -0,0 +1,280 @@
+//! The Fever API (version 3), which most mobile feed readers can talk to.
+//! See https://feedafever.com/api for the original description.
+
+use super::{constant_time_eq, error, reply, Server};
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::feeds::BlogFeed;
+use crate::feeds_and_entry::http;
+use crate::session_and_user::session::Session;
+use anyhow::Result;
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Fever clients page through items this many at a time.
+const ITEMS_PER_REQUEST: usize = 50;
+
+/// A stable number for a feed or folder name, since Fever wants numeric ids.
+/// 0 is left for the "all feeds" group.
+fn numeric_id(name: &str) -> u64 {
+    let digest = md5::compute(name.as_bytes());
+    (u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as u64).max(1)
+}
+
+fn joined(ids: impl Iterator<Item = u64>) -> String {
+    ids.map(|id| id.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// The visible entries of every feed, with the feed each is in.
+fn all_entries(feeds: &[BlogFeed]) -> Vec<(&BlogFeed, &Entry)> {
+    feeds
+        .iter()
+        .flat_map(|feed| {
+            feed.entries
+                .iter()
+                .flatten()
+                .filter(|entry| !entry.hidden && entry.id.is_some())
+                .map(move |entry| (feed, entry))
+        })
+        .collect()
+}
+
+fn item_json(feed: &BlogFeed, entry: &Entry) -> Value {
+    json!({
+        "id": entry.id,
+        "feed_id": numeric_id(&feed.name),
+        "title": entry.title,
+        "author": entry.authors,
+        "html": entry.content.clone().unwrap_or_else(|| entry.blurb.clone()),
+        "url": entry.url,
+        "is_saved": entry.starred as u8,
+        "is_read": entry.read as u8,
+        "created_on_time": entry.updated().map(|date| date.timestamp()).unwrap_or(0),
+    })
+}
+
+/// Which feeds are in which group, as Fever's comma separated lists.
+fn feeds_groups(feeds: &[BlogFeed]) -> Vec<Value> {
+    let mut groups: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for feed in feeds {
+        if let Some(folder) = &feed.folder {
+            groups
+                .entry(folder.clone())
+                .or_default()
+                .push(numeric_id(&feed.name));
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(folder, feed_ids)| {
+            json!({
+                "group_id": numeric_id(&folder),
+                "feed_ids": joined(feed_ids.into_iter()),
+            })
+        })
+        .collect()
+}
+
+impl Server {
+    fn fever_authorized(&self, parameters: &HashMap<String, String>) -> bool {
+        let fever = match &self.config.serve.fever {
+            Some(fever) => fever,
+            None => return false,
+        };
+        let password = match http::resolve_secret(&fever.password) {
+            Ok(password) => password,
+            Err(_) => return false,
+        };
+        let expected = format!(
+            "{:x}",
+            md5::compute(format!("{}:{}", fever.username, password))
+        );
+        parameters
+            .get("api_key")
+            .is_some_and(|key| constant_time_eq(key.to_lowercase().as_bytes(), expected.as_bytes()))
+    }
+
+    pub(super) async fn fever(&self, request: Request<Body>) -> Result<Response<Body>> {
+        if self.config.serve.fever.is_none() {
+            return Ok(error(
+                StatusCode::NOT_FOUND,
+                "the Fever endpoint is off, add fever to the serve config",
+            ));
+        }
+        let mut parameters = super::parameters(request.uri().query().unwrap_or(""));
+        let body = hyper::body::to_bytes(request.into_body()).await?;
+        parameters.extend(super::parameters(&String::from_utf8_lossy(&body)));
+
+        let mut response = Map::new();
+        response.insert(String::from("api_version"), json!(3));
+        if !self.fever_authorized(&parameters) {
+            response.insert(String::from("auth"), json!(0));
+            return Ok(reply(StatusCode::OK, Value::Object(response)));
+        }
+        response.insert(String::from("auth"), json!(1));
+
+        let mut session = self.session.lock().await;
+        if parameters.contains_key("mark") {
+            self.fever_mark(&mut session, &parameters);
+            session.dump_to_json();
+        }
+        let feeds = &session.blog_feeds;
+        let last_refreshed = feeds
+            .iter()
+            .filter_map(|feed| feed.health.last_success)
+            .max()
+            .map(|date| date.timestamp())
+            .unwrap_or(0);
+        response.insert(
+            String::from("last_refreshed_on_time"),
+            json!(last_refreshed),
+        );
+
+        if parameters.contains_key("groups") {
+            let mut folders: Vec<&String> = feeds
+                .iter()
+                .filter_map(|feed| feed.folder.as_ref())
+                .collect();
+            folders.sort();
+            folders.dedup();
+            let groups: Vec<Value> = folders
+                .into_iter()
+                .map(|folder| json!({ "id": numeric_id(folder), "title": folder }))
+                .collect();
+            response.insert(String::from("groups"), json!(groups));
+            response.insert(String::from("feeds_groups"), json!(feeds_groups(feeds)));
+        }
+        if parameters.contains_key("feeds") {
+            let list: Vec<Value> = feeds
+                .iter()
+                .map(|feed| {
+                    json!({
+                        "id": numeric_id(&feed.name),
+                        "favicon_id": 0,
+                        "title": feed.name,
+                        "url": feed.url,
+                        "site_url": feed.metadata.site_link.clone().unwrap_or_default(),
+                        "is_spark": 0,
+                        "last_updated_on_time": feed
+                            .health
+                            .last_success
+                            .map(|date| date.timestamp())
+                            .unwrap_or(0),
+                    })
+                })
+                .collect();
+            response.insert(String::from("feeds"), json!(list));
+            response.insert(String::from("feeds_groups"), json!(feeds_groups(feeds)));
+        }
+        if parameters.contains_key("favicons") {
+            response.insert(String::from("favicons"), json!([]));
+        }
+        if parameters.contains_key("links") {
+            response.insert(String::from("links"), json!([]));
+        }
+
+        let entries = all_entries(feeds);
+        if parameters.contains_key("items") {
+            let id_of = |entry: &Entry| entry.id.unwrap_or(0);
+            let number = |name: &str| parameters.get(name).and_then(|id| id.parse::<u64>().ok());
+            let mut chosen: Vec<&(&BlogFeed, &Entry)> =
+                if let Some(with_ids) = parameters.get("with_ids") {
+                    let wanted: Vec<u64> = with_ids
+                        .split(',')
+                        .filter_map(|id| id.trim().parse().ok())
+                        .collect();
+                    entries
+                        .iter()
+                        .filter(|(_, entry)| wanted.contains(&id_of(entry)))
+                        .collect()
+                } else if let Some(max_id) = number("max_id") {
+                    entries
+                        .iter()
+                        .filter(|(_, entry)| id_of(entry) < max_id)
+                        .collect()
+                } else {
+                    let since_id = number("since_id").unwrap_or(0);
+                    entries
+                        .iter()
+                        .filter(|(_, entry)| id_of(entry) > since_id)
+                        .collect()
+                };
+            // Newest first when paging back with max_id, oldest first otherwise.
+            chosen.sort_by_key(|(_, entry)| id_of(entry));
+            if parameters.contains_key("max_id") {
+                chosen.reverse();
+            }
+            let items: Vec<Value> = chosen
+                .into_iter()
+                .take(ITEMS_PER_REQUEST)
+                .map(|(feed, entry)| item_json(feed, entry))
+                .collect();
+            response.insert(String::from("items"), json!(items));
+            response.insert(String::from("total_items"), json!(entries.len()));
+        }
+        if parameters.contains_key("unread_item_ids") {
+            let unread = entries
+                .iter()
+                .filter(|(_, entry)| !entry.read)
+                .filter_map(|(_, entry)| entry.id);
+            response.insert(String::from("unread_item_ids"), json!(joined(unread)));
+        }
+        if parameters.contains_key("saved_item_ids") {
+            let saved = entries
+                .iter()
+                .filter(|(_, entry)| entry.starred)
+                .filter_map(|(_, entry)| entry.id);
+            response.insert(String::from("saved_item_ids"), json!(joined(saved)));
+        }
+        Ok(reply(StatusCode::OK, Value::Object(response)))
+    }
+
+    /// `mark=item&as=read|unread|saved|unsaved&id=N`, or `mark=feed|group&as=read&id=N`
+    /// with `before` to leave newer entries alone. Group 0 is every feed.
+    fn fever_mark(&self, session: &mut Session, parameters: &HashMap<String, String>) {
+        let get = |name: &str| parameters.get(name).map(String::as_str).unwrap_or("");
+        let id: u64 = get("id").parse().unwrap_or(0);
+        match get("mark") {
+            "item" => {
+                let url = match all_entries(&session.blog_feeds)
+                    .into_iter()
+                    .find(|(_, entry)| entry.id == Some(id))
+                {
+                    Some((_, entry)) => entry.url.clone(),
+                    None => return,
+                };
+                match get("as") {
+                    "read" => session.set_entry_read(&url, true),
+                    "unread" => session.set_entry_read(&url, false),
+                    "saved" => self.set_starred(session, &url, true),
+                    "unsaved" => self.set_starred(session, &url, false),
+                    _ => {}
+                }
+            }
+            kind @ ("feed" | "group") if get("as") == "read" => {
+                let before: Option<i64> = get("before").parse().ok();
+                for feed in session.blog_feeds.iter_mut() {
+                    let in_scope = match kind {
+                        "feed" => numeric_id(&feed.name) == id,
+                        _ => id == 0 || feed.folder.as_deref().map(numeric_id) == Some(id),
+                    };
+                    if !in_scope {
+                        continue;
+                    }
+                    for entry in feed.entries.iter_mut().flatten() {
+                        let old_enough = match (before, entry.updated()) {
+                            (Some(before), Some(date)) => date.timestamp() <= before,
+                            _ => true,
+                        };
+                        if old_enough {
+                            entry.read = true;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,400 @@
+mod fever;
+
+use crate::config_and_keymap::config::Config;
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::hooks::Hooks;
+use crate::feeds_and_entry::http;
+use crate::feeds_and_entry::ordering::SortMode;
+use crate::feeds_and_entry::scripts::Scripts;
+use crate::session_and_user::session::Session;
+use anyhow::{anyhow, Result};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, HOST, ORIGIN};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
+/// The `serve` section of the config.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ServeConfig {
+    /// Only localhost by default; anything else exposes the session to the network.
+    pub address: String,
+    /// A secret reference for the bearer token the JSON API asks for, e.g.
+    /// `env:READER_API_TOKEN`. Without one the API turns every request away,
+    /// unless `open` is set.
+    pub token: Option<String>,
+    /// Serve the JSON API without a token. Only allowed on a localhost address,
+    /// and requests still have to name localhost as their host and can't come
+    /// from a browser, so web pages can't reach it.
+    pub open: bool,
+    /// Turns on the Fever endpoint at `/fever/`.
+    pub fever: Option<FeverConfig>,
+}
+
+impl Default for ServeConfig {
+    fn default() -> ServeConfig {
+        ServeConfig {
+            address: String::from("127.0.0.1:8686"),
+            token: None,
+            open: false,
+            fever: None,
+        }
+    }
+}
+
+/// The login Fever clients are set up with. The api key they send is the md5 of
+/// `username:password`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeverConfig {
+    pub username: String,
+    /// A secret reference such as `env:FEVER_PASSWORD`.
+    pub password: String,
+}
+
+/// The session being served and what refreshing it needs.
+struct Server {
+    session: Mutex<Session>,
+    config: Config,
+    hooks: Hooks,
+    /// Only the transforms are used here.
+    scripts: Scripts,
+}
+
+/// Serves the session over HTTP until the process is stopped. The session is saved
+/// after every change, so don't run the TUI on the same session at the same time.
+pub async fn serve(mut session: Session, config: Config) -> Result<()> {
+    if session.assign_entry_ids() {
+        session.dump_to_json();
+    }
+    let address: SocketAddr = config
+        .serve
+        .address
+        .parse()
+        .map_err(|_| anyhow!("{:?} isn't an address to serve on", config.serve.address))?;
+    if config.serve.open && !address.ip().is_loopback() {
+        return Err(anyhow!(
+            "serve.open is only allowed on a localhost address, set serve.token instead"
+        ));
+    }
+    if config.serve.token.is_none() && !config.serve.open {
+        eprintln!("The JSON API is off: set serve.token, or serve.open to use it without one");
+    }
+    let hooks = Hooks::new(&config.hooks)?;
+    let server = Arc::new(Server {
+        session: Mutex::new(session),
+        config,
+        hooks,
+        scripts: Scripts::load(),
+    });
+    let service = make_service_fn(move |_| {
+        let server = server.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let server = server.clone();
+                async move { Ok::<_, Infallible>(server.handle(request).await) }
+            }))
+        }
+    });
+    let listener = hyper::Server::try_bind(&address)?;
+    println!("Serving on http://{}", address);
+    listener.serve(service).await?;
+    Ok(())
+}
+
+/// A JSON response.
+fn reply(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    reply(status, json!({ "error": message }))
+}
+
+/// Query string or form parameters. Flags such as Fever's `?api&items` have an
+/// empty value.
+fn parameters(text: &str) -> HashMap<String, String> {
+    form_urlencoded::parse(text.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+/// True if the request names localhost as its host and doesn't come from a web
+/// page. A page can't send a request to us without its browser adding `Origin`,
+/// and a page on another domain that resolves to us still names that domain.
+fn from_local_client(request: &Request<Body>) -> bool {
+    if request.headers().contains_key(ORIGIN) {
+        return false;
+    }
+    let host = match request
+        .headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+    {
+        Some(host) => host,
+        None => return false,
+    };
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(
+        name.to_lowercase().as_str(),
+        "localhost" | "127.0.0.1" | "[::1]"
+    )
+}
+
+/// Compares secrets without stopping at the first difference, so the time taken
+/// doesn't give away how much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// How an entry looks in the JSON API.
+fn entry_json(entry: &Entry) -> Value {
+    json!({
+        "id": entry.id,
+        "title": entry.title,
+        "url": entry.url,
+        "authors": entry.authors,
+        "feed": entry.feed_name,
+        "also_in": entry.also_in,
+        "published": entry.updated().map(|date| date.to_rfc3339()),
+        "read": entry.read,
+        "starred": entry.starred,
+        "tags": entry.tags,
+        "summary": entry.blurb,
+        "content": entry.content,
+    })
+}
+
+/// The body of `POST /api/entries/ID`; fields left out aren't changed.
+#[derive(Deserialize)]
+struct EntryChange {
+    read: Option<bool>,
+    starred: Option<bool>,
+}
+
+impl Server {
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let path = request.uri().path().trim_end_matches('/').to_string();
+        let (printed, errors) = self.scripts.take_messages();
+        for message in self
+            .hooks
+            .take_failures()
+            .into_iter()
+            .chain(printed)
+            .chain(errors)
+        {
+            eprintln!("{}", message);
+        }
+        let result = if path == "/fever" {
+            self.fever(request).await
+        } else if path.starts_with("/api") {
+            if !self.authorized(&request) {
+                return error(
+                    StatusCode::UNAUTHORIZED,
+                    "missing or wrong bearer token, or serve.token isn't set",
+                );
+            }
+            self.api(request, &path).await
+        } else {
+            Ok(error(StatusCode::NOT_FOUND, "nothing here"))
+        };
+        result.unwrap_or_else(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+    }
+
+    fn authorized(&self, request: &Request<Body>) -> bool {
+        let token = match &self.config.serve.token {
+            Some(reference) => match http::resolve_secret(reference) {
+                Ok(token) => token,
+                Err(_) => return false,
+            },
+            None => return self.config.serve.open && from_local_client(request),
+        };
+        request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+    }
+
+    async fn api(&self, request: Request<Body>, path: &str) -> Result<Response<Body>> {
+        let query = parameters(request.uri().query().unwrap_or(""));
+        let method = request.method().clone();
+        match (&method, path) {
+            (&Method::GET, "/api/feeds") => Ok(self.feeds().await),
+            (&Method::GET, "/api/entries") => Ok(self.entries(&query).await),
+            (&Method::POST, "/api/refresh") => Ok(self.refresh().await),
+            (&Method::POST, _) if path.starts_with("/api/entries/") => {
+                let id = path.trim_start_matches("/api/entries/");
+                let id = match id.parse::<u64>() {
+                    Ok(id) => id,
+                    Err(_) => return Ok(error(StatusCode::BAD_REQUEST, "entry ids are numbers")),
+                };
+                let body = hyper::body::to_bytes(request.into_body()).await?;
+                let change: EntryChange = match serde_json::from_slice(&body) {
+                    Ok(change) => change,
+                    Err(e) => return Ok(error(StatusCode::BAD_REQUEST, &e.to_string())),
+                };
+                Ok(self.change_entry(id, change).await)
+            }
+            (_, "/api/feeds" | "/api/entries" | "/api/refresh") => {
+                Ok(error(StatusCode::METHOD_NOT_ALLOWED, "wrong method"))
+            }
+            _ => Ok(error(StatusCode::NOT_FOUND, "no such endpoint")),
+        }
+    }
+
+    async fn feeds(&self) -> Response<Body> {
+        let session = self.session.lock().await;
+        let unread = session.unread_counts();
+        let feeds: Vec<Value> = session
+            .blog_feeds
+            .iter()
+            .map(|feed| {
+                json!({
+                    "name": feed.name,
+                    "url": feed.url,
+                    "folder": feed.folder,
+                    "title": feed.metadata.title,
+                    "site_link": feed.metadata.site_link,
+                    "unread": unread.feed(&feed.name),
+                    "last_error": feed.last_error,
+                })
+            })
+            .collect();
+        reply(StatusCode::OK, json!({ "feeds": feeds }))
+    }
+
+    /// `GET /api/entries?feed=NAME` or `?folder=PATH`, optionally `unread=true`,
+    /// `starred=true`, `sort=MODE`, `page=N` and `per_page=N`.
+    async fn entries(&self, query: &HashMap<String, String>) -> Response<Body> {
+        let session = self.session.lock().await;
+        let mut entries = match (query.get("feed"), query.get("folder")) {
+            (Some(feed), _) => session.get_feed_entries(feed),
+            (None, Some(folder)) => session.get_folder_entries(folder),
+            (None, None) => session.get_all_blog_entries(),
+        };
+        if query.get("unread").is_some_and(|value| value == "true") {
+            entries.retain(|entry| !entry.read);
+        }
+        if query.get("starred").is_some_and(|value| value == "true") {
+            entries.retain(|entry| entry.starred);
+        }
+        let sort = match query.get("sort").map(|name| SortMode::from_name(name)) {
+            Some(Ok(sort)) => sort,
+            Some(Err(e)) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+            None => SortMode::default(),
+        };
+        sort.sort(&mut entries);
+        let number = |name: &str, default: usize| {
+            query
+                .get(name)
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(default)
+        };
+        let page = number("page", 1).max(1);
+        let per_page = number("per_page", DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+        let total = entries.len();
+        let page_entries: Vec<Value> = entries
+            .iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(entry_json)
+            .collect();
+        reply(
+            StatusCode::OK,
+            json!({
+                "page": page,
+                "per_page": per_page,
+                "total": total,
+                "entries": page_entries,
+            }),
+        )
+    }
+
+    async fn change_entry(&self, id: u64, change: EntryChange) -> Response<Body> {
+        let mut session = self.session.lock().await;
+        let url = match session
+            .blog_feeds
+            .iter()
+            .flat_map(|feed| feed.entries.iter().flatten())
+            .find(|entry| entry.id == Some(id))
+        {
+            Some(entry) => entry.url.clone(),
+            None => return error(StatusCode::NOT_FOUND, "no entry with that id"),
+        };
+        if let Some(read) = change.read {
+            session.set_entry_read(&url, read);
+        }
+        if let Some(starred) = change.starred {
+            self.set_starred(&mut session, &url, starred);
+        }
+        session.dump_to_json();
+        reply(StatusCode::OK, json!({ "id": id, "url": url }))
+    }
+
+    /// Stars or unstars the entry, running the entry-starred hooks if it wasn't
+    /// starred before.
+    fn set_starred(&self, session: &mut Session, url: &str, starred: bool) {
+        let was_starred = session
+            .find_entry(url)
+            .is_some_and(|(_, entry)| entry.starred);
+        session.set_entry_starred(url, starred);
+        if starred && !was_starred {
+            if let Some((feed, entry)) = session.find_entry(url) {
+                self.hooks.entry_starred(feed, entry);
+            }
+        }
+    }
+
+    /// Syncs with the server in the config, if there is one, and fetches every
+    /// feed whether or not it is due, like `r` in the TUI.
+    async fn refresh(&self) -> Response<Body> {
+        let mut session = self.session.lock().await;
+        let mut errors = vec![];
+        if let Some(sync) = &self.config.sync {
+            if let Err(e) = sync
+                .sync(&mut session, &self.config.http, &self.scripts)
+                .await
+            {
+                errors.push(format!("sync failed: {}", e));
+            }
+        }
+        if let Err(e) = session
+            .fetch_all_blog_entries(true, &self.config.http, &self.hooks, &self.scripts)
+            .await
+        {
+            errors.push(format!("{:#}", e));
+        }
+        for broken in session.take_broken_rules() {
+            errors.push(format!("skipped {}", broken));
+        }
+        session.assign_entry_ids();
+        session.dump_to_json();
+        reply(
+            StatusCode::OK,
+            json!({
+                "unread": session.unread_counts().all,
+                "errors": errors,
+            }),
+        )
+    }
+}
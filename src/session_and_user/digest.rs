@@ -0,0 +1,340 @@
+use crate::feeds_and_entry::entry::Entry;
+use crate::feeds_and_entry::export;
+use crate::feeds_and_entry::opml::escape_xml;
+use crate::feeds_and_entry::source::expand_home;
+use crate::session_and_user::session::Session;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where unread entries are sent as emails by `:digest` and `j-rss-tui digest`,
+/// from the `digest` section of the config, e.g.
+/// `{ "maildir": "~/Mail/feeds", "to": "me@example.org", "every": "weekly" }`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct DigestConfig {
+    /// A Maildir the emails are delivered into. It's created if it's missing.
+    pub maildir: Option<String>,
+    /// An mbox file the emails are appended to, instead of a Maildir.
+    pub mbox: Option<String>,
+    pub from: String,
+    /// Defaults to `$USER@localhost`.
+    pub to: Option<String>,
+    /// One email per feed, or per folder.
+    pub group: DigestGroup,
+    /// How often `j-rss-tui digest` writes one, so it can run from cron as often
+    /// as you like. `:digest` and `--force` write one regardless.
+    pub every: DigestPeriod,
+    /// Mark the entries read once they've been sent.
+    pub mark_read: bool,
+}
+
+impl Default for DigestConfig {
+    fn default() -> DigestConfig {
+        DigestConfig {
+            maildir: None,
+            mbox: None,
+            from: String::from("j-rss-tui <j-rss-tui@localhost>"),
+            to: None,
+            group: DigestGroup::default(),
+            every: DigestPeriod::default(),
+            mark_read: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DigestGroup {
+    #[default]
+    Feed,
+    Folder,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DigestPeriod {
+    #[default]
+    Daily,
+    Weekly,
+}
+
+impl DigestPeriod {
+    fn name(&self) -> &'static str {
+        match self {
+            DigestPeriod::Daily => "Daily",
+            DigestPeriod::Weekly => "Weekly",
+        }
+    }
+
+    fn length(&self) -> Duration {
+        match self {
+            DigestPeriod::Daily => Duration::days(1),
+            DigestPeriod::Weekly => Duration::weeks(1),
+        }
+    }
+
+    /// An hour early still counts, so a cron job that runs a little sooner than
+    /// the last one doesn't skip a whole period.
+    pub fn is_due(&self, last: Option<DateTime<Utc>>) -> bool {
+        match last {
+            Some(last) => Utc::now() - last >= self.length() - Duration::hours(1),
+            None => true,
+        }
+    }
+
+    /// When the next digest is due after one written at `last`.
+    pub fn next_after(&self, last: DateTime<Utc>) -> DateTime<Utc> {
+        last + self.length() - Duration::hours(1)
+    }
+}
+
+/// What a digest sent.
+#[derive(Debug, Default)]
+pub struct DigestReport {
+    pub entries: usize,
+    pub emails: usize,
+    pub destination: String,
+}
+
+impl fmt::Display for DigestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.entries == 0 {
+            write!(f, "Nothing new for the digest")
+        } else {
+            write!(
+                f,
+                "Digested {} entries into {} emails in {}",
+                self.entries, self.emails, self.destination
+            )
+        }
+    }
+}
+
+enum Mailbox {
+    Maildir(PathBuf),
+    Mbox(PathBuf),
+}
+
+impl DigestConfig {
+    fn mailbox(&self) -> Result<Mailbox> {
+        match (&self.maildir, &self.mbox) {
+            (Some(path), None) => Ok(Mailbox::Maildir(expand_home(path)?)),
+            (None, Some(path)) => Ok(Mailbox::Mbox(expand_home(path)?)),
+            (Some(_), Some(_)) => Err(anyhow!("digest has both a maildir and an mbox, pick one")),
+            (None, None) => Err(anyhow!("digest needs a maildir or an mbox to write to")),
+        }
+    }
+
+    fn recipient(&self) -> String {
+        self.to.clone().unwrap_or_else(|| {
+            format!(
+                "{}@localhost",
+                std::env::var("USER").unwrap_or_else(|_| String::from("root"))
+            )
+        })
+    }
+
+    /// Sends every unread entry that hasn't been in a digest yet, one email per
+    /// group. Each group is marked and saved as soon as its email is written, so
+    /// a digest that fails partway only sends the rest next time.
+    pub fn run(&self, session: &mut Session) -> Result<DigestReport> {
+        let mailbox = self.mailbox()?;
+        let mut groups: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+        for entry in session.get_all_blog_entries() {
+            if entry.read || entry.digested {
+                continue;
+            }
+            let group = match self.group {
+                DigestGroup::Feed => entry.feed_name.clone(),
+                DigestGroup::Folder => session
+                    .feed_folder(&entry.feed_name)
+                    .unwrap_or_else(|| String::from("Other feeds")),
+            };
+            groups.entry(group).or_default().push(entry);
+        }
+        let mut report = DigestReport {
+            destination: match &mailbox {
+                Mailbox::Maildir(path) | Mailbox::Mbox(path) => path.display().to_string(),
+            },
+            ..DigestReport::default()
+        };
+        for (group, entries) in groups.iter_mut() {
+            entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated()));
+            let message = self.email(group, entries, session.last_digest);
+            match &mailbox {
+                Mailbox::Maildir(path) => deliver_to_maildir(path, &message, report.emails)?,
+                Mailbox::Mbox(path) => append_to_mbox(path, &message)?,
+            }
+            report.emails += 1;
+            report.entries += entries.len();
+            let urls: Vec<String> = entries.iter().map(|entry| entry.url.clone()).collect();
+            if self.mark_read {
+                session.set_entries_read(urls.iter().map(|url| (url.clone(), true)).collect());
+            }
+            session.set_entries_digested(urls);
+            session.dump_to_json();
+        }
+        session.last_digest = Some(Utc::now());
+        Ok(report)
+    }
+
+    /// A multipart/alternative email with the entries as plain text and as HTML.
+    fn email(&self, group: &str, entries: &[Entry], since: Option<DateTime<Utc>>) -> String {
+        let subject = format!(
+            "{} digest: {} ({} new)",
+            self.every.name(),
+            group,
+            entries.len()
+        );
+        let intro = match since {
+            Some(since) => format!(
+                "{} new entries since {}.",
+                entries.len(),
+                since.format("%Y-%m-%d %H:%M UTC")
+            ),
+            None => format!("{} new entries.", entries.len()),
+        };
+
+        let mut text = format!("# {}\n\n{}\n\n", group, intro);
+        for entry in entries {
+            text.push_str(&export::entry_markdown(entry));
+        }
+
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\"/>\n");
+        html.push_str(&format!("<title>{}</title>\n", escape_xml(&subject)));
+        html.push_str(&format!(
+            "<style>\n{}</style>\n</head>\n<body>\n",
+            export::STYLE
+        ));
+        html.push_str(&format!(
+            "<h1>{}</h1>\n<p class=\"meta\">{}</p>\n<ol>\n",
+            escape_xml(group),
+            intro
+        ));
+        for (index, entry) in entries.iter().enumerate() {
+            html.push_str(&format!(
+                "<li><a href=\"#entry-{}\">{}</a></li>\n",
+                index + 1,
+                escape_xml(entry.title.trim())
+            ));
+        }
+        html.push_str("</ol>\n");
+        for (index, entry) in entries.iter().enumerate() {
+            html.push_str(&export::article_html(
+                entry,
+                &format!("entry-{}", index + 1),
+                true,
+            ));
+        }
+        html.push_str("</body>\n</html>\n");
+
+        let now = Utc::now();
+        let boundary = format!("=_jrss_{:016x}", rand::thread_rng().gen::<u64>());
+        let message_id = format!(
+            "{:x}",
+            md5::compute(format!("{}{}{}", group, now.to_rfc3339(), boundary))
+        );
+        let mut message = format!(
+            "From: {}\nTo: {}\nSubject: {}\nDate: {}\nMessage-ID: <{}@j-rss-tui>\n\
+MIME-Version: 1.0\nContent-Type: multipart/alternative; boundary=\"{}\"\n\n",
+            self.from,
+            self.recipient(),
+            encode_header(&subject),
+            now.to_rfc2822(),
+            message_id,
+            boundary
+        );
+        for (mime_type, body) in [("text/plain", text), ("text/html", html)] {
+            message.push_str(&format!(
+                "--{}\nContent-Type: {}; charset=utf-8\nContent-Transfer-Encoding: base64\n\n{}",
+                boundary,
+                mime_type,
+                wrapped_base64(body.as_bytes())
+            ));
+        }
+        message.push_str(&format!("--{}--\n", boundary));
+        message
+    }
+}
+
+/// Base64 in lines of 76 characters, as MIME wants.
+fn wrapped_base64(bytes: &[u8]) -> String {
+    let encoded = STANDARD.encode(bytes);
+    let mut resp = String::new();
+    for line in encoded.as_bytes().chunks(76) {
+        resp.push_str(std::str::from_utf8(line).unwrap());
+        resp.push('\n');
+    }
+    resp
+}
+
+/// Headers have to be ASCII, so anything else is sent as RFC 2047 encoded
+/// words, split at character boundaries to keep each one short.
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+    let mut words = vec![];
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(std::mem::take(&mut chunk));
+        }
+        chunk.push(c);
+    }
+    words.push(chunk);
+    words
+        .iter()
+        .map(|word| format!("=?UTF-8?B?{}?=", STANDARD.encode(word)))
+        .collect::<Vec<String>>()
+        .join("\n ")
+}
+
+/// Writes the email into `tmp` and then moves it into `new`, so a mail reader
+/// never sees half of it.
+fn deliver_to_maildir(path: &Path, message: &str, sequence: usize) -> Result<()> {
+    for folder in ["tmp", "new", "cur"] {
+        fs::create_dir_all(path.join(folder))?;
+    }
+    let host = fs::read_to_string("/etc/hostname")
+        .map(|name| name.trim().replace(['/', ':'], "_"))
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("localhost"));
+    let now = Utc::now();
+    let name = format!(
+        "{}.M{}P{}Q{}.{}",
+        now.timestamp(),
+        now.timestamp_subsec_micros(),
+        std::process::id(),
+        sequence,
+        host
+    );
+    let temporary = path.join("tmp").join(&name);
+    fs::write(&temporary, message)?;
+    fs::rename(&temporary, path.join("new").join(&name))?;
+    Ok(())
+}
+
+/// Appends the email with a `From ` separator line, quoting any line of it that
+/// would look like one (mboxrd).
+fn append_to_mbox(path: &Path, message: &str) -> Result<()> {
+    let from_line = Regex::new(r"(?m)^(>*From )").unwrap();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    write!(
+        file,
+        "From j-rss-tui {}\n{}\n",
+        Utc::now().format("%a %b %e %H:%M:%S %Y"),
+        from_line.replace_all(message, ">$1")
+    )?;
+    Ok(())
+}
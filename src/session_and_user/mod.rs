@@ -1,2 +1,4 @@
+pub mod digest;
 pub mod session;
+pub mod sync;
 pub mod user;
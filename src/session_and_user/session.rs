@@ -1,16 +1,49 @@
 use crate::feeds_and_entry::entry::Entry;
-use crate::feeds_and_entry::feeds::BlogFeed;
+use crate::feeds_and_entry::archive;
+use crate::feeds_and_entry::duplicates;
+use crate::feeds_and_entry::feeds::{self, BlogFeed};
+use crate::feeds_and_entry::hooks::Hooks;
+use crate::feeds_and_entry::http::{self, Auth, HttpSettings};
+use crate::feeds_and_entry::ordering::ViewOrder;
+use crate::feeds_and_entry::rules::{self, Rule, RuleSet};
+use crate::feeds_and_entry::scripts::Scripts;
+use crate::feeds_and_entry::source::FeedSource;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use crate::session_and_user::user::User;
 
+/// Every named session is kept here, alongside `.session.json` for the last one used.
+const SESSIONS_DIR: &str = ".sessions";
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Session {
     pub user: User,
     pub name: String,
     pub blog_feeds: Vec<BlogFeed>,
+    /// Filter rules run over new entries on every refresh.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// How each entry list is sorted and grouped, keyed by view: `all`,
+    /// `folder:PATH` or `feed:NAME`. Views left at the default aren't stored.
+    #[serde(default)]
+    pub view_orders: BTreeMap<String, ViewOrder>,
+    /// When the session was last synced with the server in the config's `sync`
+    /// section.
+    #[serde(default)]
+    pub last_sync: Option<DateTime<Utc>>,
+    /// When the last digest was written, see the config's `digest` section.
+    #[serde(default)]
+    pub last_digest: Option<DateTime<Utc>>,
+    /// The last id handed out by `assign_entry_ids`.
+    #[serde(default)]
+    pub last_entry_id: u64,
+    /// Rules left out of the last refresh because they don't compile.
+    #[serde(skip)]
+    broken_rules: Vec<String>,
 }
 
 #[allow(unused)]
@@ -20,11 +53,56 @@ impl Session {
             name: String::from(name),
             user,
             blog_feeds,
+            rules: vec![],
+            view_orders: BTreeMap::new(),
+            last_sync: None,
+            last_digest: None,
+            last_entry_id: 0,
+            broken_rules: vec![],
         }
     }
 
     pub fn create_blog_feed(&mut self, name: &str, url: &str) {
-        self.blog_feeds.push(BlogFeed::new(name, url));
+        self.blog_feeds.push(BlogFeed::new(url, name));
+    }
+
+    /// Subscribes to a feed and fetches it straight away, returning its name.
+    /// Without a name the feed is called by its title, or by its url if it
+    /// doesn't have one or couldn't be fetched.
+    pub async fn add_feed(
+        &mut self,
+        url: &str,
+        name: Option<&str>,
+        http: &HttpSettings,
+        scripts: &Scripts,
+    ) -> Result<String> {
+        let rules = self.rule_set().with_scripts(scripts);
+        let mut feed = BlogFeed::new(url, name.unwrap_or(url));
+        let result = feed.refresh(&rules, http).await;
+        if result.is_ok() {
+            feed.fetch_extras(http).await;
+        }
+        if name.is_none() {
+            if let Some(title) = feed.metadata.title.clone().filter(|title| !title.is_empty()) {
+                feed.name = self.unique_feed_name(&title);
+            }
+        }
+        let name = feed.name.clone();
+        self.blog_feeds.push(feed);
+        self.dump_to_json();
+        result.map(|_| name)
+    }
+
+    /// `name`, or `name (2)` and so on if a feed is already called that.
+    pub(crate) fn unique_feed_name(&self, name: &str) -> String {
+        let taken = |candidate: &str| self.blog_feeds.iter().any(|feed| feed.name == candidate);
+        let mut candidate = name.to_string();
+        let mut number = 2;
+        while taken(&candidate) {
+            candidate = format!("{} ({})", name, number);
+            number += 1;
+        }
+        candidate
     }
 
     fn to_json(&self) -> serde_json::Value {
@@ -35,11 +113,53 @@ impl Session {
     pub fn dump_to_json(&self) {
         let the_json = self.to_json();
 
-        std::fs::write(
-            ".session.json",
-            serde_json::to_string_pretty(&the_json).unwrap(),
-        )
-        .unwrap();
+        let text = serde_json::to_string_pretty(&the_json).unwrap();
+        std::fs::write(".session.json", &text).unwrap();
+        // A name from a hand-edited session file could point anywhere.
+        if Session::check_name(&self.name).is_ok() {
+            std::fs::create_dir_all(SESSIONS_DIR).unwrap();
+            std::fs::write(Session::named_path(&self.name), &text).unwrap();
+        }
+    }
+
+    fn named_path(name: &str) -> String {
+        format!("{}/{}.json", SESSIONS_DIR, name)
+    }
+
+    /// Session names become file names in `.sessions`, so they can't be empty or
+    /// have anything in them that would put the file somewhere else.
+    pub fn check_name(name: &str) -> Result<()> {
+        if name.trim().is_empty() || name.contains(['/', '\\', '\0']) || name.contains("..") {
+            return Err(anyhow!("{:?} can't be a session name, leave out /, \\ and ..", name));
+        }
+        Ok(())
+    }
+
+    pub fn load_by_name(name: &str) -> Result<Session> {
+        Session::check_name(name)?;
+        let text = std::fs::read_to_string(Session::named_path(name))
+            .map_err(|_| anyhow!("no saved session called {:?}", name))?;
+        let the_json = serde_json::from_str::<Session>(&text)?;
+        Ok(the_json)
+    }
+
+    /// Names of the sessions that have been saved at least once.
+    pub fn saved_session_names() -> Vec<String> {
+        let mut names: Vec<String> = match std::fs::read_dir(SESSIONS_DIR) {
+            Ok(dir) => dir
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let path = entry.path();
+                    if path.extension()? != "json" {
+                        return None;
+                    }
+                    Some(path.file_stem()?.to_string_lossy().to_string())
+                })
+                .collect(),
+            Err(_) => vec![],
+        };
+        names.sort();
+        names
     }
 
     pub fn load_from_json() -> Result<Session> {
@@ -61,20 +181,28 @@ impl Session {
         println!("Great success")
     }
 
+    /// Every visible entry, with copies of the same article in several feeds
+    /// shown once.
     pub fn get_all_blog_entries(&self) -> Vec<Entry> {
-        let mut all_entries: Vec<Entry> = vec![];
+        let mut all_entries: Vec<(&str, &Entry)> = vec![];
         for feed in &self.blog_feeds {
-            let mut these_entries = feed.entries.clone().unwrap();
-            all_entries.append(&mut these_entries)
+            if let Some(entries) = &feed.entries {
+                all_entries.extend(
+                    entries
+                        .iter()
+                        .filter(|entry| !entry.hidden)
+                        .map(|entry| (feed.name.as_str(), entry)),
+                );
+            }
         }
-        all_entries
+        merge_duplicates(all_entries)
     }
 
     pub fn get_all_blog_entry_titles(&self) -> Vec<String> {
         let mut all_titles: Vec<String> = vec![];
         for feed in &self.blog_feeds {
-            for entry in &feed.entries {
-                for e in entry.iter() {
+            if let Some(entries) = &feed.entries {
+                for e in entries.iter() {
                     all_titles.push(e.to_string());
                 }
             }
@@ -90,19 +218,641 @@ impl Session {
         blurbs
     }
 
-    pub async fn fetch_all_blog_entries(&mut self) {
+    pub fn get_feed_entries(&self, name: &str) -> Vec<Entry> {
+        self.blog_feeds
+            .iter()
+            .filter(|feed| feed.name == name)
+            .flat_map(|feed| {
+                feed.entries.iter().flatten().filter(|entry| !entry.hidden).map(|entry| Entry {
+                    feed_name: feed.name.clone(),
+                    ..entry.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Unread articles in total, by feed and by folder, counted in one pass over
+    /// the articles as "All feeds" lists them. A copy read anywhere makes the
+    /// article read, and an article carried by several feeds counts once in each
+    /// of them and once in each folder holding any of them.
+    pub fn unread_counts(&self) -> UnreadCounts {
+        let folders_of: HashMap<&str, Vec<String>> = self
+            .blog_feeds
+            .iter()
+            .map(|feed| {
+                let parts = feed.folder_path();
+                let folders = (1..=parts.len()).map(|depth| parts[..depth].join("/")).collect();
+                (feed.name.as_str(), folders)
+            })
+            .collect();
+        let mut counts = UnreadCounts::default();
+        for entry in self.get_all_blog_entries().iter().filter(|entry| !entry.read) {
+            counts.all += 1;
+            let mut folders: HashSet<&str> = HashSet::new();
+            for name in std::iter::once(&entry.feed_name).chain(entry.also_in.iter()) {
+                *counts.feeds.entry(name.clone()).or_default() += 1;
+                for folder in folders_of.get(name.as_str()).into_iter().flatten() {
+                    folders.insert(folder);
+                }
+            }
+            for folder in folders {
+                *counts.folders.entry(folder.to_string()).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// Every folder path in use, including the parents of nested folders, sorted
+    /// so that each folder comes straight after its parent.
+    pub fn get_folder_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = vec![];
+        for feed in &self.blog_feeds {
+            let parts = feed.folder_path();
+            for depth in 1..=parts.len() {
+                let path = parts[..depth].join("/");
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        // By component, so "a/b" stays under "a" rather than after "a b".
+        paths.sort_by(|a, b| a.split('/').cmp(b.split('/')));
+        paths
+    }
+
+    pub fn get_folder_entries(&self, path: &str) -> Vec<Entry> {
+        let entries: Vec<(&str, &Entry)> = self
+            .blog_feeds
+            .iter()
+            .filter(|feed| feed.is_in_folder(path))
+            .flat_map(|feed| {
+                feed.entries
+                    .iter()
+                    .flatten()
+                    .filter(|entry| !entry.hidden)
+                    .map(|entry| (feed.name.as_str(), entry))
+            })
+            .collect();
+        merge_duplicates(entries)
+    }
+
+    pub fn mark_folder_read(&mut self, path: &str) {
         for feed in self.blog_feeds.iter_mut() {
-            let rss = feed.get_rss_feed().await.unwrap();
-            feed.populate_entries(&rss);
+            if !feed.is_in_folder(path) {
+                continue;
+            }
+            if let Some(entries) = feed.entries.as_mut() {
+                for entry in entries.iter_mut() {
+                    entry.read = true;
+                }
+            }
         }
+    }
+
+    /// The enabled rules that compile. The ones that don't are kept for
+    /// `take_broken_rules`.
+    pub(crate) fn rule_set(&mut self) -> RuleSet {
+        let (rules, broken) = RuleSet::skipping_broken(&self.rules);
+        self.broken_rules = broken;
+        rules
+    }
+
+    /// Why the rules left out of the last refresh don't compile, each reported once.
+    pub fn take_broken_rules(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.broken_rules)
+    }
+
+    /// Feeds the last refresh found at a new url, as (name, new url). The
+    /// suggestions are cleared so each one is only offered once.
+    pub fn take_moved_feeds(&mut self) -> Vec<(String, String)> {
+        self.blog_feeds
+            .iter_mut()
+            .filter_map(|feed| Some((feed.name.clone(), feed.moved_to.take()?)))
+            .collect()
+    }
+
+    /// Names of the feeds whose server said they're gone on the last refresh.
+    pub fn take_gone_feeds(&mut self) -> Vec<String> {
+        self.blog_feeds
+            .iter_mut()
+            .filter_map(|feed| std::mem::take(&mut feed.gone).then(|| feed.name.clone()))
+            .collect()
+    }
+
+    /// Points the named feed at a new url, returning the old one.
+    pub fn set_feed_url(&mut self, name: &str, url: &str) -> Result<String> {
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        feed.declined_move = None;
+        Ok(std::mem::replace(&mut feed.url, url.to_string()))
+    }
+
+    /// Stops suggesting that the named feed moves to `url`.
+    pub fn decline_move(&mut self, name: &str, url: &str) {
+        if let Some(feed) = self.blog_feeds.iter_mut().find(|feed| feed.name == name) {
+            feed.declined_move = Some(url.to_string());
+        }
+    }
+
+    pub fn unsubscribe(&mut self, name: &str) -> Result<()> {
+        let before = self.blog_feeds.len();
+        self.blog_feeds.retain(|feed| feed.name != name);
+        if self.blog_feeds.len() == before {
+            return Err(anyhow!("no feed called {:?}", name));
+        }
+        Ok(())
+    }
+
+    /// Moves the named feed into a folder, or to the top level if the path is empty.
+    pub fn set_feed_folder(&mut self, name: &str, path: &str) -> Result<()> {
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        let path = path.trim_matches('/');
+        feed.folder = if path.is_empty() {
+            None
+        } else {
+            Some(path.to_string())
+        };
+        Ok(())
+    }
+
+    /// Adds the feeds that aren't already subscribed to, returning how many were new.
+    /// Only web feeds are taken, so a shared OPML file can't run commands or read
+    /// files; the urls of the rest are returned too.
+    pub fn import_blog_feeds(&mut self, feeds: Vec<BlogFeed>) -> (usize, Vec<String>) {
+        let mut added = 0;
+        let mut rejected = vec![];
+        for feed in feeds {
+            if !FeedSource::is_web(&feed.url) {
+                rejected.push(feed.url);
+                continue;
+            }
+            if self.blog_feeds.iter().any(|existing| existing.url == feed.url) {
+                continue;
+            }
+            self.blog_feeds.push(feed);
+            added += 1;
+        }
+        (added, rejected)
+    }
+
+    pub fn get_feed_names(&self) -> Vec<String> {
+        self.blog_feeds.iter().map(|feed| feed.name.clone()).collect()
+    }
+
+    /// Entries whose title, authors or blurb contain the query, ignoring case.
+    pub fn search_blog_entries(&self, query: &str) -> Vec<Entry> {
+        self.get_all_blog_entries()
+            .into_iter()
+            .filter(|entry| entry.matches_query(query))
+            .collect()
+    }
+
+    /// Refreshes the named feed. Feeds from the sync server are only ever
+    /// refreshed through it, since their url came from the server.
+    pub async fn fetch_feed_entries(
+        &mut self,
+        name: &str,
+        http: &HttpSettings,
+        hooks: &Hooks,
+        scripts: &Scripts,
+    ) -> Result<()> {
+        let rules = self.rule_set().with_scripts(scripts);
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        if feed.sync_id.is_some() {
+            return Err(anyhow!("{} comes from the sync server, refresh it with :sync", name));
+        }
+        let mut refresh = Refresh::default();
+        refresh_feed(feed, &rules, http, hooks, &mut refresh).await;
+        self.finish_refresh(refresh, hooks)
+    }
+
+    /// Refreshes the feeds in the folder. With `force` off, feeds that asked not to
+    /// be fetched again yet are skipped.
+    pub async fn fetch_folder_entries(
+        &mut self,
+        path: &str,
+        force: bool,
+        http: &HttpSettings,
+        hooks: &Hooks,
+        scripts: &Scripts,
+    ) -> Result<()> {
+        let rules = self.rule_set().with_scripts(scripts);
+        let mut refresh = Refresh::default();
+        for feed in self.blog_feeds.iter_mut() {
+            if feed.is_in_folder(path) && (force || feed.is_due()) && feed.sync_id.is_none() {
+                refresh_feed(feed, &rules, http, hooks, &mut refresh).await;
+            }
+        }
+        self.finish_refresh(refresh, hooks)
+    }
+
+    /// Refreshes every feed, leaving the ones that come from the sync server to it.
+    /// With `force` off, as for scheduled refreshes, feeds that asked not to be
+    /// fetched again yet are skipped; a refresh the user asked for fetches them all.
+    pub async fn fetch_all_blog_entries(
+        &mut self,
+        force: bool,
+        http: &HttpSettings,
+        hooks: &Hooks,
+        scripts: &Scripts,
+    ) -> Result<()> {
+        let rules = self.rule_set().with_scripts(scripts);
+        let mut refresh = Refresh::default();
+        for feed in self
+            .blog_feeds
+            .iter_mut()
+            .filter(|feed| (force || feed.is_due()) && feed.sync_id.is_none())
+        {
+            refresh_feed(feed, &rules, http, hooks, &mut refresh).await;
+        }
+        self.finish_refresh(refresh, hooks)
+    }
+
+    fn finish_refresh(&mut self, refresh: Refresh, hooks: &Hooks) -> Result<()> {
         self.dump_to_json();
+        let errors: Vec<String> = refresh.errors.iter().map(|e| format!("{:#}", e)).collect();
+        let unread = self.unread_counts().all;
+        hooks.refresh_finished(refresh.feeds, refresh.new_entries, &errors, unread);
+        refresh.errors.into_iter().next().map_or(Ok(()), Err)
+    }
+
+    /// Turns full article fetching on or off for the named feed.
+    pub fn set_full_content(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        feed.fetch_full_content = enabled;
+        Ok(())
+    }
+
+    /// Turns the offline cache on or off for the named feed.
+    pub fn set_cache_offline(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        feed.cache_offline = enabled;
+        Ok(())
+    }
+
+    /// Sets or clears the named feed's credentials. Passwords and tokens have to be
+    /// secret references so they never end up in the session file.
+    pub fn set_feed_auth(&mut self, name: &str, auth: Option<Auth>) -> Result<()> {
+        match &auth {
+            Some(Auth::Basic { password, .. }) => http::check_secret_reference(password)?,
+            Some(Auth::Bearer { token }) => http::check_secret_reference(token)?,
+            None => {}
+        }
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        feed.http.auth = auth;
+        Ok(())
+    }
+
+    /// Sets a header sent with the named feed's requests, or removes it.
+    pub fn set_feed_header(&mut self, name: &str, header: &str, value: Option<String>) -> Result<()> {
+        reqwest::header::HeaderName::from_bytes(header.as_bytes())
+            .map_err(|_| anyhow!("{:?} isn't a valid header name", header))?;
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        match value {
+            Some(value) => feed.http.headers.insert(header.to_string(), value),
+            None => feed.http.headers.remove(header),
+        };
+        Ok(())
+    }
+
+    pub fn view_order(&self, view: &str) -> ViewOrder {
+        self.view_orders.get(view).copied().unwrap_or_default()
+    }
+
+    pub fn set_view_order(&mut self, view: &str, order: ViewOrder) {
+        if order == ViewOrder::default() {
+            self.view_orders.remove(view);
+        } else {
+            self.view_orders.insert(view.to_string(), order);
+        }
+    }
+
+    /// True if the named feed comes from the sync server.
+    pub fn is_synced_feed(&self, name: &str) -> bool {
+        self.blog_feeds
+            .iter()
+            .any(|feed| feed.name == name && feed.sync_id.is_some())
+    }
+
+    /// The language the feeds these entries come from are written in, if they
+    /// all say the same one.
+    pub fn language_of(&self, entries: &[Entry]) -> Option<String> {
+        let mut languages = entries.iter().map(|entry| {
+            self.blog_feeds
+                .iter()
+                .find(|feed| feed.name == entry.feed_name)
+                .and_then(|feed| feed.metadata.language.clone())
+        });
+        let first = languages.next()??;
+        languages
+            .all(|language| language.as_ref() == Some(&first))
+            .then_some(first)
+    }
+
+    /// The folder the named feed is filed under.
+    pub fn feed_folder(&self, name: &str) -> Option<String> {
+        self.blog_feeds
+            .iter()
+            .find(|feed| feed.name == name)
+            .and_then(|feed| feed.folder.clone())
+    }
+
+    /// Caches the named feed's pages straight away, returning how many were added.
+    pub async fn archive_feed(&mut self, name: &str, http: &HttpSettings) -> Result<usize> {
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        let archived = archive::archive_feed(feed, http).await;
+        self.dump_to_json();
+        Ok(archived)
+    }
+
+    /// The name of the feed an entry belongs to.
+    pub fn feed_name_for_entry(&self, url: &str) -> Option<String> {
+        self.blog_feeds
+            .iter()
+            .find(|feed| feed.entries.iter().flatten().any(|entry| entry.url == url))
+            .map(|feed| feed.name.clone())
+    }
+
+    /// Fetches the missing articles of the named feed straight away.
+    pub async fn fetch_full_articles(&mut self, name: &str, http: &HttpSettings) -> Result<usize> {
+        let feed = self
+            .blog_feeds
+            .iter_mut()
+            .find(|feed| feed.name == name)
+            .ok_or_else(|| anyhow!("no feed called {:?}", name))?;
+        let fetched = feed.fetch_full_articles(http).await;
+        self.dump_to_json();
+        Ok(fetched)
+    }
+
+    /// The settings for a page or file an entry links to, see `BlogFeed::link_settings`.
+    pub fn link_settings(&self, entry_url: &str, http: &HttpSettings, url: &str) -> HttpSettings {
+        match self.find_entry(entry_url) {
+            Some((feed, _)) => feed.link_settings(http, url),
+            None => http.anonymous(),
+        }
+    }
+
+    /// Downloads the article behind one entry, replacing any content it had.
+    pub async fn fetch_entry_article(&mut self, url: &str, http: &HttpSettings) -> Result<()> {
+        let page = self.link_settings(url, http, url);
+        let content = feeds::fetch_article(url, &page).await?;
+        let entry = self
+            .find_entry_mut(url)
+            .ok_or_else(|| anyhow!("no entry with url {}", url))?;
+        entry.content = Some(content);
+        entry.full_content = true;
+        Ok(())
+    }
+
+    /// Runs the enabled rules over every entry already fetched, returning how many matched.
+    pub fn apply_rules(&mut self) -> Result<usize> {
+        let rules = RuleSet::new(&self.rules)?;
+        let mut matched = 0;
+        for feed in self.blog_feeds.iter_mut() {
+            if let Some(entries) = feed.entries.as_mut() {
+                for entry in entries.iter_mut() {
+                    if rules.apply(entry, &feed.name) {
+                        matched += 1;
+                    }
+                }
+            }
+        }
+        Ok(matched)
+    }
+
+    /// How many fetched entries, hidden ones included, the rule would match.
+    pub fn rule_match_count(&self, rule: &Rule) -> Result<usize> {
+        let entries = self.blog_feeds.iter().flat_map(|feed| {
+            feed.entries
+                .iter()
+                .flatten()
+                .map(move |entry| (entry, feed.name.as_str()))
+        });
+        rules::count_matches(rule, entries)
+    }
+
+    /// Adds a rule, refusing one whose patterns don't compile.
+    pub fn add_rule(&mut self, rule: Rule) -> Result<()> {
+        RuleSet::new(std::slice::from_ref(&rule))?;
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    /// Switches the rule at `index` on or off, returning whether it's now enabled.
+    pub fn toggle_rule(&mut self, index: usize) -> Option<bool> {
+        let rule = self.rules.get_mut(index)?;
+        rule.enabled = !rule.enabled;
+        Some(rule.enabled)
+    }
+
+    /// Brings back every entry a rule has hidden.
+    pub fn unhide_all(&mut self) {
+        for feed in self.blog_feeds.iter_mut() {
+            if let Some(entries) = feed.entries.as_mut() {
+                for entry in entries.iter_mut() {
+                    entry.hidden = false;
+                }
+            }
+        }
+    }
+
+    /// Adds a tag to the entry unless it already has it.
+    pub fn tag_entry(&mut self, url: &str, tag: &str) {
+        if let Some(entry) = self.find_entry_mut(url) {
+            if !entry.tags.iter().any(|existing| existing == tag) {
+                entry.tags.push(tag.to_string());
+            }
+        }
+    }
+
+    /// Hides the entry from every list, as a filter rule would.
+    pub fn hide_entry(&mut self, url: &str) {
+        if let Some(entry) = self.find_entry_mut(url) {
+            entry.hidden = true;
+        }
+    }
+
+    fn find_entry_mut(&mut self, url: &str) -> Option<&mut Entry> {
+        self.blog_feeds
+            .iter_mut()
+            .filter_map(|feed| feed.entries.as_mut())
+            .flat_map(|entries| entries.iter_mut())
+            .find(|entry| entry.url == url)
+    }
+
+    pub fn mark_all_read(&mut self) {
+        for feed in self.blog_feeds.iter_mut() {
+            if let Some(entries) = feed.entries.as_mut() {
+                for entry in entries.iter_mut() {
+                    entry.read = true;
+                }
+            }
+        }
+    }
+
+    /// Marks the entry with this url, and its copies in other feeds, read or unread.
+    pub fn set_entry_read(&mut self, url: &str, read: bool) {
+        self.set_entries_read(vec![(url.to_string(), read)]);
+    }
+
+    /// Marks each entry, and its copies in other feeds, read or unread. If an url
+    /// comes up more than once, the last change wins.
+    pub fn set_entries_read(&mut self, changes: Vec<(String, bool)>) {
+        self.update_with_copies(changes, |entry, read| entry.read = read);
+    }
+
+    /// Stars or unstars the entry with this url and its copies in other feeds.
+    pub fn set_entry_starred(&mut self, url: &str, starred: bool) {
+        self.set_entries_starred(vec![(url.to_string(), starred)]);
+    }
+
+    /// Like `set_entries_read`, for the starred flag.
+    pub fn set_entries_starred(&mut self, changes: Vec<(String, bool)>) {
+        self.update_with_copies(changes, |entry, starred| entry.starred = starred);
+    }
+
+    /// Marks the entries and every copy of them as sent out in a digest.
+    pub fn set_entries_digested(&mut self, urls: Vec<String>) {
+        let changes = urls.into_iter().map(|url| (url, true)).collect();
+        self.update_with_copies(changes, |entry, digested| entry.digested = digested);
+    }
+
+    /// Applies each change to the entry with its url and to every copy of it.
+    /// Finding the copies parses every url in the session, so it's done once for
+    /// the whole batch.
+    fn update_with_copies(
+        &mut self,
+        changes: Vec<(String, bool)>,
+        update: impl Fn(&mut Entry, bool),
+    ) {
+        if changes.is_empty() {
+            return;
+        }
+        let changes: HashMap<String, bool> = changes.into_iter().collect();
+        let entries: Vec<&Entry> = self
+            .blog_feeds
+            .iter()
+            .flat_map(|feed| feed.entries.iter().flatten())
+            .collect();
+        let mut targets: HashMap<String, bool> = changes.clone();
+        for group in duplicates::group(&entries) {
+            let value = group
+                .iter()
+                .find_map(|index| changes.get(&entries[*index].url).copied());
+            if let Some(value) = value {
+                for index in group {
+                    targets.insert(entries[index].url.clone(), value);
+                }
+            }
+        }
+        for feed in self.blog_feeds.iter_mut() {
+            for entry in feed.entries.iter_mut().flatten() {
+                if let Some(value) = targets.get(&entry.url) {
+                    update(entry, *value);
+                }
+            }
+        }
+    }
+
+    /// The urls of the entry and of every copy of it in the session.
+    fn copies_of(&self, url: &str) -> Vec<String> {
+        let entries: Vec<&Entry> = self
+            .blog_feeds
+            .iter()
+            .flat_map(|feed| feed.entries.iter().flatten())
+            .collect();
+        duplicates::group(&entries)
+            .into_iter()
+            .find(|group| group.iter().any(|index| entries[*index].url == url))
+            .map(|group| group.iter().map(|index| entries[*index].url.clone()).collect())
+            .unwrap_or_else(|| vec![url.to_string()])
+    }
+
+    /// Numbers the entries that don't have an id yet, oldest first, so newer
+    /// entries always have higher ids. Returns true if any were numbered.
+    pub fn assign_entry_ids(&mut self) -> bool {
+        let mut unnumbered: Vec<&mut Entry> = self
+            .blog_feeds
+            .iter_mut()
+            .flat_map(|feed| feed.entries.iter_mut().flatten())
+            .filter(|entry| entry.id.is_none())
+            .collect();
+        unnumbered.sort_by_key(|entry| entry.updated());
+        let numbered = !unnumbered.is_empty();
+        for entry in unnumbered {
+            self.last_entry_id += 1;
+            entry.id = Some(self.last_entry_id);
+        }
+        numbered
+    }
+
+    /// The entry with the url and the feed it's in.
+    pub fn find_entry(&self, url: &str) -> Option<(&BlogFeed, &Entry)> {
+        self.blog_feeds.iter().find_map(|feed| {
+            feed.entries
+                .iter()
+                .flatten()
+                .find(|entry| entry.url == url)
+                .map(|entry| (feed, entry))
+        })
+    }
+
+    /// Flips the starred flag of the entry with this url, returning the new value.
+    /// The copies in other feeds follow, since the entry is listed once and shown
+    /// starred if any copy is.
+    pub fn toggle_entry_starred(&mut self, url: &str) -> Option<bool> {
+        self.find_entry(url)?;
+        let copies = self.copies_of(url);
+        let mut copies_mut: Vec<&mut Entry> = self
+            .blog_feeds
+            .iter_mut()
+            .flat_map(|feed| feed.entries.iter_mut().flatten())
+            .filter(|entry| copies.contains(&entry.url))
+            .collect();
+        let starred = !copies_mut.iter().any(|entry| entry.starred);
+        for entry in copies_mut.iter_mut() {
+            entry.starred = starred;
+        }
+        Some(starred)
     }
 
     pub fn get_unique_authors(&self) -> Vec<String> {
         let mut authors: Vec<String> = vec![];
         for feed in &self.blog_feeds {
-            for entry in &feed.entries {
-                for e in entry {
+            if let Some(entries) = &feed.entries {
+                for e in entries {
                     if authors.contains(&e.authors) {
                         continue
                     }
@@ -114,6 +864,90 @@ impl Session {
     }
 }
 
+/// What `Session::unread_counts` found.
+#[derive(Clone, Debug, Default)]
+pub struct UnreadCounts {
+    pub all: usize,
+    feeds: HashMap<String, usize>,
+    folders: HashMap<String, usize>,
+}
+
+impl UnreadCounts {
+    pub fn feed(&self, name: &str) -> usize {
+        self.feeds.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn folder(&self, path: &str) -> usize {
+        self.folders.get(path).copied().unwrap_or(0)
+    }
+}
+
+/// Shows each article once: the first copy stands in for the rest, listing the
+/// other feeds in `also_in`, and counts as read or starred if any copy is.
+fn merge_duplicates(entries: Vec<(&str, &Entry)>) -> Vec<Entry> {
+    let plain: Vec<&Entry> = entries.iter().map(|(_, entry)| *entry).collect();
+    duplicates::group(&plain)
+        .into_iter()
+        .map(|group| {
+            let (first_feed, first) = entries[group[0]];
+            let mut merged = first.clone();
+            merged.feed_name = first_feed.to_string();
+            for index in group.iter().skip(1) {
+                let (feed_name, copy) = entries[*index];
+                merged.read |= copy.read;
+                merged.starred |= copy.starred;
+                merged.digested |= copy.digested;
+                if feed_name != first_feed && !merged.also_in.iter().any(|name| name == feed_name) {
+                    merged.also_in.push(feed_name.to_string());
+                }
+            }
+            merged
+        })
+        .collect()
+}
+
+/// What a refresh of one or more feeds came to, for the refresh-finished hook.
+#[derive(Default)]
+struct Refresh {
+    feeds: usize,
+    new_entries: usize,
+    errors: Vec<anyhow::Error>,
+}
+
+/// Refreshes one feed of many and runs the hooks on what it found. A failure is
+/// kept on the feed and collected for the caller, so one broken feed doesn't hold
+/// up the rest.
+async fn refresh_feed(
+    feed: &mut BlogFeed,
+    rules: &RuleSet,
+    http: &HttpSettings,
+    hooks: &Hooks,
+    refresh: &mut Refresh,
+) {
+    refresh.feeds += 1;
+    match feed.refresh(rules, http).await {
+        Ok(_) => {
+            feed.fetch_extras(http).await;
+            for entry in feed.entries.iter().flatten() {
+                if entry.hidden || !feed.fresh.contains(&entry.url) {
+                    continue;
+                }
+                refresh.new_entries += 1;
+                hooks.new_entry(feed, entry);
+                // Starred by a filter rule.
+                if entry.starred {
+                    hooks.entry_starred(feed, entry);
+                }
+            }
+        }
+        Err(e) => {
+            hooks.feed_error(feed, &e.to_string());
+            let message = format!("{}: {}", feed.name, e);
+            refresh.errors.push(e.context(message));
+        }
+    }
+}
+
 impl fmt::Display for Session {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
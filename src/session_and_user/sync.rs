@@ -0,0 +1,512 @@
+use crate::feeds_and_entry::entry::{Entry, SyncedState};
+use crate::feeds_and_entry::feeds::BlogFeed;
+use crate::feeds_and_entry::greader::{self, GoogleReader, Item};
+use crate::feeds_and_entry::http::HttpSettings;
+use crate::feeds_and_entry::readability;
+use crate::feeds_and_entry::rules::RuleSet;
+use crate::feeds_and_entry::scripts::Scripts;
+use crate::feeds_and_entry::source::FeedSource;
+use crate::session_and_user::session::Session;
+use anyhow::Result;
+use chrono::{Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// The server subscriptions and read state are synced with, from the `sync` section
+/// of the config, e.g. `{ "type": "google-reader", "url": "https://rss.example.org/api/greader.php",
+/// "username": "me", "password": "env:FRESHRSS_PASSWORD" }`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SyncConfig {
+    GoogleReader {
+        url: String,
+        username: String,
+        /// A secret reference such as `env:FRESHRSS_PASSWORD`.
+        password: String,
+        #[serde(default)]
+        conflicts: ConflictPolicy,
+    },
+}
+
+/// Which side wins when an entry both sides already had, but were never synced on,
+/// is read or starred on one side and not the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    #[default]
+    Server,
+    Local,
+}
+
+/// What a sync changed.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub feeds_added: usize,
+    pub feeds_removed: usize,
+    pub new_entries: usize,
+    pub pulled: usize,
+    pub pushed: usize,
+    pub conflicts: usize,
+    /// Subscriptions the server listed with a url that isn't a web feed.
+    pub rejected: usize,
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Synced: {} new entries, {} changes pulled, {} pushed",
+            self.new_entries, self.pulled, self.pushed
+        )?;
+        if self.feeds_added + self.feeds_removed > 0 {
+            write!(
+                f,
+                ", {} feeds added, {} removed",
+                self.feeds_added, self.feeds_removed
+            )?;
+        }
+        if self.conflicts > 0 {
+            write!(f, ", {} conflicts settled", self.conflicts)?;
+        }
+        if self.rejected > 0 {
+            write!(
+                f,
+                ", {} subscriptions that aren't web feeds skipped",
+                self.rejected
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Changes to send to the server, by state.
+#[derive(Default)]
+struct Pushes {
+    read: Vec<String>,
+    unread: Vec<String>,
+    starred: Vec<String>,
+    unstarred: Vec<String>,
+}
+
+impl Pushes {
+    fn len(&self) -> usize {
+        self.read.len() + self.unread.len() + self.starred.len() + self.unstarred.len()
+    }
+}
+
+/// New entries are asked for from a little before the last sync, in case the
+/// server's clock and ours disagree.
+const SYNC_OVERLAP_HOURS: i64 = 1;
+
+impl SyncConfig {
+    /// Makes the session a client of the server: subscriptions follow the server's,
+    /// new entries come from it rather than from each feed, and read and starred
+    /// state goes both ways. A state changed on one side since the last sync takes
+    /// that side's value; changed on both, the two sides already agree.
+    pub async fn sync(
+        &self,
+        session: &mut Session,
+        http: &HttpSettings,
+        scripts: &Scripts,
+    ) -> Result<SyncReport> {
+        let SyncConfig::GoogleReader {
+            url,
+            username,
+            password,
+            conflicts,
+        } = self;
+        let server = GoogleReader::login(url, username, password, http).await?;
+        let mut report = SyncReport::default();
+
+        sync_subscriptions(session, &server.subscriptions().await?, &mut report);
+
+        let since = session
+            .last_sync
+            .map(|last| (last - Duration::hours(SYNC_OVERLAP_HOURS)).timestamp());
+        let rules = session.rule_set().with_scripts(scripts);
+        // Oldest first, so each new entry goes on top of the ones before it.
+        for item in server.items(since).await?.iter().rev() {
+            add_item(session, item, &rules, *conflicts, &mut report);
+        }
+
+        let unread = server.unread_ids().await?;
+        let starred = server.starred_ids().await?;
+        let pushes = merge_states(session, &unread, &starred, &mut report);
+        server.set_state(&pushes.read, greader::READ, true).await?;
+        server
+            .set_state(&pushes.unread, greader::READ, false)
+            .await?;
+        server
+            .set_state(&pushes.starred, greader::STARRED, true)
+            .await?;
+        server
+            .set_state(&pushes.unstarred, greader::STARRED, false)
+            .await?;
+        report.pushed = pushes.len();
+        // The server has taken the pushed changes, so both sides now agree.
+        agree(session);
+
+        session.last_sync = Some(Utc::now());
+        session.dump_to_json();
+        Ok(report)
+    }
+}
+
+/// Adds the server's new subscriptions and drops the feeds unsubscribed on it.
+/// Local feeds that were never on the server are left alone. Subscriptions
+/// whose url isn't a web feed are skipped, so the server can't make us run
+/// commands or read files.
+fn sync_subscriptions(
+    session: &mut Session,
+    subscriptions: &[greader::Subscription],
+    report: &mut SyncReport,
+) {
+    for subscription in subscriptions {
+        if !FeedSource::is_web(&subscription.url) {
+            report.rejected += 1;
+            continue;
+        }
+        let folder = subscription
+            .categories
+            .first()
+            .map(|category| category.label.clone());
+        let existing = session.blog_feeds.iter_mut().find(|feed| {
+            feed.sync_id.as_deref() == Some(subscription.id.as_str())
+                || (feed.sync_id.is_none() && feed.url == subscription.url)
+        });
+        match existing {
+            Some(feed) => {
+                feed.sync_id = Some(subscription.id.clone());
+                feed.url = subscription.url.clone();
+                feed.folder = folder;
+            }
+            None => {
+                let name = session.unique_feed_name(&subscription.title);
+                let mut feed = BlogFeed::new(&subscription.url, &name);
+                feed.sync_id = Some(subscription.id.clone());
+                feed.folder = folder;
+                session.blog_feeds.push(feed);
+                report.feeds_added += 1;
+            }
+        }
+    }
+    let before = session.blog_feeds.len();
+    session.blog_feeds.retain(|feed| match &feed.sync_id {
+        Some(id) => subscriptions
+            .iter()
+            .any(|subscription| &subscription.id == id),
+        None => true,
+    });
+    report.feeds_removed = before - session.blog_feeds.len();
+}
+
+/// Adds an entry from the server to its feed, or ties it to the local copy the
+/// feed already had.
+fn add_item(
+    session: &mut Session,
+    item: &Item,
+    rules: &RuleSet,
+    conflicts: ConflictPolicy,
+    report: &mut SyncReport,
+) {
+    let feed = match session
+        .blog_feeds
+        .iter_mut()
+        .find(|feed| feed.sync_id.as_deref() == Some(item.origin.stream_id.as_str()))
+    {
+        Some(feed) => feed,
+        None => return,
+    };
+    let id = item.short_id();
+    let url = item.url().unwrap_or_default();
+    let remote = SyncedState {
+        id: id.clone(),
+        read: item.has_state(greader::READ),
+        starred: item.has_state(greader::STARRED),
+    };
+    let entries = feed.entries.get_or_insert_with(Vec::new);
+    let existing = entries.iter_mut().find(|entry| match &entry.sync {
+        Some(sync) => sync.id == id,
+        None => !url.is_empty() && entry.url == url,
+    });
+    match existing {
+        Some(entry) if entry.sync.is_some() => {}
+        Some(entry) => {
+            // Neither side knows what the other saw, so there's no telling which
+            // change is newer.
+            if entry.read != remote.read || entry.starred != remote.starred {
+                report.conflicts += 1;
+                if conflicts == ConflictPolicy::Server {
+                    entry.read = remote.read;
+                    entry.starred = remote.starred;
+                }
+            }
+            entry.sync = Some(remote);
+        }
+        None => {
+            let summary = item
+                .summary
+                .as_ref()
+                .or(item.content.as_ref())
+                .map(|text| readability::html_to_text(&text.content))
+                .unwrap_or_default();
+            let published = item
+                .published
+                .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single());
+            let mut entry = Entry::new(
+                item.title.as_deref().unwrap_or("(untitled)"),
+                item.author.as_deref().unwrap_or_default(),
+                &summary,
+                url,
+                item.content
+                    .as_ref()
+                    .or(item.summary.as_ref())
+                    .map(|text| text.content.clone()),
+                published,
+            );
+            entry.read = remote.read;
+            entry.starred = remote.starred;
+            entry.sync = Some(remote);
+            rules.transform(&mut entry, &feed.name);
+            rules.apply(&mut entry, &feed.name);
+            entries.insert(0, entry);
+            report.new_entries += 1;
+        }
+    }
+}
+
+/// Settles each synced entry's read and starred state against the server's,
+/// returning the local changes the server hasn't seen.
+fn merge_states(
+    session: &mut Session,
+    unread: &HashSet<String>,
+    starred: &HashSet<String>,
+    report: &mut SyncReport,
+) -> Pushes {
+    let mut pushes = Pushes::default();
+    for entry in session
+        .blog_feeds
+        .iter_mut()
+        .flat_map(|feed| feed.entries.iter_mut().flatten())
+    {
+        let sync = match &entry.sync {
+            Some(sync) => sync.clone(),
+            None => continue,
+        };
+        let remote_read = !unread.contains(&sync.id);
+        if entry.read != sync.read && remote_read == sync.read {
+            let list = if entry.read {
+                &mut pushes.read
+            } else {
+                &mut pushes.unread
+            };
+            list.push(sync.id.clone());
+        } else if remote_read != sync.read && entry.read == sync.read {
+            entry.read = remote_read;
+            report.pulled += 1;
+        }
+        let remote_starred = starred.contains(&sync.id);
+        if entry.starred != sync.starred && remote_starred == sync.starred {
+            let list = if entry.starred {
+                &mut pushes.starred
+            } else {
+                &mut pushes.unstarred
+            };
+            list.push(sync.id.clone());
+        } else if remote_starred != sync.starred && entry.starred == sync.starred {
+            entry.starred = remote_starred;
+            report.pulled += 1;
+        }
+    }
+    pushes
+}
+
+/// Records every synced entry's current state as agreed with the server.
+fn agree(session: &mut Session) {
+    for entry in session
+        .blog_feeds
+        .iter_mut()
+        .flat_map(|feed| feed.entries.iter_mut().flatten())
+    {
+        if let Some(sync) = entry.sync.as_mut() {
+            sync.read = entry.read;
+            sync.starred = entry.starred;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_and_user::user::User;
+
+    const ID: &str = "31";
+    const URL: &str = "https://a.example/1";
+
+    /// A session with one feed subscribed on the server, holding one entry.
+    fn subscribed(read: bool, starred: bool, sync: Option<SyncedState>) -> Session {
+        let mut entry = Entry::new("One", "", "", URL, None, None);
+        entry.read = read;
+        entry.starred = starred;
+        entry.sync = sync;
+        let mut feed = BlogFeed::new("https://a.example/feed", "a");
+        feed.sync_id = Some(String::from("feed/1"));
+        feed.entries = Some(vec![entry]);
+        Session::new(User::new("me"), vec![feed], "sync-test")
+    }
+
+    fn synced(read: bool, starred: bool) -> Option<SyncedState> {
+        Some(SyncedState {
+            id: String::from(ID),
+            read,
+            starred,
+        })
+    }
+
+    fn entry(session: &Session) -> &Entry {
+        &session.blog_feeds[0].entries.as_ref().unwrap()[0]
+    }
+
+    /// Merges against a server where the entry is read and starred as given.
+    fn merge(session: &mut Session, read: bool, starred: bool) -> (Pushes, SyncReport) {
+        let mut report = SyncReport::default();
+        let unread = if read {
+            HashSet::new()
+        } else {
+            HashSet::from([String::from(ID)])
+        };
+        let starred = if starred {
+            HashSet::from([String::from(ID)])
+        } else {
+            HashSet::new()
+        };
+        let pushes = merge_states(session, &unread, &starred, &mut report);
+        (pushes, report)
+    }
+
+    /// The server's copy of the entry, read and starred as given.
+    fn item(read: bool, starred: bool) -> Item {
+        let mut categories = vec![];
+        if read {
+            categories.push(greader::READ);
+        }
+        if starred {
+            categories.push(greader::STARRED);
+        }
+        serde_json::from_value(serde_json::json!({
+            "id": "tag:google.com,2005:reader/item/000000000000001f",
+            "title": "One",
+            "canonical": [{ "href": URL }],
+            "origin": { "streamId": "feed/1" },
+            "categories": categories,
+        }))
+        .unwrap()
+    }
+
+    fn add(session: &mut Session, item: &Item, conflicts: ConflictPolicy) -> SyncReport {
+        let mut report = SyncReport::default();
+        let rules = RuleSet::new(&[]).unwrap();
+        add_item(session, item, &rules, conflicts, &mut report);
+        report
+    }
+
+    #[test]
+    fn pushes_changes_made_only_locally() {
+        let mut session = subscribed(true, true, synced(false, false));
+        let (pushes, report) = merge(&mut session, false, false);
+        assert_eq!(pushes.read, vec![ID]);
+        assert_eq!(pushes.starred, vec![ID]);
+        assert_eq!(pushes.len(), 2);
+        assert_eq!(report.pulled, 0);
+        assert!(entry(&session).read && entry(&session).starred);
+
+        let mut session = subscribed(false, false, synced(true, true));
+        let (pushes, _) = merge(&mut session, true, true);
+        assert_eq!(pushes.unread, vec![ID]);
+        assert_eq!(pushes.unstarred, vec![ID]);
+        assert!(!entry(&session).read && !entry(&session).starred);
+    }
+
+    #[test]
+    fn pulls_changes_made_only_on_the_server() {
+        let mut session = subscribed(false, false, synced(false, false));
+        let (pushes, report) = merge(&mut session, true, true);
+        assert_eq!(pushes.len(), 0);
+        assert_eq!(report.pulled, 2);
+        assert!(entry(&session).read && entry(&session).starred);
+
+        let mut session = subscribed(true, true, synced(true, true));
+        let (pushes, report) = merge(&mut session, false, false);
+        assert_eq!(pushes.len(), 0);
+        assert_eq!(report.pulled, 2);
+        assert!(!entry(&session).read && !entry(&session).starred);
+    }
+
+    #[test]
+    fn leaves_changes_made_on_both_sides_alone() {
+        let mut session = subscribed(true, false, synced(false, true));
+        let (pushes, report) = merge(&mut session, true, false);
+        assert_eq!(pushes.len(), 0);
+        assert_eq!(report.pulled, 0);
+        assert!(entry(&session).read && !entry(&session).starred);
+    }
+
+    #[test]
+    fn skips_entries_that_were_never_synced() {
+        let mut session = subscribed(true, true, None);
+        let (pushes, report) = merge(&mut session, false, false);
+        assert_eq!(pushes.len(), 0);
+        assert_eq!(report.pulled, 0);
+        assert!(entry(&session).read && entry(&session).starred);
+    }
+
+    #[test]
+    fn server_policy_takes_the_server_state_for_never_synced_entries() {
+        let mut session = subscribed(true, false, None);
+        let report = add(&mut session, &item(false, true), ConflictPolicy::Server);
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(report.new_entries, 0);
+        assert!(!entry(&session).read && entry(&session).starred);
+        assert_eq!(entry(&session).sync, synced(false, true));
+
+        let (pushes, report) = merge(&mut session, false, true);
+        assert_eq!(pushes.len(), 0);
+        assert_eq!(report.pulled, 0);
+    }
+
+    #[test]
+    fn local_policy_keeps_the_local_state_and_pushes_it() {
+        let mut session = subscribed(true, false, None);
+        let report = add(&mut session, &item(false, true), ConflictPolicy::Local);
+        assert_eq!(report.conflicts, 1);
+        assert!(entry(&session).read && !entry(&session).starred);
+        assert_eq!(entry(&session).sync, synced(false, true));
+
+        let (pushes, report) = merge(&mut session, false, true);
+        assert_eq!(pushes.read, vec![ID]);
+        assert_eq!(pushes.unstarred, vec![ID]);
+        assert_eq!(report.pulled, 0);
+    }
+
+    #[test]
+    fn never_synced_entries_that_agree_are_not_conflicts() {
+        for conflicts in [ConflictPolicy::Server, ConflictPolicy::Local] {
+            let mut session = subscribed(true, true, None);
+            let report = add(&mut session, &item(true, true), conflicts);
+            assert_eq!(report.conflicts, 0);
+            assert_eq!(entry(&session).sync, synced(true, true));
+        }
+    }
+
+    #[test]
+    fn adds_entries_new_to_the_feed_with_the_server_state() {
+        let mut session = subscribed(false, false, None);
+        session.blog_feeds[0].entries = Some(vec![]);
+        let report = add(&mut session, &item(true, false), ConflictPolicy::Local);
+        assert_eq!(report.new_entries, 1);
+        assert_eq!(report.conflicts, 0);
+        assert!(entry(&session).read && !entry(&session).starred);
+        assert_eq!(entry(&session).url, URL);
+    }
+}
@@ -0,0 +1,484 @@
+use crate::feeds_and_entry::export::ExportFormat;
+use crate::feeds_and_entry::http::Auth;
+use crate::feeds_and_entry::ordering::{GroupMode, SortMode};
+use crate::feeds_and_entry::rules::{Field, RuleAction};
+use anyhow::{anyhow, Result};
+
+/// Everything that can be typed after `:`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add {
+        url: String,
+        name: Option<String>,
+    },
+    Refresh {
+        feed: Option<String>,
+    },
+    RefreshFolder {
+        folder: String,
+    },
+    Search {
+        query: String,
+    },
+    Session {
+        name: String,
+    },
+    ExportOpml {
+        path: String,
+    },
+    /// Write entries to a Markdown, HTML or EPUB file, e.g.
+    /// `export epub ~/reading.epub starred`.
+    Export {
+        format: ExportFormat,
+        path: String,
+        scope: ExportScope,
+    },
+    ImportOpml {
+        path: String,
+    },
+    MarkAllRead,
+    MarkFolderRead {
+        folder: String,
+    },
+    /// Move the feed highlighted in the feeds pane into a folder.
+    Folder {
+        path: String,
+    },
+    /// Add a single pattern filter rule, e.g. `rule hide title (?i)sponsored`.
+    Rule {
+        action: RuleAction,
+        field: Field,
+        pattern: String,
+    },
+    ApplyRules,
+    UnhideAll,
+    /// Turn full article fetching on or off for the feed highlighted in the feeds pane.
+    FullContent {
+        enabled: bool,
+    },
+    FetchArticle,
+    /// Turn the offline cache on or off for the feed highlighted in the feeds pane.
+    OfflineCache {
+        enabled: bool,
+    },
+    /// Set the credentials of the feed highlighted in the feeds pane, e.g.
+    /// `auth bearer env:TOKEN`, `auth basic me credential:wiki` or `auth none`.
+    Auth {
+        auth: Option<Auth>,
+    },
+    /// Set a request header for the feed highlighted in the feeds pane, or remove it
+    /// when no value is given.
+    Header {
+        name: String,
+        value: Option<String>,
+    },
+    /// Show what the feed highlighted in the feeds pane says about itself.
+    Details,
+    /// Remove the feed highlighted in the feeds pane, after asking.
+    Unsubscribe,
+    /// Trim the offline cache according to the retention settings.
+    Cleanup,
+    /// Queue the enclosures of the selected entry.
+    Download,
+    /// Hand the selected entry's first enclosure to the configured player.
+    Play,
+    Downloads,
+    /// Send the selected entry to the read-later service.
+    ReadLater,
+    /// Try the read-later sends that failed again.
+    RetryReadLater,
+    /// Sync subscriptions and read state with the server in the config.
+    Sync,
+    /// Send unread entries not yet in a digest to the mailbox in the config.
+    Digest,
+    /// Change the order of the entry list being shown, e.g. `sort unread-first`.
+    Sort {
+        mode: SortMode,
+    },
+    /// Split the entry list being shown under headers, e.g. `group day`.
+    Group {
+        mode: GroupMode,
+    },
+    /// Run a command defined by a script, e.g. `script mark-old-read`.
+    Script {
+        name: String,
+    },
+    /// List the entries a script view picks out, or go back to the plain list
+    /// without a name.
+    View {
+        name: Option<String>,
+    },
+    Quit,
+}
+
+impl Command {
+    /// The command names offered by tab completion.
+    pub fn names() -> Vec<&'static str> {
+        vec![
+            "add",
+            "refresh",
+            "refresh-folder",
+            "search",
+            "session",
+            "export",
+            "import",
+            "mark-all-read",
+            "mark-folder-read",
+            "folder",
+            "rule",
+            "apply-rules",
+            "unhide-all",
+            "full-content",
+            "fetch-article",
+            "offline-cache",
+            "auth",
+            "header",
+            "details",
+            "unsubscribe",
+            "cleanup",
+            "download",
+            "downloads",
+            "play",
+            "read-later",
+            "retry-read-later",
+            "sync",
+            "digest",
+            "sort",
+            "group",
+            "script",
+            "view",
+            "q",
+        ]
+    }
+
+    pub fn parse(line: &str) -> Result<Command> {
+        let line = line.trim();
+        let (name, rest) = match line.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, rest.trim()),
+            None => (line, ""),
+        };
+        let rest_or_none = || {
+            if rest.is_empty() {
+                None
+            } else {
+                Some(rest.to_string())
+            }
+        };
+        let command = match name {
+            "add" => {
+                let mut words = rest.splitn(2, char::is_whitespace);
+                let url = words
+                    .next()
+                    .filter(|url| !url.is_empty())
+                    .ok_or_else(|| anyhow!("usage: add <url> [name]"))?;
+                Command::Add {
+                    url: url.to_string(),
+                    name: words.next().map(|name| name.trim().to_string()),
+                }
+            }
+            "refresh" => Command::Refresh {
+                feed: rest_or_none(),
+            },
+            "refresh-folder" => Command::RefreshFolder {
+                folder: rest_or_none().ok_or_else(|| anyhow!("usage: refresh-folder <folder>"))?,
+            },
+            "search" => Command::Search {
+                query: rest_or_none().ok_or_else(|| anyhow!("usage: search <query>"))?,
+            },
+            "session" => Command::Session {
+                name: rest_or_none().ok_or_else(|| anyhow!("usage: session <name>"))?,
+            },
+            "export" => match rest.split_once(char::is_whitespace) {
+                Some(("opml", path)) if !path.trim().is_empty() => Command::ExportOpml {
+                    path: path.trim().to_string(),
+                },
+                Some((format, rest)) if !rest.trim().is_empty() => {
+                    let format = ExportFormat::from_name(format)?;
+                    let rest = rest.trim();
+                    let (path, scope) = match rest.rsplit_once(char::is_whitespace) {
+                        Some((path, scope)) => match ExportScope::from_name(scope) {
+                            Some(scope) => (path.trim(), scope),
+                            None => (rest, ExportScope::List),
+                        },
+                        None => (rest, ExportScope::List),
+                    };
+                    Command::Export {
+                        format,
+                        path: path.to_string(),
+                        scope,
+                    }
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "usage: export opml <path>, or export <markdown|html|epub> <path> [list|marked|starred]"
+                    ))
+                }
+            },
+            "import" => match rest.split_once(char::is_whitespace) {
+                Some(("opml", path)) if !path.trim().is_empty() => Command::ImportOpml {
+                    path: path.trim().to_string(),
+                },
+                _ => return Err(anyhow!("usage: import opml <path>")),
+            },
+            "mark-all-read" => Command::MarkAllRead,
+            "mark-folder-read" => Command::MarkFolderRead {
+                folder: rest_or_none()
+                    .ok_or_else(|| anyhow!("usage: mark-folder-read <folder>"))?,
+            },
+            "folder" => Command::Folder {
+                path: rest.to_string(),
+            },
+            "rule" => {
+                let usage = || anyhow!("usage: rule <hide|read|star|tag:NAME> <field> <pattern>");
+                let mut words = rest.splitn(3, char::is_whitespace);
+                let (action, field, pattern) = match (words.next(), words.next(), words.next()) {
+                    (Some(action), Some(field), Some(pattern)) if !pattern.trim().is_empty() => {
+                        (action, field, pattern.trim())
+                    }
+                    _ => return Err(usage()),
+                };
+                Command::Rule {
+                    action: RuleAction::from_name(action)?,
+                    field: Field::from_name(field)?,
+                    pattern: pattern.to_string(),
+                }
+            }
+            "apply-rules" => Command::ApplyRules,
+            "unhide-all" => Command::UnhideAll,
+            "full-content" => Command::FullContent {
+                enabled: match rest {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(anyhow!("usage: full-content <on|off>")),
+                },
+            },
+            "fetch-article" => Command::FetchArticle,
+            "offline-cache" => Command::OfflineCache {
+                enabled: match rest {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(anyhow!("usage: offline-cache <on|off>")),
+                },
+            },
+            "auth" => {
+                let words: Vec<&str> = rest.split_whitespace().collect();
+                let auth = match words.as_slice() {
+                    ["none"] => None,
+                    ["basic", username, password] => Some(Auth::Basic {
+                        username: username.to_string(),
+                        password: password.to_string(),
+                    }),
+                    ["bearer", token] => Some(Auth::Bearer {
+                        token: token.to_string(),
+                    }),
+                    _ => {
+                        return Err(anyhow!(
+                            "usage: auth <basic USER SECRET|bearer SECRET|none>"
+                        ))
+                    }
+                };
+                Command::Auth { auth }
+            }
+            "header" => {
+                let (name, value) = match rest.split_once(char::is_whitespace) {
+                    Some((name, value)) => (name, Some(value.trim().to_string())),
+                    None => (rest, None),
+                };
+                if name.is_empty() {
+                    return Err(anyhow!("usage: header <name> [value]"));
+                }
+                Command::Header {
+                    name: name.to_string(),
+                    value,
+                }
+            }
+            "details" => Command::Details,
+            "unsubscribe" => Command::Unsubscribe,
+            "cleanup" => Command::Cleanup,
+            "download" => Command::Download,
+            "downloads" => Command::Downloads,
+            "play" => Command::Play,
+            "read-later" => Command::ReadLater,
+            "retry-read-later" => Command::RetryReadLater,
+            "sync" => Command::Sync,
+            "digest" => Command::Digest,
+            "sort" => Command::Sort {
+                mode: SortMode::from_name(rest)?,
+            },
+            "group" => Command::Group {
+                mode: GroupMode::from_name(rest)?,
+            },
+            "script" => Command::Script {
+                name: rest_or_none().ok_or_else(|| anyhow!("usage: script <command>"))?,
+            },
+            "view" => Command::View {
+                name: rest_or_none(),
+            },
+            "q" | "quit" => Command::Quit,
+            "" => return Err(anyhow!("no command given")),
+            other => return Err(anyhow!("not a command: {}", other)),
+        };
+        Ok(command)
+    }
+}
+
+/// Which entries `export` writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportScope {
+    /// The entry list being shown, with its search or view.
+    List,
+    /// The entries marked in the list, or the one under the cursor if none are.
+    Marked,
+    Starred,
+}
+
+impl ExportScope {
+    fn from_name(name: &str) -> Option<ExportScope> {
+        match name {
+            "list" => Some(ExportScope::List),
+            "marked" => Some(ExportScope::Marked),
+            "starred" => Some(ExportScope::Starred),
+            _ => None,
+        }
+    }
+}
+
+/// Values tab completion can draw from besides the command names.
+pub struct CompletionSource {
+    pub feed_names: Vec<String>,
+    pub session_names: Vec<String>,
+    pub folder_names: Vec<String>,
+    pub script_commands: Vec<String>,
+    pub script_views: Vec<String>,
+}
+
+#[derive(Clone)]
+struct Completion {
+    prefix: String,
+    matches: Vec<String>,
+    index: usize,
+}
+
+/// History and tab completion for the `:` prompt. The text itself lives in the
+/// app's input buffer.
+#[derive(Clone, Default)]
+pub struct CommandLine {
+    history: Vec<String>,
+    history_index: Option<usize>,
+    completion: Option<Completion>,
+}
+
+impl CommandLine {
+    /// Forget any in-progress completion or history browsing, e.g. after a keypress.
+    pub fn reset(&mut self) {
+        self.history_index = None;
+        self.completion = None;
+    }
+
+    pub fn push_history(&mut self, line: &str) {
+        if !line.trim().is_empty() && self.history.last().map(|l| l.as_str()) != Some(line) {
+            self.history.push(line.to_string());
+        }
+        self.reset();
+    }
+
+    /// Step back through history, returning the line to show.
+    pub fn previous(&mut self) -> Option<String> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let index = match self.history_index {
+            Some(0) => 0,
+            Some(i) => i - 1,
+            None => self.history.len() - 1,
+        };
+        self.history_index = Some(index);
+        Some(self.history[index].clone())
+    }
+
+    /// Step forward through history. Walking off the end gives an empty line.
+    pub fn next(&mut self) -> Option<String> {
+        let index = self.history_index?;
+        if index + 1 >= self.history.len() {
+            self.history_index = None;
+            return Some(String::new());
+        }
+        self.history_index = Some(index + 1);
+        Some(self.history[index + 1].clone())
+    }
+
+    /// Completes the word being typed. Pressing tab again cycles through the
+    /// other matches.
+    pub fn complete(&mut self, line: &str, source: &CompletionSource) -> Option<String> {
+        if let Some(completion) = self.completion.as_mut() {
+            let current = format!(
+                "{}{}",
+                completion.prefix, completion.matches[completion.index]
+            );
+            if current == line {
+                completion.index = (completion.index + 1) % completion.matches.len();
+                return Some(format!(
+                    "{}{}",
+                    completion.prefix, completion.matches[completion.index]
+                ));
+            }
+        }
+        let (prefix, word, candidates): (String, &str, Vec<String>) = match line.split_once(' ') {
+            None => (
+                String::new(),
+                line,
+                Command::names().iter().map(|n| n.to_string()).collect(),
+            ),
+            Some((command, argument)) => {
+                let candidates = match command {
+                    "refresh" => source.feed_names.clone(),
+                    "session" => source.session_names.clone(),
+                    "refresh-folder" | "mark-folder-read" | "folder" => source.folder_names.clone(),
+                    "export" => std::iter::once("opml")
+                        .chain(ExportFormat::NAMES)
+                        .map(|format| format.to_string())
+                        .collect(),
+                    "import" => vec![String::from("opml")],
+                    "full-content" | "offline-cache" => {
+                        vec![String::from("on"), String::from("off")]
+                    }
+                    "auth" => ["basic", "bearer", "none"]
+                        .iter()
+                        .map(|kind| kind.to_string())
+                        .collect(),
+                    "sort" => SortMode::NAMES
+                        .iter()
+                        .map(|mode| mode.to_string())
+                        .collect(),
+                    "group" => GroupMode::NAMES
+                        .iter()
+                        .map(|mode| mode.to_string())
+                        .collect(),
+                    "script" => source.script_commands.clone(),
+                    "view" => source.script_views.clone(),
+                    "rule" => ["hide", "read", "star", "tag:"]
+                        .iter()
+                        .map(|action| action.to_string())
+                        .collect(),
+                    _ => vec![],
+                };
+                (format!("{} ", command), argument, candidates)
+            }
+        };
+        let matches: Vec<String> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .collect();
+        if matches.is_empty() {
+            self.completion = None;
+            return None;
+        }
+        let completed = format!("{}{}", prefix, matches[0]);
+        self.completion = Some(Completion {
+            prefix,
+            matches,
+            index: 0,
+        });
+        Some(completed)
+    }
+}
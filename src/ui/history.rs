@@ -0,0 +1,51 @@
+use crate::ui::layout::FeedPaneItem;
+use crate::ui::screens::SelectedScreen;
+use tui::widgets::ListState;
+
+/// Older visits are dropped once the history gets this long.
+const MAX_HISTORY: usize = 100;
+
+/// Where the user was: the screen, its cursor and scroll position, and the item
+/// under the cursor so it can be found again if the list has changed since.
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub screen: SelectedScreen,
+    pub list_state: ListState,
+    pub selected_label: Option<String>,
+    pub search_query: Option<String>,
+    pub feed_filter: FeedPaneItem,
+    pub script_view: Option<String>,
+}
+
+/// Back and forward stacks, like a web browser's.
+#[derive(Clone, Default)]
+pub struct NavigationHistory {
+    back: Vec<HistoryEntry>,
+    forward: Vec<HistoryEntry>,
+}
+
+impl NavigationHistory {
+    /// Record the screen being left for a new one. Visiting somewhere new
+    /// forgets anything that could have been gone forward to.
+    pub fn visit(&mut self, leaving: HistoryEntry) {
+        self.back.push(leaving);
+        if self.back.len() > MAX_HISTORY {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+    }
+
+    /// Returns the screen to go back to, remembering `current` so it can be
+    /// returned to with `go_forward`.
+    pub fn go_back(&mut self, current: HistoryEntry) -> Option<HistoryEntry> {
+        let target = self.back.pop()?;
+        self.forward.push(current);
+        Some(target)
+    }
+
+    pub fn go_forward(&mut self, current: HistoryEntry) -> Option<HistoryEntry> {
+        let target = self.forward.pop()?;
+        self.back.push(current);
+        Some(target)
+    }
+}
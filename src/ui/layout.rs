@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+/// How the BrowsePosts screen is arranged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutMode {
+    /// Entries on the left, article on the right.
+    Horizontal,
+    /// Entries above the article.
+    #[default]
+    Vertical,
+    /// Feeds, entries and article side by side.
+    ThreePane,
+}
+
+/// Layout settings from the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub mode: LayoutMode,
+    /// Width of the feeds pane as a percentage of the screen.
+    pub feeds_percent: u16,
+    /// Share of the remaining space given to the entry list rather than the article.
+    pub entries_percent: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> LayoutConfig {
+        LayoutConfig {
+            mode: LayoutMode::default(),
+            feeds_percent: 20,
+            entries_percent: 50,
+        }
+    }
+}
+
+impl LayoutConfig {
+    /// Percentages over 100 from the config file are taken as 100, since the pane
+    /// sizes are worked out by taking them from 100.
+    pub fn clamp(&mut self) {
+        self.feeds_percent = self.feeds_percent.min(100);
+        self.entries_percent = self.entries_percent.min(100);
+    }
+}
+
+/// A line in the feeds pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedPaneItem {
+    AllFeeds,
+    /// A folder path like `Rust/Async`, covering every feed beneath it.
+    Folder(String),
+    Feed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Feeds,
+    Entries,
+    Preview,
+}
+
+/// Where each pane ended up. Panes that didn't fit are `None`.
+pub struct PaneAreas {
+    pub feeds: Option<Rect>,
+    pub entries: Rect,
+    pub preview: Option<Rect>,
+}
+
+impl PaneAreas {
+    pub fn is_visible(&self, pane: Pane) -> bool {
+        match pane {
+            Pane::Feeds => self.feeds.is_some(),
+            Pane::Entries => true,
+            Pane::Preview => self.preview.is_some(),
+        }
+    }
+}
+
+/// Terminals narrower than this drop the feeds pane.
+const THREE_PANE_MIN_WIDTH: u16 = 100;
+/// Terminals narrower than this stack the entries above the article.
+const SIDE_BY_SIDE_MIN_WIDTH: u16 = 70;
+/// Terminals shorter than this only show the entry list.
+const STACKED_MIN_HEIGHT: u16 = 14;
+
+/// Splits `area` for the configured layout, collapsing to fewer panes when the
+/// terminal is too small to fit them.
+pub fn split_panes(config: &LayoutConfig, area: Rect) -> PaneAreas {
+    let mut mode = config.mode;
+    if mode == LayoutMode::ThreePane && area.width < THREE_PANE_MIN_WIDTH {
+        mode = LayoutMode::Horizontal;
+    }
+    if mode == LayoutMode::Horizontal && area.width < SIDE_BY_SIDE_MIN_WIDTH {
+        mode = LayoutMode::Vertical;
+    }
+    if mode == LayoutMode::Vertical && area.height < STACKED_MIN_HEIGHT {
+        return PaneAreas {
+            feeds: None,
+            entries: area,
+            preview: None,
+        };
+    }
+    let entries_percent = config.entries_percent;
+    let two_panes = |direction: Direction, area: Rect| {
+        Layout::default()
+            .direction(direction)
+            .constraints([
+                Constraint::Percentage(entries_percent),
+                Constraint::Percentage(100 - entries_percent),
+            ])
+            .split(area)
+    };
+    match mode {
+        LayoutMode::Vertical | LayoutMode::Horizontal => {
+            let direction = match mode {
+                LayoutMode::Vertical => Direction::Vertical,
+                _ => Direction::Horizontal,
+            };
+            let chunks = two_panes(direction, area);
+            PaneAreas {
+                feeds: None,
+                entries: chunks[0],
+                preview: Some(chunks[1]),
+            }
+        }
+        LayoutMode::ThreePane => {
+            let outer = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Percentage(config.feeds_percent),
+                    Constraint::Percentage(100 - config.feeds_percent),
+                ])
+                .split(area);
+            let chunks = two_panes(Direction::Horizontal, outer[1]);
+            PaneAreas {
+                feeds: Some(outer[0]),
+                entries: chunks[0],
+                preview: Some(chunks[1]),
+            }
+        }
+    }
+}
+
+/// Gives the focused pane `step` more percent of the screen (or less, if negative).
+pub fn resize_pane(config: &mut LayoutConfig, focused: Pane, step: i16) {
+    let adjust = |value: u16, by: i16| (value as i16 + by).clamp(10, 90) as u16;
+    match focused {
+        Pane::Feeds => config.feeds_percent = adjust(config.feeds_percent, step).min(50),
+        Pane::Entries => config.entries_percent = adjust(config.entries_percent, step),
+        Pane::Preview => config.entries_percent = adjust(config.entries_percent, -step),
+    }
+}
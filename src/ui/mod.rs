@@ -1,2 +1,6 @@
+pub mod command_line;
+pub mod history;
+pub mod layout;
 pub mod primitives;
 pub mod screens;
+pub mod theme;
@@ -43,6 +43,27 @@ impl<T> StatefulList<T> {
         self.state.select(Some(i));
     }
 
+    pub fn first(&mut self) {
+        self.state.select(Some(0));
+    }
+
+    pub fn last(&mut self) {
+        self.state.select(Some(self.items.len().saturating_sub(1)));
+    }
+
+    /// Moves the cursor down by `count` items, stopping at the end of the list.
+    pub fn forward(&mut self, count: usize) {
+        let i = self.state.selected().unwrap_or(0) + count;
+        self.state
+            .select(Some(i.min(self.items.len().saturating_sub(1))));
+    }
+
+    /// Moves the cursor up by `count` items, stopping at the top of the list.
+    pub fn backward(&mut self, count: usize) {
+        let i = self.state.selected().unwrap_or(0).saturating_sub(count);
+        self.state.select(Some(i));
+    }
+
     pub fn unselect(&mut self) {
         self.state.select(None);
     }
@@ -1,3 +1,4 @@
+use crate::session_and_user::session::Session;
 use crate::ui::primitives::StatefulList;
 
 #[derive(Clone)]
@@ -10,6 +11,12 @@ pub enum SelectedScreen {
     SelectSession,
     Procedures,
     Authors,
+    Folders,
+    Rules,
+    Storage,
+    Downloads,
+    ViewFeeds,
+    FeedDetails,
 }
 
 impl SelectedScreen {
@@ -24,10 +31,18 @@ impl SelectedScreen {
                 String::from("User Name: "),
                 String::from("Session Name: "),
             ]),
-            SelectedScreen::SelectSession => todo!(),
+            SelectedScreen::SelectSession => {
+                StatefulList::with_items(Session::saved_session_names())
+            }
             SelectedScreen::Procedures => todo!(),
             SelectedScreen::BrowsePosts => StatefulList::with_items(vec![]),
             SelectedScreen::Authors => StatefulList::with_items(vec![]),
+            SelectedScreen::Folders => StatefulList::with_items(vec![]),
+            SelectedScreen::Rules => StatefulList::with_items(vec![]),
+            SelectedScreen::Storage => StatefulList::with_items(vec![]),
+            SelectedScreen::Downloads => StatefulList::with_items(vec![]),
+            SelectedScreen::ViewFeeds => StatefulList::with_items(vec![]),
+            SelectedScreen::FeedDetails => StatefulList::with_items(vec![]),
         }
     }
 
@@ -41,6 +56,12 @@ impl SelectedScreen {
             SelectedScreen::Procedures => String::from("Procedures"),
             SelectedScreen::BrowsePosts => String::from("Browse Posts"),
             SelectedScreen::Authors => String::from("Browse Authors"),
+            SelectedScreen::Folders => String::from("Browse Folders"),
+            SelectedScreen::Rules => String::from("Filter Rules"),
+            SelectedScreen::Storage => String::from("Storage Usage"),
+            SelectedScreen::Downloads => String::from("Downloads"),
+            SelectedScreen::ViewFeeds => String::from("View Feeds"),
+            SelectedScreen::FeedDetails => String::from("Feed Details"),
         }
     }
 }
@@ -49,6 +70,8 @@ pub enum ProceduresOptions {
     UpdatePosts,
     AddSource,
     DumpSessionData,
+    Storage,
+    Downloads,
     Home,
 }
 
@@ -58,6 +81,8 @@ impl Options<ProceduresOptions> for ProceduresOptions {
             ProceduresOptions::UpdatePosts => String::from("Update Posts"),
             ProceduresOptions::AddSource => String::from("Add Source"),
             ProceduresOptions::DumpSessionData => String::from("Save"),
+            ProceduresOptions::Storage => String::from("Storage Usage"),
+            ProceduresOptions::Downloads => String::from("Downloads"),
             ProceduresOptions::Home => String::from("Home"),
         }
     }
@@ -67,6 +92,8 @@ impl Options<ProceduresOptions> for ProceduresOptions {
             "Update Posts" => ProceduresOptions::UpdatePosts,
             "Add Source" => ProceduresOptions::AddSource,
             "Save" => ProceduresOptions::DumpSessionData,
+            "Storage Usage" => ProceduresOptions::Storage,
+            "Downloads" => ProceduresOptions::Downloads,
             "Home" => ProceduresOptions::Home,
             _ => panic!("This isn't an option!"),
         }
@@ -76,6 +103,8 @@ impl Options<ProceduresOptions> for ProceduresOptions {
             ProceduresOptions::UpdatePosts.as_string(),
             ProceduresOptions::AddSource.as_string(),
             ProceduresOptions::DumpSessionData.as_string(),
+            ProceduresOptions::Storage.as_string(),
+            ProceduresOptions::Downloads.as_string(),
             ProceduresOptions::Home.as_string(),
         ]
     }
@@ -84,6 +113,7 @@ impl Options<ProceduresOptions> for ProceduresOptions {
 pub enum FeedsOptions {
     ViewFeeds,
     AddFeed,
+    Rules,
     Home,
 }
 
@@ -99,6 +129,7 @@ impl Options<FeedsOptions> for FeedsOptions {
             FeedsOptions::Home => String::from("Home"),
             FeedsOptions::AddFeed => String::from("Add Feed"),
             FeedsOptions::ViewFeeds => String::from("View Feeds"),
+            FeedsOptions::Rules => String::from("Filter Rules"),
         }
     }
 
@@ -107,6 +138,7 @@ impl Options<FeedsOptions> for FeedsOptions {
             "Home" => FeedsOptions::Home,
             "Add Feed" => FeedsOptions::AddFeed,
             "View Feeds" => FeedsOptions::ViewFeeds,
+            "Filter Rules" => FeedsOptions::Rules,
             _ => panic!("This isn't an option!"),
         }
     }
@@ -115,12 +147,14 @@ impl Options<FeedsOptions> for FeedsOptions {
             FeedsOptions::Home.as_string(),
             FeedsOptions::AddFeed.as_string(),
             FeedsOptions::ViewFeeds.as_string(),
+            FeedsOptions::Rules.as_string(),
         ]
     }
 }
 
 pub enum PostsOptions {
     Browse,
+    Folders,
     Search,
     Categories,
     Authors,
@@ -131,6 +165,7 @@ impl Options<PostsOptions> for PostsOptions {
     fn as_string(&self) -> String {
         match self {
             PostsOptions::Browse => String::from("Browse Posts"),
+            PostsOptions::Folders => String::from("Browse Folders"),
             PostsOptions::Search => String::from("Search Posts"),
             PostsOptions::Categories => String::from("Browse Categories"),
             PostsOptions::Authors => String::from("Browse Authors"),
@@ -141,6 +176,7 @@ impl Options<PostsOptions> for PostsOptions {
     fn from_string(text: &str) -> PostsOptions {
         match text {
             "Browse Posts" => PostsOptions::Browse,
+            "Browse Folders" => PostsOptions::Folders,
             "Search Posts" => PostsOptions::Search,
             "Browse Categories" => PostsOptions::Categories,
             "Browse Authors" => PostsOptions::Authors,
@@ -153,6 +189,7 @@ impl Options<PostsOptions> for PostsOptions {
             PostsOptions::Home.as_string(),
             PostsOptions::Search.as_string(),
             PostsOptions::Browse.as_string(),
+            PostsOptions::Folders.as_string(),
             PostsOptions::Authors.as_string(),
             PostsOptions::Categories.as_string(),
         ]
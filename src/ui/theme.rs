@@ -0,0 +1,268 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tui::style::{Color, Modifier, Style};
+
+/// A style as written in the config file, e.g.
+/// `{ "fg": "yellow", "bg": "#202020", "modifiers": ["bold"] }`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct StyleSpec {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub modifiers: Vec<String>,
+}
+
+impl StyleSpec {
+    pub fn to_style(&self) -> Result<Style> {
+        let mut style = Style::default();
+        if let Some(fg) = &self.fg {
+            style = style.fg(parse_color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(parse_color(bg)?);
+        }
+        for modifier in self.modifiers.iter() {
+            style = style.add_modifier(parse_modifier(modifier)?);
+        }
+        Ok(style)
+    }
+}
+
+fn parse_color(name: &str) -> Result<Color> {
+    let color = match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        other => {
+            if let Some(hex) = other.strip_prefix('#') {
+                if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(anyhow!("colour {:?} should look like #rrggbb", name));
+                }
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+                Color::Rgb(channel(0)?, channel(2)?, channel(4)?)
+            } else if let Ok(index) = other.parse::<u8>() {
+                Color::Indexed(index)
+            } else {
+                return Err(anyhow!("unknown colour {:?}", name));
+            }
+        }
+    };
+    Ok(color)
+}
+
+fn parse_modifier(name: &str) -> Result<Modifier> {
+    let modifier = match name.to_lowercase().as_str() {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" | "underline" => Modifier::UNDERLINED,
+        "slow_blink" | "blink" => Modifier::SLOW_BLINK,
+        "rapid_blink" => Modifier::RAPID_BLINK,
+        "reversed" | "reverse" => Modifier::REVERSED,
+        "hidden" => Modifier::HIDDEN,
+        "crossed_out" | "strikethrough" => Modifier::CROSSED_OUT,
+        _ => return Err(anyhow!("unknown modifier {:?}", name)),
+    };
+    Ok(modifier)
+}
+
+/// A user defined theme. Slots that aren't given are taken from the theme it
+/// `extends`, which defaults to the built-in dark theme.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ThemeSpec {
+    pub extends: Option<String>,
+    pub highlight_symbol: Option<String>,
+    pub list_highlight: Option<StyleSpec>,
+    pub unread: Option<StyleSpec>,
+    pub read: Option<StyleSpec>,
+    pub starred: Option<StyleSpec>,
+    pub border: Option<StyleSpec>,
+    pub status_bar: Option<StyleSpec>,
+    pub input_normal: Option<StyleSpec>,
+    pub input_editing: Option<StyleSpec>,
+    pub error: Option<StyleSpec>,
+    pub group_header: Option<StyleSpec>,
+}
+
+/// The named style slots the ui draws with.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub highlight_symbol: String,
+    pub list_highlight: Style,
+    pub unread: Style,
+    pub read: Style,
+    pub starred: Style,
+    pub border: Style,
+    pub status_bar: Style,
+    pub input_normal: Style,
+    pub input_editing: Style,
+    pub error: Style,
+    /// The headers between groups of entries.
+    pub group_header: Style,
+}
+
+impl Theme {
+    pub fn dark() -> Theme {
+        Theme {
+            highlight_symbol: String::from("🍄 "),
+            list_highlight: Style::default().add_modifier(Modifier::BOLD),
+            unread: Style::default().fg(Color::White),
+            read: Style::default().fg(Color::DarkGray),
+            starred: Style::default().fg(Color::Yellow),
+            border: Style::default(),
+            status_bar: Style::default().fg(Color::White).bg(Color::DarkGray),
+            input_normal: Style::default().fg(Color::Magenta),
+            input_editing: Style::default().fg(Color::Yellow),
+            error: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            group_header: Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            highlight_symbol: String::from("🍄 "),
+            list_highlight: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            unread: Style::default().fg(Color::Black),
+            read: Style::default().fg(Color::Gray),
+            starred: Style::default().fg(Color::Magenta),
+            border: Style::default().fg(Color::DarkGray),
+            status_bar: Style::default().fg(Color::Black).bg(Color::Gray),
+            input_normal: Style::default().fg(Color::Magenta),
+            input_editing: Style::default().fg(Color::Blue),
+            error: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            group_header: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+        }
+    }
+
+    pub fn monochrome() -> Theme {
+        Theme {
+            highlight_symbol: String::from("> "),
+            list_highlight: Style::default().add_modifier(Modifier::REVERSED),
+            unread: Style::default().add_modifier(Modifier::BOLD),
+            read: Style::default(),
+            starred: Style::default().add_modifier(Modifier::BOLD | Modifier::ITALIC),
+            border: Style::default(),
+            status_bar: Style::default().add_modifier(Modifier::REVERSED),
+            input_normal: Style::default().add_modifier(Modifier::BOLD),
+            input_editing: Style::default().add_modifier(Modifier::UNDERLINED),
+            error: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            group_header: Style::default().add_modifier(Modifier::UNDERLINED),
+        }
+    }
+
+    fn built_in(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "monochrome" => Some(Theme::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// Resolves the theme called `name`, looking at the user's themes before the
+    /// built-in ones. When `NO_COLOR` is set the built-in themes give way to the
+    /// monochrome one and user themes lose their colours.
+    pub fn load(name: &str, user_themes: &HashMap<String, ThemeSpec>) -> Result<Theme> {
+        // https://no-color.org: any non-empty value means no colour.
+        let no_color = std::env::var("NO_COLOR").is_ok_and(|v| !v.is_empty());
+        if no_color && !user_themes.contains_key(name) && Theme::built_in(name).is_some() {
+            return Ok(Theme::monochrome());
+        }
+        let mut theme = Theme::resolve(name, user_themes, &mut vec![])?;
+        if no_color {
+            theme.strip_colors();
+        }
+        Ok(theme)
+    }
+
+    fn resolve(
+        name: &str,
+        user_themes: &HashMap<String, ThemeSpec>,
+        seen: &mut Vec<String>,
+    ) -> Result<Theme> {
+        if seen.iter().any(|s| s == name) {
+            return Err(anyhow!("theme {:?} extends itself", name));
+        }
+        seen.push(name.to_string());
+        match user_themes.get(name) {
+            Some(spec) => {
+                let base = spec.extends.as_deref().unwrap_or("dark");
+                let mut theme = Theme::resolve(base, user_themes, seen)?;
+                theme.apply(spec)?;
+                Ok(theme)
+            }
+            None => Theme::built_in(name).ok_or_else(|| anyhow!("no theme called {:?}", name)),
+        }
+    }
+
+    fn apply(&mut self, spec: &ThemeSpec) -> Result<()> {
+        if let Some(symbol) = &spec.highlight_symbol {
+            self.highlight_symbol = symbol.to_string();
+        }
+        let slots = [
+            (&mut self.list_highlight, &spec.list_highlight),
+            (&mut self.unread, &spec.unread),
+            (&mut self.read, &spec.read),
+            (&mut self.starred, &spec.starred),
+            (&mut self.border, &spec.border),
+            (&mut self.status_bar, &spec.status_bar),
+            (&mut self.input_normal, &spec.input_normal),
+            (&mut self.input_editing, &spec.input_editing),
+            (&mut self.error, &spec.error),
+            (&mut self.group_header, &spec.group_header),
+        ];
+        for (slot, style) in slots {
+            if let Some(style) = style {
+                *slot = style.to_style()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps bold, reverse and friends but drops every foreground and background colour.
+    fn strip_colors(&mut self) {
+        for slot in [
+            &mut self.list_highlight,
+            &mut self.unread,
+            &mut self.read,
+            &mut self.starred,
+            &mut self.border,
+            &mut self.status_bar,
+            &mut self.input_normal,
+            &mut self.input_editing,
+            &mut self.error,
+            &mut self.group_header,
+        ] {
+            slot.fg = None;
+            slot.bg = None;
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}